@@ -0,0 +1,40 @@
+//! Run a ROM for 60 frames without SDL and print a checksum of the output.
+//!
+//! ```sh
+//! cargo run --example headless -- game.nes
+//! ```
+
+use std::env;
+use std::fs;
+use std::process;
+
+use vines::cartridge::Cartridge;
+use vines::nes::Nes;
+
+fn main() {
+    let Some(rom_path) = env::args().nth(1) else {
+        eprintln!("Usage: headless <rom.nes>");
+        process::exit(1);
+    };
+    let rom_data = fs::read(&rom_path).unwrap_or_else(|e| {
+        eprintln!("Failed to read ROM file '{}': {}", rom_path, e);
+        process::exit(1);
+    });
+    let cartridge = Cartridge::from_ines(&rom_data).unwrap_or_else(|e| {
+        eprintln!("Failed to parse ROM: {}", e);
+        process::exit(1);
+    });
+
+    let mut nes = Nes::new_headless(cartridge);
+
+    let mut sample_count = 0;
+    let mut pixel_sum = 0u64;
+    for _ in 0..60 {
+        let (pixels, samples) = nes.step_frame_headless();
+        sample_count += samples.len();
+        pixel_sum = pixels.iter().map(|&p| p as u64).sum();
+    }
+
+    println!("Pixel sum after 60 frames: {}", pixel_sum);
+    println!("Audio samples: {}", sample_count);
+}
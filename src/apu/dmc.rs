@@ -0,0 +1,245 @@
+use crate::nes::Region;
+
+// Timer periods in CPU cycles (NTSC)
+const DMC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const DMC_RATE_TABLE_PAL: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dmc {
+    pub irq_flag: bool,
+    irq_enabled: bool,
+    loop_flag: bool,
+
+    // Timer
+    timer_period: u16,
+    timer_counter: u16,
+
+    // Output unit
+    output_level: u8,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+
+    // Memory reader
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    pub bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    // Picks the rate table
+    pub region: Region,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Dmc {
+            irq_flag: false,
+            irq_enabled: false,
+            loop_flag: false,
+            timer_period: DMC_RATE_TABLE[0],
+            timer_counter: DMC_RATE_TABLE[0],
+            output_level: 0,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            region: Region::Ntsc,
+        }
+    }
+
+    // $4010
+    pub fn write_control(&mut self, val: u8) {
+        self.irq_enabled = val & 0x80 != 0;
+        self.loop_flag = val & 0x40 != 0;
+        let table = if self.region == Region::Pal { &DMC_RATE_TABLE_PAL } else { &DMC_RATE_TABLE };
+        self.timer_period = table[(val & 0x0F) as usize];
+        if !self.irq_enabled {
+            self.irq_flag = false;
+        }
+    }
+
+    // $4011
+    pub fn write_output_level(&mut self, val: u8) {
+        self.output_level = val & 0x7F;
+    }
+
+    // $4012
+    pub fn write_sample_address(&mut self, val: u8) {
+        self.sample_address = 0xC000 | (val as u16) << 6;
+    }
+
+    // $4013
+    pub fn write_sample_length(&mut self, val: u8) {
+        self.sample_length = (val as u16) << 4 | 1;
+    }
+
+    /// $4015 bit 4: disabling stops the sample, enabling restarts it if it had finished.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Address of the next sample byte, if the reader wants one. The bus performs
+    /// the read (stalling the CPU) and hands the byte back via `load_sample`.
+    pub fn pending_fetch(&self) -> Option<u16> {
+        (self.sample_buffer.is_none() && self.bytes_remaining > 0).then_some(self.current_address)
+    }
+
+    pub fn load_sample(&mut self, val: u8) {
+        self.sample_buffer = Some(val);
+        // Addresses wrap from $FFFF back to $8000
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    /// Clock the timer (called every CPU cycle)
+    pub fn tick_timer(&mut self) {
+        if self.timer_counter > 1 {
+            self.timer_counter -= 1;
+            return;
+        }
+        self.timer_counter = self.timer_period;
+
+        // Each bit moves the 7-bit level by 2, staying within 0-127
+        if !self.silence {
+            if self.shift_register & 1 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(byte) => {
+                    self.silence = false;
+                    self.shift_register = byte;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clock one full output cycle (8 bits) at the fastest rate. Expects `timer_counter`
+    /// to start at the full period.
+    fn clock_byte(dmc: &mut Dmc) {
+        for _ in 0..8 * DMC_RATE_TABLE[15] {
+            dmc.tick_timer();
+        }
+    }
+
+    #[test]
+    fn test_dmc_fetch_and_output() {
+        let mut dmc = Dmc::new();
+        dmc.write_control(0x0F);
+        dmc.timer_counter = dmc.timer_period;
+        dmc.write_output_level(0x40);
+        dmc.write_sample_address(0x01); // $C040
+        dmc.write_sample_length(0x00); // 1 byte
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.pending_fetch(), Some(0xC040));
+        dmc.load_sample(0xFF);
+        assert_eq!(dmc.pending_fetch(), None);
+        assert_eq!(dmc.bytes_remaining, 0);
+
+        // The first output cycle is silent; the buffered byte then plays as 8 up-steps
+        clock_byte(&mut dmc);
+        assert_eq!(dmc.output(), 0x40);
+        clock_byte(&mut dmc);
+        assert_eq!(dmc.output(), 0x50);
+    }
+
+    #[test]
+    fn test_dmc_output_level_clamps() {
+        let mut dmc = Dmc::new();
+        dmc.write_control(0x0F);
+        dmc.timer_counter = dmc.timer_period;
+        dmc.write_output_level(0x7F);
+        dmc.set_enabled(true);
+        dmc.load_sample(0xFF);
+        clock_byte(&mut dmc);
+        clock_byte(&mut dmc);
+        assert_eq!(dmc.output(), 0x7F);
+    }
+
+    #[test]
+    fn test_dmc_irq_and_loop() {
+        let mut dmc = Dmc::new();
+        dmc.write_control(0x80); // IRQ enabled
+        dmc.set_enabled(true);
+        dmc.load_sample(0);
+        assert!(dmc.irq_flag);
+        dmc.write_control(0x00);
+        assert!(!dmc.irq_flag);
+
+        dmc.write_control(0x40); // loop
+        dmc.write_sample_length(0x01); // 17 bytes
+        dmc.set_enabled(true);
+        for _ in 0..17 {
+            dmc.load_sample(0);
+        }
+        assert_eq!(dmc.bytes_remaining, 17);
+        assert_eq!(dmc.pending_fetch(), None); // buffer still full
+        assert!(!dmc.irq_flag);
+    }
+
+    #[test]
+    fn test_dmc_address_wraps_to_8000() {
+        let mut dmc = Dmc::new();
+        dmc.write_sample_address(0xFF); // $FFC0
+        dmc.write_sample_length(0x04); // 65 bytes
+        dmc.set_enabled(true);
+        for _ in 0..64 {
+            dmc.load_sample(0);
+            dmc.sample_buffer = None;
+        }
+        assert_eq!(dmc.pending_fetch(), Some(0x8000));
+    }
+}
@@ -0,0 +1,107 @@
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+enum FilterKind {
+    HighPass,
+    LowPass,
+}
+
+/// First-order RC filter, used to model the NES's analog output stage.
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioFilter {
+    kind: FilterKind,
+    alpha: f32,
+    prev_in: f32,
+    prev_out: f32,
+}
+
+impl AudioFilter {
+    pub fn high_pass(cutoff_hz: f64, sample_rate: f64) -> Self {
+        let (rc, dt) = Self::rc_dt(cutoff_hz, sample_rate);
+        Self::new(FilterKind::HighPass, rc / (rc + dt))
+    }
+
+    pub fn low_pass(cutoff_hz: f64, sample_rate: f64) -> Self {
+        let (rc, dt) = Self::rc_dt(cutoff_hz, sample_rate);
+        Self::new(FilterKind::LowPass, dt / (rc + dt))
+    }
+
+    fn rc_dt(cutoff_hz: f64, sample_rate: f64) -> (f64, f64) {
+        (1.0 / (2.0 * PI * cutoff_hz), 1.0 / sample_rate)
+    }
+
+    fn new(kind: FilterKind, alpha: f64) -> Self {
+        AudioFilter {
+            kind,
+            alpha: alpha as f32,
+            prev_in: 0.0,
+            prev_out: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let out = match self.kind {
+            FilterKind::HighPass => self.alpha * (self.prev_out + sample - self.prev_in),
+            FilterKind::LowPass => self.prev_out + self.alpha * (sample - self.prev_out),
+        };
+        self.prev_in = sample;
+        self.prev_out = out;
+        out
+    }
+}
+
+/// The NES output chain: high-pass at 90Hz and 440Hz, then low-pass at 14kHz.
+/// Coefficients depend on `sample_rate`, so rebuild the chain if it changes.
+pub fn output_chain(sample_rate: f64) -> [AudioFilter; 3] {
+    [
+        AudioFilter::high_pass(90.0, sample_rate),
+        AudioFilter::high_pass(440.0, sample_rate),
+        AudioFilter::low_pass(14_000.0, sample_rate),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 44_100.0;
+
+    /// Peak output amplitude over the second half of `n` samples of a unit sine.
+    fn sine_peak(filter: &mut AudioFilter, freq: f64, n: usize) -> f32 {
+        (0..n)
+            .map(|i| filter.process((2.0 * PI * freq * i as f64 / RATE).sin() as f32))
+            .skip(n / 2)
+            .fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn test_high_pass_removes_dc() {
+        let mut filter = AudioFilter::high_pass(90.0, RATE);
+        let first = filter.process(0.5);
+        assert!(first > 0.45);
+        let last = (0..RATE as usize / 10).map(|_| filter.process(0.5)).last().unwrap();
+        assert!(last.abs() < 0.001, "DC not removed: {}", last);
+    }
+
+    #[test]
+    fn test_low_pass_attenuates_high_frequencies() {
+        let mut filter = AudioFilter::low_pass(14_000.0, RATE);
+        let high = sine_peak(&mut filter, 20_000.0, 4410);
+        let mut filter = AudioFilter::low_pass(14_000.0, RATE);
+        let low = sine_peak(&mut filter, 440.0, 4410);
+        assert!(high < 0.6, "20kHz passed at {}", high);
+        assert!(low > 0.95, "440Hz attenuated to {}", low);
+    }
+
+    #[test]
+    fn test_output_chain_centers_signal() {
+        let mut chain = output_chain(RATE);
+        let mut out = 0.0;
+        for _ in 0..RATE as usize / 10 {
+            out = chain.iter_mut().fold(0.25, |s, f| f.process(s));
+        }
+        assert!(out.abs() < 0.001);
+    }
+}
@@ -1,88 +1,236 @@
 pub mod pulse;
 pub mod triangle;
 pub mod noise;
+pub mod dmc;
+pub mod filter;
+pub mod resample;
 
 use pulse::Pulse;
 use triangle::Triangle;
 use noise::Noise;
+use dmc::Dmc;
+use filter::AudioFilter;
+use resample::Resampler;
 use crossbeam::queue::ArrayQueue;
 use std::sync::Arc;
 
-const CPU_FREQ: f64 = 1_789_773.0;
-const SAMPLE_RATE: f64 = 44_100.0;
-const CYCLES_PER_SAMPLE: f64 = CPU_FREQ / SAMPLE_RATE;
+use crate::nes::Region;
 
+/// Output rate used unless the frontend asks for another.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+/// Width of the resampling kernel, in output samples.
+const RESAMPLER_TAPS: usize = 64;
+
+/// Frame counter step cycles, in APU cycles (two CPU cycles each): three
+/// quarter frames, the 4-step IRQ, the 4-step wrap, and the 5-step wrap.
+const NTSC_FRAME_STEPS: [u16; 6] = [3729, 7457, 11186, 14914, 14915, 18641];
+const PAL_FRAME_STEPS: [u16; 6] = [4157, 8314, 12470, 16626, 16627, 20783];
+
+/// Where the APU delivers its output samples, resampled to the configured
+/// sample rate (`DEFAULT_SAMPLE_RATE` unless `Nes::set_sample_rate` changes it).
 #[derive(Clone)]
+pub enum SampleSink {
+    /// Shared with an audio callback; samples are dropped while it's full.
+    Queue(Arc<ArrayQueue<f32>>),
+    /// Kept for the caller to drain, as `Nes::step_frame_headless` does.
+    Buffer(Vec<f32>),
+    /// Thrown away, for running without any audio at all.
+    Discard,
+}
+
+impl SampleSink {
+    /// Push one sample per output channel. A full queue drops the whole frame
+    /// so stereo pairs stay aligned.
+    fn push(&mut self, frame: &[f32]) {
+        match self {
+            SampleSink::Queue(queue) => {
+                if queue.capacity() - queue.len() >= frame.len() {
+                    for &sample in frame {
+                        let _ = queue.push(sample);
+                    }
+                }
+            }
+            SampleSink::Buffer(buffer) => buffer.extend_from_slice(frame),
+            SampleSink::Discard => {}
+        }
+    }
+}
+
+/// Left and right gain of one APU channel in stereo output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoPan {
+    pub left: f32,
+    pub right: f32,
+}
+
+impl StereoPan {
+    pub const CENTER: StereoPan = StereoPan { left: 1.0, right: 1.0 };
+}
+
+/// Pans for pulse 1, pulse 2, triangle, noise and DMC, roughly the separation
+/// of a Famicom stereo headphone mod.
+pub const DEFAULT_PANNING: [StereoPan; 5] = [
+    StereoPan { left: 0.8, right: 0.5 },
+    StereoPan { left: 0.5, right: 0.8 },
+    StereoPan::CENTER,
+    StereoPan { left: 0.6, right: 0.8 },
+    StereoPan::CENTER,
+];
+
+/// Placeholder sink for a deserialized `Apu`; `Nes::load_state` swaps the live one back in.
+#[cfg(feature = "save_states")]
+fn detached_output() -> SampleSink {
+    SampleSink::Buffer(Vec::new())
+}
+
+fn full_volume() -> f32 {
+    1.0
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub struct Apu {
     pub pulse1: Pulse,
     pub pulse2: Pulse,
     pub triangle: Triangle,
     pub noise: Noise,
+    pub dmc: Dmc,
 
     // Frame counter
     frame_counter_mode: u8, // 0 = 4-step, 1 = 5-step
     frame_counter: u16,
     irq_inhibit: bool,
+    pub irq_pending: bool, // frame IRQ flag, read (and cleared) through $4015 bit 6
+
+    // Downsampling from the CPU clock, per output channel (left, right)
+    resamplers: [Resampler; 2],
+
+    // Timing
+    region: Region,
+    sample_rate: u32,
 
-    // Downsampling
-    sample_accumulator: f64,
-    sample_count: f64,
-    cycle_fraction: f64,
+    // Output filters (HP 90Hz, HP 440Hz, LP 14kHz) for each channel, run at sample_rate
+    filters: [[AudioFilter; 3]; 2],
 
-    // Output buffer
-    pub sample_buffer: Arc<ArrayQueue<f32>>,
+    // Output, owned by the audio device or caller rather than the machine state
+    #[cfg_attr(feature = "save_states", serde(skip, default = "detached_output"))]
+    pub output: SampleSink,
+    /// Stereo pans for each channel, or `None` for mono output. Stereo
+    /// samples are interleaved left, right.
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    pub panning: Option<[StereoPan; 5]>,
+    /// Output gain, 0.0-1.0.
+    #[cfg_attr(feature = "save_states", serde(skip, default = "full_volume"))]
+    pub master_volume: f32,
+    /// Silenced channels: pulse 1, pulse 2, triangle, noise, DMC.
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    pub channel_mute: [bool; 5],
 
     // Cycle parity (APU runs at half CPU rate for pulse/noise)
     odd_cycle: bool,
 }
 
 impl Apu {
-    pub fn new(sample_buffer: Arc<ArrayQueue<f32>>) -> Self {
+    pub fn new(sample_buffer: Arc<ArrayQueue<f32>>, sample_rate: u32) -> Self {
+        let mut apu = Self::with_output(SampleSink::Queue(sample_buffer));
+        apu.set_sample_rate(sample_rate);
+        apu
+    }
+
+    pub fn with_output(output: SampleSink) -> Self {
         Apu {
             pulse1: Pulse::new(0),
             pulse2: Pulse::new(1),
             triangle: Triangle::new(),
             noise: Noise::new(),
+            dmc: Dmc::new(),
             frame_counter_mode: 0,
             frame_counter: 0,
             irq_inhibit: true,
-            sample_accumulator: 0.0,
-            sample_count: 0.0,
-            cycle_fraction: 0.0,
-            sample_buffer,
+            irq_pending: false,
+            resamplers: Self::resamplers(Region::Ntsc, DEFAULT_SAMPLE_RATE),
+            region: Region::Ntsc,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            filters: [filter::output_chain(DEFAULT_SAMPLE_RATE as f64), filter::output_chain(DEFAULT_SAMPLE_RATE as f64)],
+            output,
+            panning: None,
+            master_volume: full_volume(),
+            channel_mute: [false; 5],
             odd_cycle: false,
         }
     }
 
+    /// Switch the CPU clock the output is resampled from, along with the
+    /// frame counter and the noise and DMC period tables.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.resamplers = Self::resamplers(region, self.sample_rate);
+        self.noise.region = region;
+        self.dmc.region = region;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Switch the output rate in Hz, rebuilding the resamplers and the output
+    /// filters for it. Samples already queued aren't converted.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.resamplers = Self::resamplers(self.region, sample_rate);
+        let chain = filter::output_chain(sample_rate as f64);
+        self.filters = [chain.clone(), chain];
+    }
+
+    fn resamplers(region: Region, sample_rate: u32) -> [Resampler; 2] {
+        let resampler = Resampler::new(region.cpu_freq(), sample_rate as f64, RESAMPLER_TAPS);
+        [resampler.clone(), resampler]
+    }
+
+    /// Samples per output frame: 2 in stereo, 1 in mono.
+    pub fn channels(&self) -> usize {
+        if self.panning.is_some() { 2 } else { 1 }
+    }
+
+    fn frame_steps(&self) -> [u16; 6] {
+        match self.region {
+            Region::Pal => PAL_FRAME_STEPS,
+            Region::Ntsc | Region::Dendy => NTSC_FRAME_STEPS,
+        }
+    }
+
     /// Tick the APU for one CPU cycle.
     pub fn tick(&mut self) {
-        // Triangle timer runs at CPU rate
+        // Triangle and DMC timers run at CPU rate
         self.triangle.tick_timer();
+        self.dmc.tick_timer();
 
-        // Pulse and noise timers run at half CPU rate (every other cycle)
+        // Pulse and noise timers and the frame counter run at half CPU rate
+        // (every other cycle)
         self.odd_cycle = !self.odd_cycle;
         if self.odd_cycle {
             self.pulse1.tick_timer();
             self.pulse2.tick_timer();
             self.noise.tick_timer();
+        } else {
+            self.frame_counter += 1;
+            self.clock_frame_counter();
         }
 
-        // Frame counter
-        self.frame_counter += 1;
-        self.clock_frame_counter();
-
         // Mix and downsample
-        let sample = self.mix();
-        self.sample_accumulator += sample;
-        self.sample_count += 1.0;
-        self.cycle_fraction += 1.0;
+        let (left, right) = self.mix();
+        self.resamplers[0].push_input(left);
+        self.resamplers[1].push_input(right);
 
-        if self.cycle_fraction >= CYCLES_PER_SAMPLE {
-            let avg = (self.sample_accumulator / self.sample_count) as f32;
-            let _ = self.sample_buffer.push(avg);
-            self.sample_accumulator = 0.0;
-            self.sample_count = 0.0;
-            self.cycle_fraction -= CYCLES_PER_SAMPLE;
+        let channels = self.channels();
+        while let Some(left) = self.resamplers[0].pop_output() {
+            // Both resamplers see the same number of inputs, so they stay in step
+            let right = self.resamplers[1].pop_output().unwrap_or(left);
+            let mut frame = [left, right];
+            for (channel, out) in frame.iter_mut().enumerate().take(channels) {
+                *out = self.filters[channel].iter_mut().fold(*out, |s, f| f.process(s));
+            }
+            self.output.push(&frame[..channels]);
         }
     }
 
@@ -95,13 +243,15 @@ impl Apu {
     }
 
     fn clock_4step(&mut self) {
+        let [q1, half, q3, irq, wrap, _] = self.frame_steps();
         match self.frame_counter {
-            3729 => self.quarter_frame(),
-            7457 => { self.quarter_frame(); self.half_frame(); }
-            11186 => self.quarter_frame(),
-            14915 => {
+            c if c == q1 || c == q3 => self.quarter_frame(),
+            c if c == half => { self.quarter_frame(); self.half_frame(); }
+            c if c == irq => self.set_frame_irq(),
+            c if c == wrap => {
                 self.quarter_frame();
                 self.half_frame();
+                self.set_frame_irq();
                 self.frame_counter = 0;
             }
             _ => {}
@@ -109,11 +259,11 @@ impl Apu {
     }
 
     fn clock_5step(&mut self) {
+        let [q1, half, q3, _, _, wrap] = self.frame_steps();
         match self.frame_counter {
-            3729 => self.quarter_frame(),
-            7457 => { self.quarter_frame(); self.half_frame(); }
-            11186 => self.quarter_frame(),
-            18641 => {
+            c if c == q1 || c == q3 => self.quarter_frame(),
+            c if c == half => { self.quarter_frame(); self.half_frame(); }
+            c if c == wrap => {
                 self.quarter_frame();
                 self.half_frame();
                 self.frame_counter = 0;
@@ -122,6 +272,17 @@ impl Apu {
         }
     }
 
+    fn set_frame_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.irq_pending = true;
+        }
+    }
+
+    /// True while the frame counter or DMC is asserting the CPU's IRQ line.
+    pub fn irq_line(&self) -> bool {
+        self.irq_pending || self.dmc.irq_flag
+    }
+
     fn quarter_frame(&mut self) {
         self.pulse1.tick_envelope();
         self.pulse2.tick_envelope();
@@ -138,21 +299,44 @@ impl Apu {
         self.noise.tick_length();
     }
 
-    /// Mix all channels using the NES non-linear mixing formula (approximated).
-    fn mix(&self) -> f64 {
-        let p1 = self.pulse1.output() as f64;
-        let p2 = self.pulse2.output() as f64;
-        let t = self.triangle.output() as f64;
-        let n = self.noise.output() as f64;
+    /// Left and right mixes of the unmuted channels, scaled by the master
+    /// volume. Mono output puts the plain mix on both sides.
+    fn mix(&self) -> (f64, f64) {
+        let mut levels = [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ]
+        .map(f64::from);
+        for (level, muted) in levels.iter_mut().zip(self.channel_mute) {
+            if muted {
+                *level = 0.0;
+            }
+        }
+        let volume = self.master_volume as f64;
+        let Some(panning) = self.panning else {
+            let mono = Self::dac(levels) * volume;
+            return (mono, mono);
+        };
+        let side = |gain: fn(&StereoPan) -> f32| {
+            Self::dac(std::array::from_fn(|i| levels[i] * gain(&panning[i]) as f64)) * volume
+        };
+        (side(|pan| pan.left), side(|pan| pan.right))
+    }
 
+    /// Mix channel levels (pulse 1, pulse 2, triangle, noise, DMC) using the
+    /// NES non-linear mixing formula (approximated).
+    fn dac([p1, p2, t, n, d]: [f64; 5]) -> f64 {
         // Approximation of the NES DAC mixing
         let pulse_out = if p1 + p2 > 0.0 {
             95.88 / (8128.0 / (p1 + p2) + 100.0)
         } else {
             0.0
         };
-        let tnd_out = if t + n > 0.0 {
-            159.79 / (1.0 / (t / 8227.0 + n / 12241.0) + 100.0)
+        let tnd_out = if t + n + d > 0.0 {
+            159.79 / (1.0 / (t / 8227.0 + n / 12241.0 + d / 22638.0) + 100.0)
         } else {
             0.0
         };
@@ -178,7 +362,11 @@ impl Apu {
             0x400C => self.noise.write_control(val),
             0x400E => self.noise.write_period(val),
             0x400F => self.noise.write_length(val),
-            _ => {} // $4009, $400D, $4010-$4013 (DMC) ignored
+            0x4010 => self.dmc.write_control(val),
+            0x4011 => self.dmc.write_output_level(val),
+            0x4012 => self.dmc.write_sample_address(val),
+            0x4013 => self.dmc.write_sample_length(val),
+            _ => {} // $4009, $400D unused
         }
     }
 
@@ -188,6 +376,7 @@ impl Apu {
         self.pulse2.enabled = val & 0x02 != 0;
         self.triangle.enabled = val & 0x04 != 0;
         self.noise.enabled = val & 0x08 != 0;
+        self.dmc.set_enabled(val & 0x10 != 0);
 
         if !self.pulse1.enabled { self.pulse1.length_counter = 0; }
         if !self.pulse2.enabled { self.pulse2.length_counter = 0; }
@@ -197,11 +386,22 @@ impl Apu {
 
     // $4015 read
     pub fn read_status(&mut self) -> u8 {
+        let val = self.peek_status();
+        // Reading acknowledges the frame IRQ (but not the DMC one)
+        self.irq_pending = false;
+        val
+    }
+
+    /// $4015 as `read_status` would return it, without acknowledging the frame IRQ.
+    pub fn peek_status(&self) -> u8 {
         let mut val = 0u8;
         if self.pulse1.length_counter > 0 { val |= 0x01; }
         if self.pulse2.length_counter > 0 { val |= 0x02; }
         if self.triangle.length_counter > 0 { val |= 0x04; }
         if self.noise.length_counter > 0 { val |= 0x08; }
+        if self.dmc.bytes_remaining > 0 { val |= 0x10; }
+        if self.irq_pending { val |= 0x40; }
+        if self.dmc.irq_flag { val |= 0x80; }
         val
     }
 
@@ -209,6 +409,9 @@ impl Apu {
     pub fn write_frame_counter(&mut self, val: u8) {
         self.frame_counter_mode = (val >> 7) & 1;
         self.irq_inhibit = val & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq_pending = false;
+        }
         self.frame_counter = 0;
         if self.frame_counter_mode == 1 {
             // 5-step mode immediately clocks
@@ -217,3 +420,141 @@ impl Apu {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apu() -> Apu {
+        Apu::new(Arc::new(ArrayQueue::new(16)), DEFAULT_SAMPLE_RATE)
+    }
+
+    #[test]
+    fn test_frame_irq_fires_at_step_4() {
+        let mut apu = apu();
+        apu.write_frame_counter(0x00); // 4-step, IRQs enabled
+        // Step 4 is APU cycle 14914, CPU cycle 29828
+        for _ in 0..29827 {
+            apu.tick();
+        }
+        assert!(!apu.irq_line());
+        apu.tick();
+        assert!(apu.irq_line());
+
+        // $4015 reports the flag in bit 6 and clears it
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.irq_pending);
+        assert_eq!(apu.read_status() & 0x40, 0);
+    }
+
+    #[test]
+    fn test_frame_irq_inhibit() {
+        let mut apu = apu();
+        apu.write_frame_counter(0x00);
+        for _ in 0..2 * 14915 {
+            apu.tick();
+        }
+        assert!(apu.irq_pending);
+        // Setting the inhibit bit clears a pending IRQ and blocks new ones
+        apu.write_frame_counter(0x40);
+        assert!(!apu.irq_pending);
+        for _ in 0..4 * 14915 {
+            apu.tick();
+        }
+        assert!(!apu.irq_pending);
+
+        // 5-step mode never raises the frame IRQ
+        apu.write_frame_counter(0x80);
+        for _ in 0..4 * 18641 {
+            apu.tick();
+        }
+        assert!(!apu.irq_pending);
+    }
+
+    #[test]
+    fn test_stereo_mix_pans_a_single_channel() {
+        let mut apu = apu();
+        apu.cpu_write(0x4011, 0x40); // DMC level 64, every other channel silent
+        let dmc_only = |level: f64| Apu::dac([0.0, 0.0, 0.0, 0.0, level]);
+
+        let (left, right) = apu.mix();
+        assert_eq!(left, right);
+        assert_eq!(left, dmc_only(64.0));
+
+        let mut panning = [StereoPan::CENTER; 5];
+        panning[4] = StereoPan { left: 1.0, right: 0.5 };
+        apu.panning = Some(panning);
+        let (left, right) = apu.mix();
+        assert_eq!(left, dmc_only(64.0));
+        assert_eq!(right, dmc_only(32.0));
+        assert!(right < left);
+    }
+
+    #[test]
+    fn test_muted_channels_drop_out_of_the_mix() {
+        let mut apu = apu();
+        apu.write_status(0x01); // enable pulse 1
+        apu.cpu_write(0x4000, 0x3F); // constant volume 15
+        apu.cpu_write(0x4002, 0x00);
+        apu.cpu_write(0x4003, 0x01);
+        apu.cpu_write(0x4011, 0x40); // DMC level 64
+        while apu.pulse1.output() == 0 {
+            apu.tick();
+        }
+        let levels = [apu.pulse1.output(), apu.pulse2.output(), apu.triangle.output(), apu.noise.output(), apu.dmc.output()]
+            .map(f64::from);
+        assert_eq!(apu.mix().0, Apu::dac(levels));
+
+        apu.channel_mute[0] = true;
+        let (left, right) = apu.mix();
+        assert_eq!(left, right);
+        assert_eq!(left, Apu::dac([0.0, levels[1], levels[2], levels[3], levels[4]]));
+        assert!(left < Apu::dac(levels));
+    }
+
+    #[test]
+    fn test_master_volume_scales_output() {
+        let mut apu = apu();
+        apu.cpu_write(0x4011, 0x40);
+        let full = apu.mix().0;
+        assert!(full > 0.0);
+        apu.master_volume = 0.5;
+        assert_eq!(apu.mix().0, full * 0.5);
+        apu.panning = Some(DEFAULT_PANNING);
+        let (left, right) = apu.mix();
+        apu.master_volume = 1.0;
+        assert_eq!(apu.mix(), (left * 2.0, right * 2.0));
+        apu.master_volume = 0.0;
+        assert_eq!(apu.mix(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_stereo_output_interleaves_channels() {
+        let mut apu = Apu::with_output(SampleSink::Buffer(Vec::new()));
+        apu.panning = Some(DEFAULT_PANNING);
+        for _ in 0..10_000 {
+            apu.tick();
+        }
+        let SampleSink::Buffer(samples) = &apu.output else { unreachable!() };
+        // About 246 frames at 44.1kHz, less the resampler's 32-frame lookahead,
+        // two samples each
+        assert_eq!(apu.channels(), 2);
+        assert!(samples.len() % 2 == 0 && (424..=432).contains(&samples.len()), "{} samples", samples.len());
+    }
+
+    #[test]
+    fn test_sample_rate_sets_samples_per_frame() {
+        let mut apu = Apu::new(Arc::new(ArrayQueue::new(4096)), 48_000);
+        // Settle past the resampler's lookahead, then count one NTSC frame
+        for _ in 0..29_781 {
+            apu.tick();
+        }
+        let SampleSink::Queue(queue) = &apu.output else { unreachable!() };
+        while queue.pop().is_some() {}
+        for _ in 0..29_781 {
+            apu.tick();
+        }
+        let SampleSink::Queue(queue) = &apu.output else { unreachable!() };
+        assert!((798..=800).contains(&queue.len()), "{} samples", queue.len());
+    }
+}
@@ -1,10 +1,15 @@
 use super::pulse::LENGTH_TABLE;
+use crate::nes::Region;
 
 const NOISE_PERIOD_TABLE: [u16; 16] = [
     4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
 ];
+const NOISE_PERIOD_TABLE_PAL: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
 
 #[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub struct Noise {
     pub enabled: bool,
 
@@ -27,6 +32,9 @@ pub struct Noise {
     envelope_period: u8,
     envelope_divider: u8,
     envelope_decay: u8,
+
+    // Picks the period table
+    pub region: Region,
 }
 
 impl Default for Noise {
@@ -51,6 +59,7 @@ impl Noise {
             envelope_period: 0,
             envelope_divider: 0,
             envelope_decay: 0,
+            region: Region::Ntsc,
         }
     }
 
@@ -65,7 +74,8 @@ impl Noise {
     // $400E
     pub fn write_period(&mut self, val: u8) {
         self.mode = val & 0x80 != 0;
-        self.timer_period = NOISE_PERIOD_TABLE[(val & 0x0F) as usize];
+        let table = if self.region == Region::Pal { &NOISE_PERIOD_TABLE_PAL } else { &NOISE_PERIOD_TABLE };
+        self.timer_period = table[(val & 0x0F) as usize];
     }
 
     // $400F
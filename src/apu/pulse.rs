@@ -11,6 +11,7 @@ pub const LENGTH_TABLE: [u8; 32] = [
 ];
 
 #[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pulse {
     pub enabled: bool,
     pub channel: u8, // 0 = pulse1, 1 = pulse2
@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Kernel phases stored per filter-rate sample; offsets in between are
+/// linearly interpolated.
+const PHASES: usize = 32;
+/// The windowed-sinc stage runs at no less than this multiple of the output
+/// rate.
+const OVERSAMPLE: f64 = 4.0;
+
+/// Band-limited downsampler from the APU's CPU-rate samples to the output
+/// rate. Input is first decimated by a whole factor to a few times the output
+/// rate through a short Blackman-windowed FIR, which keeps everything that
+/// would fold into the audible band out, then filtered with a Lanczos
+/// (windowed-sinc) kernel cut off at the output Nyquist frequency.
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct Resampler {
+    taps: usize,
+    /// Input samples per filter-rate sample.
+    decimation: u32,
+    /// Decimation filter cutoff, as a fraction of the input rate.
+    decimator_cutoff: f64,
+    /// Normalized decimation filter coefficients, a multiple of 8 long.
+    /// Rebuilt like `kernel`.
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    decimator: Arc<[f32]>,
+    /// The last `decimator.len()` input samples, stored twice over so the
+    /// window starting at `input_pos` is always contiguous.
+    input: Vec<f32>,
+    input_pos: usize,
+    /// Input samples since the last filter-rate sample.
+    pending: u32,
+    /// Filter-rate samples per output sample.
+    step: f64,
+    /// Filter-rate samples on each side of an output sample's position.
+    half_width: usize,
+    /// `PHASES + 1` rows of `2 * half_width` normalized coefficients. Save
+    /// states leave it out and it's rebuilt on first use.
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    kernel: Arc<[f32]>,
+    history: VecDeque<f32>,
+    /// Position of the next output sample, in filter-rate samples from the
+    /// front of `history`.
+    position: f64,
+}
+
+impl Resampler {
+    /// A resampler whose kernel spans `taps` output samples (`taps / 2`
+    /// Lanczos lobes each side).
+    pub fn new(input_rate: f64, output_rate: f64, taps: usize) -> Self {
+        let decimation = (input_rate / (output_rate * OVERSAMPLE)).floor().max(1.0) as u32;
+        let filter_rate = input_rate / decimation as f64;
+        let step = filter_rate / output_rate;
+        // Pass the output band, and stop what would alias into it at the
+        // filter rate. A Blackman window needs about 5.5 / width taps.
+        let (pass, stop) = (output_rate / 2.0, filter_rate - output_rate / 2.0);
+        let decimator_taps = if decimation == 1 { 8 } else { (5.5 * input_rate / (stop - pass) / 8.0).ceil() as usize * 8 };
+        let decimator_cutoff = (pass + stop) / 2.0 / input_rate;
+        // Rounded so the window splits into whole 8-sample chunks
+        let half_width = ((taps / 2) as f64 * step / 4.0).ceil() as usize * 4;
+        Resampler {
+            taps,
+            decimation,
+            decimator_cutoff,
+            decimator: Self::build_decimator(decimator_taps, decimation, decimator_cutoff),
+            input: vec![0.0; 2 * decimator_taps],
+            input_pos: 0,
+            pending: 0,
+            step,
+            half_width,
+            kernel: Self::build_kernel(taps, step, half_width),
+            // Start on silence so the first outputs have a full window
+            history: VecDeque::from(vec![0.0; half_width]),
+            position: (half_width - 1) as f64,
+        }
+    }
+
+    /// Lanczos kernel, `(taps / 2)` lobes wide in output samples, tabulated at
+    /// `PHASES` sub-sample offsets. Each row is normalized to unity DC gain.
+    fn build_kernel(taps: usize, step: f64, half_width: usize) -> Arc<[f32]> {
+        let lobes = (taps / 2) as f64;
+        let lanczos = |x: f64| {
+            let sinc = |x: f64| if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            if x.abs() < lobes { sinc(x) * sinc(x / lobes) } else { 0.0 }
+        };
+        let width = 2 * half_width;
+        let mut kernel = Vec::with_capacity((PHASES + 1) * width);
+        for phase in 0..=PHASES {
+            let frac = phase as f64 / PHASES as f64;
+            let row: Vec<f64> = (0..width)
+                .map(|j| lanczos((j as f64 - half_width as f64 + 1.0 - frac) / step))
+                .collect();
+            let total: f64 = row.iter().sum();
+            kernel.extend(row.iter().map(|&c| (c / total) as f32));
+        }
+        kernel.into()
+    }
+
+    /// Blackman-windowed sinc lowpass at `cutoff` (a fraction of the input
+    /// rate), normalized to unity DC gain. Without decimation it passes the
+    /// newest sample straight through.
+    fn build_decimator(len: usize, decimation: u32, cutoff: f64) -> Arc<[f32]> {
+        if decimation == 1 {
+            let mut identity = vec![0.0; len];
+            identity[len - 1] = 1.0;
+            return identity.into();
+        }
+        let center = (len - 1) as f64 / 2.0;
+        let coefficients: Vec<f64> = (0..len)
+            .map(|i| {
+                let x = i as f64 - center;
+                let sinc = if x == 0.0 { 2.0 * cutoff } else { (2.0 * PI * cutoff * x).sin() / (PI * x) };
+                let phase = 2.0 * PI * i as f64 / (len - 1) as f64;
+                sinc * (0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos())
+            })
+            .collect();
+        let total: f64 = coefficients.iter().sum();
+        coefficients.iter().map(|&c| (c / total) as f32).collect()
+    }
+
+    /// Add one input-rate sample.
+    pub fn push_input(&mut self, sample: f64) {
+        let len = self.input.len() / 2;
+        self.input[self.input_pos] = sample as f32;
+        self.input[self.input_pos + len] = sample as f32;
+        self.input_pos = (self.input_pos + 1) % len;
+        self.pending += 1;
+        if self.pending < self.decimation {
+            return;
+        }
+        self.pending = 0;
+        if self.decimator.is_empty() {
+            self.decimator = Self::build_decimator(len, self.decimation, self.decimator_cutoff);
+        }
+        let window = &self.input[self.input_pos..self.input_pos + len];
+        self.history.push_back(dot(window, &self.decimator));
+    }
+
+    /// The next output-rate sample, once enough input has arrived to fill
+    /// its window.
+    pub fn pop_output(&mut self) -> Option<f32> {
+        let base = self.position as usize;
+        if base + self.half_width >= self.history.len() {
+            return None;
+        }
+        if self.kernel.is_empty() {
+            self.kernel = Self::build_kernel(self.taps, self.step, self.half_width);
+        }
+
+        let width = 2 * self.half_width;
+        let scaled = (self.position - base as f64) * PHASES as f64;
+        let phase = (scaled as usize).min(PHASES - 1);
+        let blend = (scaled - phase as f64) as f32;
+        let (row, next) = self.kernel[phase * width..(phase + 2) * width].split_at(width);
+        let window = &self.history.make_contiguous()[base + 1 - self.half_width..=base + self.half_width];
+        // Eight independent partial sums so the loop vectorizes
+        let mut sums = [0.0f32; 8];
+        for ((samples, a), b) in window.chunks_exact(8).zip(row.chunks_exact(8)).zip(next.chunks_exact(8)) {
+            for lane in 0..8 {
+                sums[lane] += samples[lane] * (a[lane] + (b[lane] - a[lane]) * blend);
+            }
+        }
+        let out: f32 = sums.iter().sum();
+
+        self.position += self.step;
+        // Drop samples no later window reaches back to
+        let consumed = (self.position as usize + 1).saturating_sub(self.half_width);
+        self.history.drain(..consumed);
+        self.position -= consumed as f64;
+        Some(out)
+    }
+}
+
+/// Dot product of two equal-length slices whose length is a multiple of 8,
+/// in eight independent partial sums so the loop vectorizes.
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let mut sums = [0.0f32; 8];
+    for (a, b) in a.chunks_exact(8).zip(b.chunks_exact(8)) {
+        for lane in 0..8 {
+            sums[lane] += a[lane] * b[lane];
+        }
+    }
+    sums.iter().sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CPU_RATE: f64 = 1_789_773.0;
+    const OUT_RATE: f64 = 44_100.0;
+
+    /// Amplitude of the `freq` component of the output for a unit sine input
+    /// at `freq`, measured over 0.1s after the filter has settled.
+    fn output_magnitude(input_freq: f64, measure_freq: f64) -> f64 {
+        let mut resampler = Resampler::new(CPU_RATE, OUT_RATE, 64);
+        let mut output = Vec::new();
+        for i in 0..(CPU_RATE * 0.2) as usize {
+            resampler.push_input((2.0 * PI * input_freq * i as f64 / CPU_RATE).sin());
+            while let Some(sample) = resampler.pop_output() {
+                output.push(sample as f64);
+            }
+        }
+        let settled = &output[output.len() - OUT_RATE as usize / 10..];
+        let (re, im) = settled.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, &s)| {
+            let angle = 2.0 * PI * measure_freq * n as f64 / OUT_RATE;
+            (re + s * angle.cos(), im + s * angle.sin())
+        });
+        2.0 * (re * re + im * im).sqrt() / settled.len() as f64
+    }
+
+    #[test]
+    fn test_passband_sine_keeps_its_level() {
+        for freq in [440.0, 1000.0, 10_000.0] {
+            let db = 20.0 * output_magnitude(freq, freq).log10();
+            assert!(db.abs() < 1.0, "{}Hz came out at {:.2}dB", freq, db);
+        }
+    }
+
+    #[test]
+    fn test_tones_above_nyquist_dont_alias() {
+        // 30kHz would fold down to 14.1kHz
+        let alias = output_magnitude(30_000.0, OUT_RATE - 30_000.0);
+        assert!(20.0 * alias.log10() < -40.0, "alias at {:.1}dB", 20.0 * alias.log10());
+    }
+
+    #[test]
+    fn test_tones_near_filter_rate_dont_alias() {
+        // Input is decimated by 10 before the sinc stage, to ~179kHz; a tone
+        // just below that would fold down to the same distance above DC
+        let filter_rate = CPU_RATE / (CPU_RATE / (OUT_RATE * OVERSAMPLE)).floor();
+        for offset in [3_000.0, 15_000.0] {
+            let alias = output_magnitude(filter_rate - offset, offset);
+            let db = 20.0 * alias.log10();
+            assert!(db < -60.0, "{:.0}Hz aliased to {}Hz at {:.1}dB", filter_rate - offset, offset, db);
+        }
+    }
+
+    #[test]
+    fn test_output_rate_tracks_input() {
+        let mut resampler = Resampler::new(CPU_RATE, OUT_RATE, 64);
+        let mut count = 0;
+        for _ in 0..CPU_RATE as usize {
+            resampler.push_input(0.5);
+            while let Some(sample) = resampler.pop_output() {
+                count += 1;
+                if count > 100 {
+                    assert!((sample - 0.5).abs() < 1e-4);
+                }
+            }
+        }
+        // One second of input, less the filter's latency
+        assert!((44_050..=44_100).contains(&count), "{} samples", count);
+    }
+}
@@ -6,6 +6,7 @@ const TRIANGLE_SEQUENCE: [u8; 32] = [
 ];
 
 #[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub struct Triangle {
     pub enabled: bool,
 
@@ -102,6 +103,31 @@ impl Triangle {
         if !self.enabled || self.length_counter == 0 || self.linear_counter == 0 {
             return 0;
         }
+        // Ultrasonic suppression: periods below 2 step the sequencer far above
+        // the audible range, which hardware turns into clicks and pops
+        if self.timer_period < 2 {
+            return 0;
+        }
         TRIANGLE_SEQUENCE[self.seq_pos as usize]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ultrasonic_period_is_silenced() {
+        let mut triangle = Triangle::new();
+        triangle.enabled = true;
+        triangle.write_linear(0x7F);
+        triangle.write_timer_lo(1);
+        triangle.write_timer_hi(0x08);
+        triangle.tick_linear();
+        assert!(triangle.length_counter > 0 && triangle.linear_counter > 0);
+        assert_eq!(triangle.output(), 0);
+
+        triangle.write_timer_lo(4);
+        assert_ne!(triangle.output(), 0);
+    }
+}
@@ -0,0 +1,55 @@
+use std::env;
+use std::fs;
+use std::process;
+use std::time::Instant;
+
+use vines::cartridge::Cartridge;
+use vines::nes::{FrameResult, Nes};
+
+/// One minute of NTSC frames.
+const DEFAULT_FRAMES: u32 = 3600;
+
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let usage = format!("Usage: {} [--frames <n>] <rom.nes>", args[0]);
+
+    let mut rom_path = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--frames" => {
+                frames = iter
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(|| fail(format!("--frames expects a frame count\n{}", usage)));
+            }
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| fail(usage));
+    let rom_data = fs::read(&rom_path).unwrap_or_else(|e| fail(format!("Failed to read ROM file '{}': {}", rom_path, e)));
+    let cartridge = Cartridge::from_ines(&rom_data).unwrap_or_else(|e| fail(format!("Failed to parse ROM: {}", e)));
+
+    let mut nes = Nes::new_no_audio(cartridge);
+
+    let start = Instant::now();
+    let mut rendered = 0;
+    while rendered < frames && nes.step_frame() == FrameResult::Completed {
+        rendered += 1;
+    }
+    let elapsed = start.elapsed();
+
+    // One line of key=value pairs for scripts and CI
+    println!(
+        "frames={} elapsed_ms={} fps={:.2}",
+        rendered,
+        elapsed.as_millis(),
+        rendered as f64 / elapsed.as_secs_f64()
+    );
+}
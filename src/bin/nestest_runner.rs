@@ -3,9 +3,21 @@ use std::sync::Arc;
 use crossbeam::queue::ArrayQueue;
 use vines::cartridge::Cartridge;
 use vines::cpu::Cpu;
+use vines::cpu::trace::TraceFormat;
 use vines::bus::Bus;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let format = match args.iter().position(|a| a == "--format").and_then(|i| args.get(i + 1)) {
+        Some(f) if f == "mesen" => TraceFormat::Mesen,
+        Some(f) if f == "nestest" => TraceFormat::Nestest,
+        Some(f) => {
+            eprintln!("Unknown trace format '{}' (expected nestest or mesen)", f);
+            std::process::exit(1);
+        }
+        None => TraceFormat::Nestest,
+    };
+
     let rom_data = fs::read("nestest.nes").expect("Could not read nestest.nes - place it in the project root");
     let cartridge = Cartridge::from_ines(&rom_data).expect("Failed to parse nestest.nes");
 
@@ -21,7 +33,8 @@ fn main() {
 
     let max_steps = 8991;
     for i in 0..max_steps {
-        let trace = cpu.trace(&mut bus);
+        let scanline = bus.ppu.scanline;
+        let trace = cpu.trace_format(&bus, format, scanline);
 
         // Print first 20 lines and any that diverge
         if i < 20 {
@@ -30,6 +43,13 @@ fn main() {
 
         cpu.step(&mut bus);
 
+        if cpu.halted {
+            println!("\n--- NESTEST FAILURE at step {} ---", i);
+            println!("Last trace: {}", trace);
+            println!("CPU halted on a KIL opcode at ${:04X}", cpu.pc);
+            std::process::exit(1);
+        }
+
         // Check nestest error codes at $02 and $03
         let err1 = bus.cpu_read(0x0002);
         let err2 = bus.cpu_read(0x0003);
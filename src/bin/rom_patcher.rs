@@ -0,0 +1,76 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use vines::cartridge::{crc32, ips, Cartridge};
+use vines::cheat::GameGenieCode;
+
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let usage = format!(
+        "Usage: {} --input <rom.nes> [--ips <patch.ips>] [--genie <code>]... --output <patched.nes>",
+        args[0]
+    );
+
+    let mut input = None;
+    let mut output = None;
+    let mut ips_path = None;
+    let mut genie_codes = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().unwrap_or_else(|| fail(format!("{} expects a value\n{}", arg, usage)));
+        match arg.as_str() {
+            "--input" => input = Some(PathBuf::from(value())),
+            "--output" => output = Some(PathBuf::from(value())),
+            "--ips" => ips_path = Some(PathBuf::from(value())),
+            "--genie" => genie_codes.push(value()),
+            _ => fail(format!("Unknown argument '{}'\n{}", arg, usage)),
+        }
+    }
+    let (Some(input), Some(output)) = (input, output) else {
+        fail(usage);
+    };
+    if ips_path.is_none() && genie_codes.is_empty() {
+        fail(format!("Nothing to apply: pass --ips and/or --genie\n{}", usage));
+    }
+
+    let mut rom_data = fs::read(&input)
+        .unwrap_or_else(|e| fail(format!("Failed to read ROM file '{}': {}", input.display(), e)));
+
+    if let Some(ips_path) = ips_path {
+        let patch = fs::read(&ips_path)
+            .unwrap_or_else(|e| fail(format!("Failed to read IPS patch '{}': {}", ips_path.display(), e)));
+        rom_data = ips::apply(&rom_data, &patch)
+            .unwrap_or_else(|e| fail(format!("Failed to apply '{}': {}", ips_path.display(), e)));
+        println!("Applied IPS patch {}", ips_path.display());
+    }
+
+    let mut cartridge = Cartridge::from_ines(&rom_data)
+        .unwrap_or_else(|e| fail(format!("Failed to parse ROM '{}': {}", input.display(), e)));
+
+    for code in &genie_codes {
+        let decoded = GameGenieCode::decode(code)
+            .unwrap_or_else(|e| fail(format!("Bad Game Genie code '{}': {}", code, e)));
+        let patched = decoded.patch_prg(&mut cartridge.prg_rom);
+        println!("{} -> ${:04X}={:02X}: {} byte(s) patched", code, decoded.address, decoded.value, patched);
+    }
+
+    cartridge
+        .save_ines(&output)
+        .unwrap_or_else(|e| fail(format!("Failed to write '{}': {}", output.display(), e)));
+
+    // Re-read the output to make sure it's a loadable ROM
+    let saved = fs::read(&output)
+        .unwrap_or_else(|e| fail(format!("Failed to read back '{}': {}", output.display(), e)));
+    let reloaded = Cartridge::from_ines(&saved)
+        .unwrap_or_else(|e| fail(format!("Patched ROM '{}' doesn't parse: {}", output.display(), e)));
+
+    println!("Wrote {}", output.display());
+    println!("; PRG ROM CRC32: {:08X}", crc32(&reloaded.prg_rom));
+}
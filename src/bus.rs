@@ -1,78 +1,365 @@
 use crossbeam::queue::ArrayQueue;
 use std::sync::Arc;
 
-use crate::apu::Apu;
+use crate::apu::{Apu, SampleSink};
 use crate::cartridge::Cartridge;
-use crate::cartridge::mapper::{Mapper, Mapper0};
-use crate::controller::Controller;
+use crate::cartridge::mapper::Mapper;
+use crate::cheat::CheatEngine;
+use crate::controller::{Controller, Zapper};
+use crate::cpu::debugger::Watchpoint;
 use crate::ppu::Ppu;
 
+/// Why the bus is being read. Every kind decodes and has side effects the
+/// same way, but a `Dummy` read isn't counted as bus traffic: it leaves the
+/// open-bus latch holding the last real transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadKind {
+    /// Opcode fetch.
+    Instruction,
+    /// Operand, pointer, stack, or vector read.
+    Data,
+    /// Read the CPU makes and throws away.
+    Dummy,
+    /// OAM or DMC DMA, performed while the CPU is halted.
+    DMA,
+}
+
+/// What the 2 KB of work RAM holds at power-on. The console doesn't clear
+/// it, and some games seed their random number generators from it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PowerOnMode {
+    #[default]
+    Zero,
+    /// Eight bytes of $FF, then eight of $00, repeating: the pattern most
+    /// consoles power up with.
+    HardwarePattern,
+    /// xorshift64 output from the seed, for reproducible garbage.
+    Random(u64),
+}
+
+impl PowerOnMode {
+    pub fn ram(self) -> [u8; 2048] {
+        let mut ram = [0; 2048];
+        match self {
+            PowerOnMode::Zero => {}
+            PowerOnMode::HardwarePattern => {
+                for (i, byte) in ram.iter_mut().enumerate() {
+                    *byte = if i & 8 == 0 { 0xFF } else { 0x00 };
+                }
+            }
+            PowerOnMode::Random(seed) => {
+                // xorshift64 gets stuck at zero, so a zero seed needs replacing
+                let mut state = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+                for chunk in ram.chunks_mut(8) {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    chunk.copy_from_slice(&state.to_le_bytes());
+                }
+            }
+        }
+        ram
+    }
+}
+
 #[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bus {
+    #[cfg_attr(feature = "save_states", serde(with = "serde_big_array::BigArray"))]
     pub ram: [u8; 2048],
     pub ppu: Ppu,
     pub apu: Apu,
     pub mapper: Box<dyn Mapper>,
     pub controller1: Controller,
     pub controller2: Controller,
-    pub cycles: u64,
+    /// Plugged into port 2 in place of `controller2` when set.
+    pub zapper: Option<Zapper>,
+    /// CPU cycles an OAM DMA has taken over, for `Nes` to hand to the CPU.
+    pub pending_stall: u16,
+    /// The CPU is halted for an OAM DMA. `Nes` sets this when it hands the
+    /// CPU the stall and clears it once the stall runs out.
+    pub oam_dma_in_progress: bool,
+    /// Length of the OAM DMA in progress: 513 cycles, or 514 when it started
+    /// on an odd CPU cycle and spends one waiting to line up with a get.
+    pub oam_dma_length: u16,
+    /// The DMC just fetched a sample byte, and `Nes` hasn't charged the CPU
+    /// for it yet.
+    pub dmc_fetch_pending: bool,
+    /// Last value on the CPU data bus, which reads of unmapped and write-only
+    /// addresses return. Dummy reads leave it alone.
+    pub open_bus_latch: u8,
+    /// The last CPU access was a write, so a write now lands on the cycle
+    /// right after it. Always clear between instructions, which start with a read.
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    last_access_was_write: bool,
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    pub watchpoint: Watchpoint,
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    pub cheats: CheatEngine,
 }
 
 impl Bus {
     pub fn new(cartridge: Cartridge, sample_buffer: Arc<ArrayQueue<f32>>) -> Self {
-        let mirroring = cartridge.mirroring;
-        let chr_rom = cartridge.chr_rom.clone();
-        let mapper: Box<dyn Mapper> = Box::new(Mapper0::new(
-            cartridge.prg_rom,
-            cartridge.chr_rom,
-            cartridge.mirroring,
-        ));
-
-        Bus {
+        Self::with_audio_output(cartridge, SampleSink::Queue(sample_buffer))
+    }
+
+    pub fn with_audio_output(cartridge: Cartridge, output: SampleSink) -> Self {
+        let mut ppu = Ppu::new(cartridge.chr_rom.clone(), cartridge.mirroring);
+        ppu.chr_is_ram = cartridge.chr_is_ram;
+        let mapper = cartridge.into_mapper();
+
+        let mut bus = Bus {
             ram: [0; 2048],
-            ppu: Ppu::new(chr_rom, mirroring),
-            apu: Apu::new(sample_buffer),
+            ppu,
+            apu: Apu::with_output(output),
             mapper,
             controller1: Controller::new(),
             controller2: Controller::new(),
-            cycles: 0,
+            zapper: None,
+            pending_stall: 0,
+            oam_dma_in_progress: false,
+            oam_dma_length: 0,
+            dmc_fetch_pending: false,
+            open_bus_latch: 0,
+            last_access_was_write: false,
+            watchpoint: Watchpoint::default(),
+            cheats: CheatEngine::default(),
+        };
+        bus.sync_mapper();
+        bus
+    }
+
+    /// Fill work RAM the way `mode` says the console powers up. Call before
+    /// `Nes::reset`.
+    pub fn power_on(&mut self, mode: PowerOnMode) {
+        self.ram = mode.ram();
+    }
+
+    /// Tick the PPU one cycle, clocking the mapper's scanline counter where PPU
+    /// A12 rises. Returns true when a frame is complete.
+    pub fn tick_ppu(&mut self) -> bool {
+        if self.ppu.a12_rising_edge() {
+            self.mapper.notify_a12();
+        }
+        self.ppu.tick()
+    }
+
+    /// Tick the APU one CPU cycle, then perform the DMC's sample fetch if it
+    /// wants one, flagging it in `dmc_fetch_pending`.
+    pub fn tick_apu(&mut self) {
+        self.apu.tick();
+        if let Some(addr) = self.apu.dmc.pending_fetch() {
+            let val = self.cpu_read_typed(addr, ReadKind::DMA);
+            self.apu.dmc.load_sample(val);
+            self.dmc_fetch_pending = true;
+        }
+    }
+
+    /// CPU cycles a pending DMC fetch steals, given how many cycles of OAM DMA
+    /// are left. On its own the fetch halts the CPU for 4 cycles. During OAM
+    /// DMA the CPU is already halted, so the DMC get slots in between OAM
+    /// cycles for 2 (the get plus a realignment cycle), or 1 when it lands on
+    /// the second-to-last OAM cycle, where no realignment is needed. On the
+    /// last OAM cycle it's too late to share the halt, and the fetch costs 3.
+    /// A 514-cycle DMA idles for a cycle after its halt to line up with a get;
+    /// a fetch that arrives before that cycle has passed takes it over and
+    /// costs only 1.
+    pub fn calculate_dma_stall(&self, oam_cycles_left: u16) -> u16 {
+        if !self.dmc_fetch_pending {
+            return 0;
         }
+        if !self.oam_dma_in_progress || oam_cycles_left == 0 {
+            return 4;
+        }
+        match oam_cycles_left {
+            1 => 3,
+            2 => 1,
+            513.. if self.oam_dma_length == 514 => 1,
+            _ => 2,
+        }
+    }
+
+    /// True while the cartridge or the APU is asserting the CPU's IRQ line.
+    pub fn irq_pending(&self) -> bool {
+        self.mapper.irq_pending() || self.apu.irq_line()
+    }
+
+    /// Hand the mapper's current CHR banking and mirroring to the PPU. Called
+    /// after every mapper write, since any of them can switch banks.
+    fn sync_mapper(&mut self) {
+        self.ppu.chr_banks = self.mapper.chr_banks();
+        self.ppu.mirroring = self.mapper.mirroring();
     }
 
     pub fn cpu_read(&mut self, addr: u16) -> u8 {
-        match addr {
+        self.cpu_read_typed(addr, ReadKind::Data)
+    }
+
+    /// `cpu_read`, tagged with the kind of access making it.
+    pub fn cpu_read_typed(&mut self, addr: u16, kind: ReadKind) -> u8 {
+        self.last_access_was_write = false;
+        let val = match addr {
             0x0000..=0x1FFF => self.ram[(addr & 0x07FF) as usize],
             0x2000..=0x3FFF => self.ppu.cpu_read(0x2000 + (addr & 0x07)),
-            0x4014 => 0,
             0x4015 => self.apu.read_status(),
             0x4016 => self.controller1.read(),
-            0x4017 => self.controller2.read(),
-            0x4000..=0x4017 => 0, // write-only APU regs
-            0x4018..=0x401F => 0,
-            0x4020..=0xFFFF => self.mapper.cpu_read(addr),
+            0x4017 => match &self.zapper {
+                Some(zapper) => zapper.read(&self.ppu),
+                None => self.controller2.read(),
+            },
+            // Write-only APU and DMA registers, and the disabled test registers
+            0x4000..=0x401F => self.open_bus_latch,
+            0x4020..=0x7FFF => self.mapper.cpu_read(addr),
+            0x8000..=0xFFFF => self.cheats.apply(addr, self.mapper.cpu_read(addr)),
+        };
+        if kind != ReadKind::Dummy {
+            self.open_bus_latch = val;
+        }
+        val
+    }
+
+    /// Same decoding as `cpu_read`, but without side effects: status flags stay
+    /// set, and the PPU address and controller shift registers don't advance.
+    /// For the tracer and debugger.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.ram[(addr & 0x07FF) as usize],
+            0x2000..=0x3FFF => self.ppu.peek_register(0x2000 + (addr & 0x07)),
+            0x4015 => self.apu.peek_status(),
+            0x4016 => self.controller1.peek(),
+            0x4017 => match &self.zapper {
+                Some(zapper) => zapper.read(&self.ppu),
+                None => self.controller2.peek(),
+            },
+            0x4000..=0x401F => self.open_bus_latch,
+            0x4020..=0x7FFF => self.mapper.cpu_read(addr),
+            0x8000..=0xFFFF => self.cheats.apply(addr, self.mapper.cpu_read(addr)),
         }
     }
 
     pub fn cpu_write(&mut self, addr: u16, val: u8) {
+        self.watchpoint.check_write(addr, val);
+        self.open_bus_latch = val;
+        let back_to_back = std::mem::replace(&mut self.last_access_was_write, true);
         match addr {
             0x0000..=0x1FFF => self.ram[(addr & 0x07FF) as usize] = val,
             0x2000..=0x3FFF => self.ppu.cpu_write(0x2000 + (addr & 0x07), val),
             0x4014 => self.oam_dma(val),
             0x4000..=0x4013 => self.apu.cpu_write(addr, val),
             0x4015 => self.apu.write_status(val),
-            0x4016 => self.controller1.write(val),
+            0x4016 => {
+                // The strobe line is shared by both ports
+                self.controller1.write(val);
+                self.controller2.write(val);
+            }
             0x4017 => self.apu.write_frame_counter(val),
             0x4018..=0x401F => {}
-            0x4020..=0xFFFF => self.mapper.cpu_write(addr, val),
+            0x4020..=0xFFFF => {
+                if back_to_back {
+                    self.mapper.cpu_write_after_write(addr, val);
+                } else {
+                    self.mapper.cpu_write(addr, val);
+                }
+                self.sync_mapper();
+            }
         }
     }
 
     fn oam_dma(&mut self, page: u8) {
         let base = (page as u16) << 8;
         for i in 0..256u16 {
-            let val = self.cpu_read(base + i);
+            let val = self.cpu_read_typed(base + i, ReadKind::DMA);
             self.ppu.oam[self.ppu.oam_addr.wrapping_add(i as u8) as usize] = val;
         }
-        // DMA takes 513 or 514 CPU cycles - handled by CPU stall
+        // One get and one put cycle per byte, plus a halt cycle. `Nes` adds the
+        // alignment cycle when the DMA starts on an odd CPU cycle.
+        self.pending_stall += 513;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bus() -> Bus {
+        Bus::with_audio_output(Cartridge::nrom(vec![0; 16384], vec![0; 8192]), SampleSink::Discard)
+    }
+
+    #[test]
+    fn test_dmc_fetch_during_oam_dma_shares_the_halt() {
+        let mut bus = bus();
+        assert_eq!(bus.calculate_dma_stall(0), 0);
+        bus.dmc_fetch_pending = true;
+        assert_eq!(bus.calculate_dma_stall(0), 4);
+
+        bus.oam_dma_in_progress = true;
+        // (OAM DMA length, cycles of it left when the DMC fetches, DMC cost).
+        // 513 cycles when the DMA starts on an even CPU cycle, 514 on an odd one.
+        let cases = [
+            (513, 513, 2),
+            (514, 514, 1),
+            (514, 513, 1),
+            (513, 512, 2),
+            (514, 512, 2),
+            (513, 300, 2),
+            (514, 300, 2),
+            (513, 2, 1),
+            (514, 2, 1),
+            (513, 1, 3),
+            (514, 1, 3),
+        ];
+        for (oam_length, left, expected) in cases {
+            bus.oam_dma_length = oam_length;
+            assert_eq!(bus.calculate_dma_stall(left), expected, "{} of {} OAM cycles left", left, oam_length);
+        }
+        // The OAM DMA has finished but `Nes` hasn't cleared the flag yet
+        assert_eq!(bus.calculate_dma_stall(0), 4);
+    }
+
+    #[test]
+    fn test_unmapped_reads_return_open_bus() {
+        let mut bus = bus();
+        bus.ram[0x10] = 0x5A;
+        assert_eq!(bus.cpu_read(0x0010), 0x5A);
+        for addr in [0x4001, 0x4003, 0x4014, 0x4018, 0x401F] {
+            assert_eq!(bus.cpu_read(addr), 0x5A, "${:04X}", addr);
+        }
+
+        bus.cpu_write(0x0300, 0xC3);
+        assert_eq!(bus.cpu_read(0x4018), 0xC3);
+        assert_eq!(bus.peek(0x4018), 0xC3);
+    }
+
+    #[test]
+    fn test_ppudata_writes_only_change_chr_ram() {
+        for chr_is_ram in [false, true] {
+            let mut cartridge = Cartridge::nrom(vec![0; 32768], vec![0x11; 8192]);
+            cartridge.mapper_id = 1;
+            cartridge.chr_is_ram = chr_is_ram;
+            let mut bus = Bus::with_audio_output(cartridge, SampleSink::Discard);
+            bus.cpu_write(0x2006, 0x01);
+            bus.cpu_write(0x2006, 0x23);
+            bus.cpu_write(0x2007, 0xAB);
+            let expected = if chr_is_ram { 0xAB } else { 0x11 };
+            assert_eq!(bus.ppu.chr_rom[0x0123], expected, "chr_is_ram: {}", chr_is_ram);
+        }
+    }
+
+    #[test]
+    fn test_power_on_ram_modes() {
+        let ram = PowerOnMode::Zero.ram();
+        assert_eq!((ram[0], ram[8], ram[2047]), (0x00, 0x00, 0x00));
+
+        let ram = PowerOnMode::HardwarePattern.ram();
+        assert_eq!((ram[0], ram[8], ram[2047]), (0xFF, 0x00, 0x00));
+        assert_eq!(ram[16..24], [0xFF; 8]);
+
+        let ram = PowerOnMode::Random(1).ram();
+        // First xorshift64 output from a seed of 1 is $40822041
+        assert_eq!(ram[0..8], 0x4082_2041u64.to_le_bytes());
+        assert_ne!(ram[8..16], ram[0..8]);
+        assert_eq!(ram, PowerOnMode::Random(1).ram());
+        assert_ne!(ram, PowerOnMode::Random(2).ram());
+        assert_ne!(PowerOnMode::Random(0).ram(), PowerOnMode::Zero.ram());
     }
 }
@@ -0,0 +1,114 @@
+//! IPS patch application.
+//!
+//! An IPS file is "PATCH", a list of records, then "EOF". Each record is a
+//! 3-byte big-endian offset and a 2-byte size followed by that many bytes; a
+//! size of zero marks an RLE record (2-byte count, 1 fill byte). Some patches
+//! append a 3-byte length after "EOF" to truncate the output.
+
+use std::fmt;
+
+const HEADER: &[u8] = b"PATCH";
+const EOF_MARKER: [u8; 3] = *b"EOF";
+
+#[derive(Debug, PartialEq)]
+pub enum IpsError {
+    InvalidHeader,
+    /// The patch ended in the middle of a record, at this patch offset.
+    UnexpectedEnd(usize),
+}
+
+impl fmt::Display for IpsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpsError::InvalidHeader => write!(f, "Invalid IPS patch (missing PATCH header)"),
+            IpsError::UnexpectedEnd(at) => write!(f, "IPS patch is truncated at offset {}", at),
+        }
+    }
+}
+
+impl std::error::Error for IpsError {}
+
+fn take<'a>(patch: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], IpsError> {
+    let bytes = patch.get(*pos..*pos + len).ok_or(IpsError::UnexpectedEnd(*pos))?;
+    *pos += len;
+    Ok(bytes)
+}
+
+/// Apply an IPS patch to `rom`, returning the patched image. Records past the
+/// end of the ROM grow it, zero-filling any gap.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, IpsError> {
+    if !patch.starts_with(HEADER) {
+        return Err(IpsError::InvalidHeader);
+    }
+
+    let mut out = rom.to_vec();
+    let mut pos = HEADER.len();
+    loop {
+        let offset = take(patch, &mut pos, 3)?;
+        if offset == EOF_MARKER {
+            break;
+        }
+        let offset = (offset[0] as usize) << 16 | (offset[1] as usize) << 8 | offset[2] as usize;
+        let size = take(patch, &mut pos, 2)?;
+        let size = (size[0] as usize) << 8 | size[1] as usize;
+
+        if size == 0 {
+            let rle = take(patch, &mut pos, 3)?;
+            let count = (rle[0] as usize) << 8 | rle[1] as usize;
+            write_at(&mut out, offset, &vec![rle[2]; count]);
+        } else {
+            let data = take(patch, &mut pos, size)?;
+            write_at(&mut out, offset, data);
+        }
+    }
+
+    if let Some(len) = patch.get(pos..pos + 3) {
+        out.truncate((len[0] as usize) << 16 | (len[1] as usize) << 8 | len[2] as usize);
+    }
+    Ok(out)
+}
+
+fn write_at(out: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    let end = offset + data.len();
+    if out.len() < end {
+        out.resize(end, 0);
+    }
+    out[offset..end].copy_from_slice(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_records() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        // RLE: 3 x $CC at offset 4
+        patch.extend_from_slice(&[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend_from_slice(b"EOF");
+        let out = apply(&[0u8; 8], &patch).unwrap();
+        assert_eq!(out, [0x00, 0xAA, 0xBB, 0x00, 0xCC, 0xCC, 0xCC, 0x00]);
+    }
+
+    #[test]
+    fn test_apply_grows_and_truncates() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x05, 0x00, 0x01, 0x11]);
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(apply(&[0xFF; 2], &patch).unwrap(), [0xFF, 0xFF, 0, 0, 0, 0x11]);
+
+        patch.extend_from_slice(&[0x00, 0x00, 0x03]);
+        assert_eq!(apply(&[0xFF; 2], &patch).unwrap(), [0xFF, 0xFF, 0]);
+    }
+
+    #[test]
+    fn test_apply_rejects_bad_patches() {
+        assert_eq!(apply(&[0; 4], b"PACTH"), Err(IpsError::InvalidHeader));
+        assert_eq!(
+            apply(&[0; 4], b"PATCH\x00\x00\x01\x00\x04\xAA"),
+            Err(IpsError::UnexpectedEnd(10))
+        );
+        assert_eq!(apply(&[0; 4], b"PATCH"), Err(IpsError::UnexpectedEnd(5)));
+    }
+}
@@ -1,12 +1,104 @@
 use super::Mirroring;
 
-pub trait Mapper {
+/// Mapper IDs `Cartridge::from_ines` accepts.
+pub const SUPPORTED_MAPPERS: &[u16] = &[0, 1, 2, 3, 4, 7, 66];
+
+/// Unbanked CHR: each 1KB slot of $0000-$1FFF maps straight through.
+pub const DEFAULT_CHR_BANKS: [usize; 8] = [0, 0x400, 0x800, 0xC00, 0x1000, 0x1400, 0x1800, 0x1C00];
+
+/// `Send` so a `Nes` can be moved to another thread.
+pub trait Mapper: Send {
     fn cpu_read(&self, addr: u16) -> u8;
     fn cpu_write(&mut self, addr: u16, val: u8);
+
+    /// A CPU write on the cycle right after another write, like the result
+    /// write of a read-modify-write instruction. Most boards can't tell.
+    fn cpu_write_after_write(&mut self, addr: u16, val: u8) {
+        self.cpu_write(addr, val);
+    }
+
     fn chr_read(&self, addr: u16) -> u8;
     fn chr_write(&mut self, addr: u16, val: u8);
     fn mirroring(&self) -> Mirroring;
     fn clone_box(&self) -> Box<dyn Mapper>;
+
+    /// Snapshot for save states, tagged with the concrete mapper type.
+    #[cfg(feature = "save_states")]
+    fn state(&self) -> MapperState;
+
+    /// Offset into CHR memory of each 1KB slot of PPU $0000-$1FFF. The PPU keeps
+    /// its own copy of CHR and reads it through these banks.
+    fn chr_banks(&self) -> [usize; 8] {
+        DEFAULT_CHR_BANKS
+    }
+
+    /// Called by the bus when PPU address line A12 rises during rendering
+    /// (once per scanline with the usual pattern table layout).
+    fn notify_a12(&mut self) {}
+
+    /// True while the mapper is holding the CPU's IRQ line.
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// Work RAM at $6000-$7FFF, for boards that have it.
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        None
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        None
+    }
+
+    /// Battery-backed PRG RAM to persist between sessions, if the cartridge has any.
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.prg_ram().filter(|ram| ram.battery).map(|ram| &ram.data[..])
+    }
+
+    /// Restore battery-backed PRG RAM from a save file. Extra bytes are ignored.
+    fn load_ram(&mut self, data: &[u8]) {
+        if let Some(ram) = self.prg_ram_mut() {
+            let len = data.len().min(ram.data.len());
+            ram.data[..len].copy_from_slice(&data[..len]);
+        }
+    }
+}
+
+/// Cartridge work RAM at $6000-$7FFF. Smaller chips are mirrored through
+/// the window; only the first 8KB of larger ones is reachable, since none of
+/// the supported mappers bank PRG RAM.
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrgRam {
+    data: Vec<u8>,
+    /// Kept between sessions through `Mapper::save_ram`.
+    pub battery: bool,
+}
+
+impl Default for PrgRam {
+    fn default() -> Self {
+        Self::new(0x2000, false)
+    }
+}
+
+impl PrgRam {
+    pub fn new(size: usize, battery: bool) -> Self {
+        PrgRam { data: vec![0; size], battery }
+    }
+
+    /// Reads with no RAM fitted return 0, like other unmapped cartridge space.
+    pub fn read(&self, addr: u16) -> u8 {
+        match self.data.len() {
+            0 => 0,
+            len => self.data[(addr - 0x6000) as usize % len],
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) {
+        if let len @ 1.. = self.data.len() {
+            self.data[(addr - 0x6000) as usize % len] = val;
+        }
+    }
 }
 
 impl Clone for Box<dyn Mapper> {
@@ -15,15 +107,59 @@ impl Clone for Box<dyn Mapper> {
     }
 }
 
+/// A mapper by concrete type, so a save state knows what to rebuild.
+#[cfg(feature = "save_states")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum MapperState {
+    Nrom(Mapper0),
+    Mmc1(Mapper1),
+    Uxrom(Mapper2),
+    Cnrom(Mapper3),
+    Mmc3(Mapper4),
+    Axrom(Mapper7),
+    Gxrom(Mapper66),
+}
+
+#[cfg(feature = "save_states")]
+impl MapperState {
+    pub fn into_mapper(self) -> Box<dyn Mapper> {
+        match self {
+            MapperState::Nrom(m) => Box::new(m),
+            MapperState::Mmc1(m) => Box::new(m),
+            MapperState::Uxrom(m) => Box::new(m),
+            MapperState::Cnrom(m) => Box::new(m),
+            MapperState::Mmc3(m) => Box::new(m),
+            MapperState::Axrom(m) => Box::new(m),
+            MapperState::Gxrom(m) => Box::new(m),
+        }
+    }
+}
+
+#[cfg(feature = "save_states")]
+impl serde::Serialize for dyn Mapper {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.state().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "save_states")]
+impl<'de> serde::Deserialize<'de> for Box<dyn Mapper> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        MapperState::deserialize(deserializer).map(MapperState::into_mapper)
+    }
+}
+
 /// Mapper 0 (NROM): No bank switching.
 /// NROM-128: 16KB PRG ROM mirrored at $8000 and $C000.
 /// NROM-256: 32KB PRG ROM at $8000-$FFFF.
 /// 8KB CHR ROM (or CHR RAM) at PPU $0000-$1FFF.
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub struct Mapper0 {
     prg_rom: Vec<u8>,
     chr: Vec<u8>,
     mirroring: Mirroring,
-    prg_ram: [u8; 8192],
+    prg_ram: PrgRam,
 }
 
 impl Mapper0 {
@@ -32,7 +168,7 @@ impl Mapper0 {
             prg_rom,
             chr,
             mirroring,
-            prg_ram: [0; 8192],
+            prg_ram: PrgRam::default(),
         }
     }
 }
@@ -40,7 +176,7 @@ impl Mapper0 {
 impl Mapper for Mapper0 {
     fn cpu_read(&self, addr: u16) -> u8 {
         match addr {
-            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x6000..=0x7FFF => self.prg_ram.read(addr),
             0x8000..=0xFFFF => {
                 let mut index = (addr - 0x8000) as usize;
                 if self.prg_rom.len() == 16384 {
@@ -54,7 +190,7 @@ impl Mapper for Mapper0 {
 
     fn cpu_write(&mut self, addr: u16, val: u8) {
         if let 0x6000..=0x7FFF = addr {
-            self.prg_ram[(addr - 0x6000) as usize] = val;
+            self.prg_ram.write(addr, val);
         }
     }
 
@@ -72,12 +208,637 @@ impl Mapper for Mapper0 {
     }
 
     fn clone_box(&self) -> Box<dyn Mapper> {
-        Box::new(Mapper0 {
-            prg_rom: self.prg_rom.clone(),
-            chr: self.chr.clone(),
-            mirroring: self.mirroring,
-            prg_ram: self.prg_ram,
-        })
+        Box::new(self.clone())
+    }
+
+    #[cfg(feature = "save_states")]
+    fn state(&self) -> MapperState {
+        MapperState::Nrom(self.clone())
+    }
+
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+/// Mapper 1 (SxROM/MMC1): registers are loaded serially, one bit per write to
+/// $8000-$FFFF. PRG is switched in 16KB or 32KB banks, CHR in 4KB or 8KB banks,
+/// and mirroring is under software control.
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper1 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    prg_ram: PrgRam,
+    shift: u8,
+    shift_count: u8,
+    // Bits 0-1: mirroring, 2-3: PRG bank mode, 4: CHR bank mode
+    control: u8,
+    chr_bank0: u8,
+    chr_bank1: u8,
+    // Bits 0-3: PRG bank, 4: PRG RAM disable
+    prg_bank: u8,
+    /// The board has 8KB of CHR RAM instead of CHR ROM: writable, and unbanked.
+    chr_is_ram: bool,
+}
+
+impl Mapper1 {
+    pub fn new(prg_rom: Vec<u8>, chr: Vec<u8>, chr_is_ram: bool) -> Self {
+        Mapper1 {
+            prg_rom,
+            chr: if chr_is_ram { vec![0; 0x2000] } else { chr },
+            prg_ram: PrgRam::default(),
+            shift: 0,
+            shift_count: 0,
+            control: 0x0C, // power on with the last PRG bank fixed at $C000
+            chr_bank0: 0,
+            chr_bank1: 0,
+            prg_bank: 0,
+            chr_is_ram,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = (self.prg_rom.len() / 0x4000).max(1);
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let upper = addr >= 0xC000;
+        let bank = match (self.control >> 2) & 0x03 {
+            // 32KB mode: low bit of the bank number is ignored
+            0 | 1 => (bank & !1) + upper as usize,
+            // Fix the first bank at $8000, switch $C000
+            2 => if upper { bank } else { 0 },
+            // Switch $8000, fix the last bank at $C000
+            _ => if upper { bank_count - 1 } else { bank },
+        };
+        (bank % bank_count) * 0x4000 + (addr as usize & 0x3FFF)
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.control = val,
+            // Some CHR RAM games write the CHR banks anyway; there's nothing to switch
+            0xA000..=0xDFFF if self.chr_is_ram => {}
+            0xA000..=0xBFFF => self.chr_bank0 = val,
+            0xC000..=0xDFFF => self.chr_bank1 = val,
+            _ => self.prg_bank = val,
+        }
+    }
+}
+
+impl Mapper for Mapper1 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram.read(addr),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram.write(addr, val),
+            0x8000..=0xFFFF if val & 0x80 != 0 => {
+                // Reset the shift register and go back to PRG mode 3
+                self.shift = 0;
+                self.shift_count = 0;
+                self.control |= 0x0C;
+            }
+            0x8000..=0xFFFF => {
+                self.shift |= (val & 1) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    // The register is chosen by the address of the fifth write
+                    self.write_register(addr, self.shift);
+                    self.shift = 0;
+                    self.shift_count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The serial port only takes the first of back-to-back writes, so an RMW
+    // instruction's dummy write of the old value shifts in a bit and the
+    // result is dropped
+    fn cpu_write_after_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x8000 {
+            self.cpu_write(addr, val);
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        let banks = self.chr_banks();
+        self.chr[banks[addr as usize / 0x400] + (addr as usize & 0x3FF)]
+    }
+
+    fn chr_write(&mut self, addr: u16, val: u8) {
+        if self.chr_is_ram {
+            self.chr[addr as usize & 0x1FFF] = val;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::SingleScreenA,
+            1 => Mirroring::SingleScreenB,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    #[cfg(feature = "save_states")]
+    fn state(&self) -> MapperState {
+        MapperState::Mmc1(self.clone())
+    }
+
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn chr_banks(&self) -> [usize; 8] {
+        if self.chr_is_ram {
+            return DEFAULT_CHR_BANKS;
+        }
+        let bank_count = (self.chr.len() / 0x1000).max(1);
+        // 4KB bank numbers for $0000 and $1000; 8KB mode ignores the low bit
+        let (lower, upper) = if self.control & 0x10 != 0 {
+            (self.chr_bank0 as usize, self.chr_bank1 as usize)
+        } else {
+            let bank = self.chr_bank0 as usize & !1;
+            (bank, bank + 1)
+        };
+        let mut banks = [0; 8];
+        for (slot, bank) in banks.iter_mut().enumerate() {
+            let bank_4k = if slot < 4 { lower } else { upper };
+            *bank = (bank_4k % bank_count) * 0x1000 + (slot % 4) * 0x400;
+        }
+        banks
+    }
+}
+
+/// Mapper 2 (UxROM): a switchable 16KB PRG bank at $8000 selected by any write
+/// to $8000-$FFFF, the last 16KB bank fixed at $C000, and 8KB of CHR RAM.
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper2 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    mirroring: Mirroring,
+    bank: u8,
+    bus_conflicts: bool,
+}
+
+impl Mapper2 {
+    pub fn new(prg_rom: Vec<u8>, chr: Vec<u8>, mirroring: Mirroring) -> Self {
+        Mapper2 {
+            prg_rom,
+            chr,
+            mirroring,
+            bank: 0,
+            bus_conflicts: false,
+        }
+    }
+
+    /// Emulate bus conflicts: the ROM drives the data bus during the write, so
+    /// the latched value is the written value ANDed with the ROM byte there.
+    pub fn with_bus_conflicts(mut self, enabled: bool) -> Self {
+        self.bus_conflicts = enabled;
+        self
+    }
+
+    fn bank_count(&self) -> usize {
+        (self.prg_rom.len() / 0x4000).max(1)
+    }
+}
+
+impl Mapper for Mapper2 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.bank as usize % self.bank_count(),
+            0xC000..=0xFFFF => self.bank_count() - 1,
+            _ => return 0,
+        };
+        self.prg_rom[bank * 0x4000 + (addr as usize & 0x3FFF)]
+    }
+
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if addr >= 0x8000 {
+            self.bank = if self.bus_conflicts { val & self.cpu_read(addr) } else { val };
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        self.chr[addr as usize]
+    }
+
+    fn chr_write(&mut self, addr: u16, val: u8) {
+        self.chr[addr as usize] = val;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clone_box(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    #[cfg(feature = "save_states")]
+    fn state(&self) -> MapperState {
+        MapperState::Uxrom(self.clone())
+    }
+}
+
+/// Mapper 3 (CNROM): fixed 16KB or 32KB PRG like NROM, with the 8KB CHR ROM
+/// bank selected by any write to $8000-$FFFF. Writes have bus conflicts.
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper3 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    mirroring: Mirroring,
+    chr_bank: u8,
+}
+
+impl Mapper3 {
+    pub fn new(prg_rom: Vec<u8>, chr: Vec<u8>, mirroring: Mirroring) -> Self {
+        Mapper3 {
+            prg_rom,
+            chr,
+            mirroring,
+            chr_bank: 0,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_banks()[addr as usize / 0x400] + (addr as usize & 0x3FF)
+    }
+}
+
+impl Mapper for Mapper3 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => self.prg_rom[(addr - 0x8000) as usize % self.prg_rom.len()],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if addr >= 0x8000 {
+            // Bus conflict: the ROM byte at the address is driven at the same time
+            self.chr_bank = val & self.cpu_read(addr);
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, val: u8) {
+        let offset = self.chr_offset(addr);
+        self.chr[offset] = val;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clone_box(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    #[cfg(feature = "save_states")]
+    fn state(&self) -> MapperState {
+        MapperState::Cnrom(self.clone())
+    }
+
+    fn chr_banks(&self) -> [usize; 8] {
+        // Boards with fewer banks don't decode the upper bits of the register
+        let mask = (self.chr.len() / 0x2000).max(1) - 1;
+        let base = (self.chr_bank as usize & mask) * 0x2000;
+        DEFAULT_CHR_BANKS.map(|offset| base + offset)
+    }
+}
+
+/// Mapper 4 (TxROM/MMC3): eight bank registers selected through $8000, 8KB PRG
+/// and 1KB/2KB CHR banking with inversion flags, software mirroring, and a
+/// scanline counter clocked by PPU A12 that raises an IRQ when it hits zero.
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper4 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    prg_ram: PrgRam,
+    four_screen: bool,
+    // Bits 0-2: register to update, 6: PRG inversion, 7: CHR inversion
+    bank_select: u8,
+    registers: [u8; 8],
+    horizontal: bool,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl Mapper4 {
+    pub fn new(prg_rom: Vec<u8>, chr: Vec<u8>, mirroring: Mirroring) -> Self {
+        Mapper4 {
+            prg_rom,
+            chr,
+            prg_ram: PrgRam::default(),
+            four_screen: mirroring == Mirroring::FourScreen,
+            bank_select: 0,
+            registers: [0; 8],
+            horizontal: mirroring == Mirroring::Horizontal,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = (self.prg_rom.len() / 0x2000).max(2);
+        let second_last = bank_count - 2;
+        let inverted = self.bank_select & 0x40 != 0;
+        let bank = match (addr - 0x8000) / 0x2000 {
+            0 if inverted => second_last,
+            0 => self.registers[6] as usize,
+            1 => self.registers[7] as usize,
+            2 if inverted => self.registers[6] as usize,
+            2 => second_last,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr as usize & 0x1FFF)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_banks()[addr as usize / 0x400] + (addr as usize & 0x3FF)
+    }
+}
+
+impl Mapper for Mapper4 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram.read(addr),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        let even = addr & 1 == 0;
+        match addr {
+            // PRG RAM protect ($A001) isn't emulated; RAM is always enabled, as MMC6 games expect
+            0x6000..=0x7FFF => self.prg_ram.write(addr, val),
+            0x8000..=0x9FFF if even => self.bank_select = val,
+            0x8000..=0x9FFF => self.registers[(self.bank_select & 0x07) as usize] = val,
+            0xA000..=0xBFFF if even => self.horizontal = val & 1 != 0,
+            0xC000..=0xDFFF if even => self.irq_latch = val,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false; // acknowledge
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, val: u8) {
+        let offset = self.chr_offset(addr);
+        self.chr[offset] = val;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.four_screen {
+            Mirroring::FourScreen
+        } else if self.horizontal {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    #[cfg(feature = "save_states")]
+    fn state(&self) -> MapperState {
+        MapperState::Mmc3(self.clone())
+    }
+
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn chr_banks(&self) -> [usize; 8] {
+        let r = self.registers.map(|bank| bank as usize);
+        // R0/R1 select 2KB banks (low bit ignored), R2-R5 1KB banks
+        let two_k = [r[0] & !1, r[0] | 1, r[1] & !1, r[1] | 1];
+        let one_k = [r[2], r[3], r[4], r[5]];
+        let (low, high) = if self.bank_select & 0x80 != 0 { (one_k, two_k) } else { (two_k, one_k) };
+
+        let bank_count = (self.chr.len() / 0x400).max(1);
+        let mut banks = [0; 8];
+        for (slot, bank) in low.iter().chain(high.iter()).enumerate() {
+            banks[slot] = (bank % bank_count) * 0x400;
+        }
+        banks
+    }
+
+    fn notify_a12(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+/// Mapper 7 (AxROM): the whole of $8000-$FFFF is one switchable 32KB PRG bank.
+/// A write to $8000-$FFFF selects the bank (bits 0-2) and which 1KB of VRAM
+/// the single-screen nametable uses (bit 4). CHR is 8KB of RAM.
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper7 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    bank: u8,
+    mirroring: Mirroring,
+}
+
+impl Mapper7 {
+    pub fn new(prg_rom: Vec<u8>, chr: Vec<u8>) -> Self {
+        Mapper7 {
+            prg_rom,
+            chr,
+            bank: 0,
+            mirroring: Mirroring::SingleScreenA,
+        }
+    }
+}
+
+impl Mapper for Mapper7 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => {
+                let bank_count = (self.prg_rom.len() / 0x8000).max(1);
+                let bank = self.bank as usize % bank_count;
+                self.prg_rom[(bank * 0x8000 + (addr - 0x8000) as usize) % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if addr >= 0x8000 {
+            self.bank = val & 0x07;
+            self.mirroring = if val & 0x10 != 0 {
+                Mirroring::SingleScreenB
+            } else {
+                Mirroring::SingleScreenA
+            };
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        self.chr[addr as usize]
+    }
+
+    fn chr_write(&mut self, addr: u16, val: u8) {
+        self.chr[addr as usize] = val;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clone_box(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    #[cfg(feature = "save_states")]
+    fn state(&self) -> MapperState {
+        MapperState::Axrom(self.clone())
+    }
+}
+
+/// Mapper 66 (GxROM): one register at $8000-$FFFF selects a 32KB PRG bank
+/// (bits 4-5) and an 8KB CHR bank (bits 0-1). Mirroring is fixed by the
+/// board. Writes have bus conflicts.
+#[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper66 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    mirroring: Mirroring,
+    prg_bank: u8,
+    chr_bank: u8,
+}
+
+impl Mapper66 {
+    pub fn new(prg_rom: Vec<u8>, chr: Vec<u8>, mirroring: Mirroring) -> Self {
+        Mapper66 {
+            prg_rom,
+            chr,
+            mirroring,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_banks()[addr as usize / 0x400] + (addr as usize & 0x3FF)
+    }
+}
+
+impl Mapper for Mapper66 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => {
+                // Undersized ROMs wrap rather than index past the end
+                let bank_count = (self.prg_rom.len() / 0x8000).max(1);
+                let bank = self.prg_bank as usize % bank_count;
+                self.prg_rom[(bank * 0x8000 + (addr - 0x8000) as usize) % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if addr >= 0x8000 {
+            // Bus conflict: the ROM byte at the address is driven at the same time
+            let val = val & self.cpu_read(addr);
+            self.prg_bank = (val >> 4) & 0x03;
+            self.chr_bank = val & 0x03;
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, val: u8) {
+        let offset = self.chr_offset(addr);
+        self.chr[offset] = val;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clone_box(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    #[cfg(feature = "save_states")]
+    fn state(&self) -> MapperState {
+        MapperState::Gxrom(self.clone())
+    }
+
+    fn chr_banks(&self) -> [usize; 8] {
+        let bank_count = (self.chr.len() / 0x2000).max(1);
+        let base = (self.chr_bank as usize % bank_count) * 0x2000;
+        DEFAULT_CHR_BANKS.map(|offset| base + offset)
     }
 }
 
@@ -122,6 +883,24 @@ mod tests {
         assert_eq!(mapper.cpu_read(0x6000), 0x42);
     }
 
+    #[test]
+    fn test_mapper0_save_ram() {
+        let mapper = Mapper0::new(vec![0u8; 16384], vec![0u8; 8192], Mirroring::Horizontal);
+        assert!(mapper.save_ram().is_none());
+
+        let mut mapper = Mapper0::new(vec![0u8; 16384], vec![0u8; 8192], Mirroring::Horizontal);
+        mapper.prg_ram.battery = true;
+        mapper.cpu_write(0x6123, 0x42);
+        let saved = mapper.save_ram().unwrap().to_vec();
+        assert_eq!(saved.len(), 8192);
+        assert_eq!(saved[0x123], 0x42);
+
+        let mut restored = Mapper0::new(vec![0u8; 16384], vec![0u8; 8192], Mirroring::Horizontal);
+        restored.prg_ram.battery = true;
+        restored.load_ram(&saved[..0x200]); // short files only fill the start
+        assert_eq!(restored.cpu_read(0x6123), 0x42);
+    }
+
     #[test]
     fn test_mapper0_chr() {
         let prg = vec![0u8; 16384];
@@ -131,4 +910,438 @@ mod tests {
 
         assert_eq!(mapper.chr_read(0x100), 0xFF);
     }
+
+    /// 16KB PRG banks each filled with their bank number; 4KB CHR banks likewise.
+    fn numbered_mmc1() -> Mapper1 {
+        let prg = (0..8u8).flat_map(|bank| vec![bank; 0x4000]).collect();
+        let chr = (0..8u8).flat_map(|bank| vec![bank; 0x1000]).collect();
+        Mapper1::new(prg, chr, false)
+    }
+
+    fn mmc1_write(mapper: &mut Mapper1, addr: u16, val: u8) {
+        for bit in 0..5 {
+            mapper.cpu_write(addr, (val >> bit) & 1);
+        }
+    }
+
+    #[test]
+    fn test_mapper1_prg_mode_3_fixes_last_bank() {
+        let mut mapper = numbered_mmc1();
+        mmc1_write(&mut mapper, 0xE000, 2);
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+        assert_eq!(mapper.cpu_read(0xC000), 7);
+    }
+
+    #[test]
+    fn test_mapper1_prg_mode_2_fixes_first_bank() {
+        let mut mapper = numbered_mmc1();
+        mmc1_write(&mut mapper, 0x8000, 0x08);
+        mmc1_write(&mut mapper, 0xE000, 5);
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        assert_eq!(mapper.cpu_read(0xFFFF), 5);
+    }
+
+    #[test]
+    fn test_mapper1_prg_32k_mode_ignores_low_bit() {
+        let mut mapper = numbered_mmc1();
+        mmc1_write(&mut mapper, 0x8000, 0x00);
+        mmc1_write(&mut mapper, 0xE000, 5);
+        assert_eq!(mapper.cpu_read(0x8000), 4);
+        assert_eq!(mapper.cpu_read(0xC000), 5);
+    }
+
+    fn mmc1_prg_mode(mapper: &mut Mapper1, mode: u8) {
+        mmc1_write(mapper, 0x8000, mode << 2);
+    }
+
+    /// The bytes at the start and end of the $8000 and $C000 windows.
+    fn mmc1_prg_windows(mapper: &Mapper1) -> [u8; 4] {
+        [0x8000, 0xBFFF, 0xC000, 0xFFFF].map(|addr| mapper.cpu_read(addr))
+    }
+
+    #[test]
+    fn test_mapper1_prg_mode_0_switches_32k() {
+        let mut mapper = numbered_mmc1();
+        mmc1_prg_mode(&mut mapper, 0);
+        for (bank, lower) in [(0, 0), (2, 2), (3, 2), (6, 6), (7, 6)] {
+            mmc1_write(&mut mapper, 0xE000, bank);
+            assert_eq!(mmc1_prg_windows(&mapper), [lower, lower, lower + 1, lower + 1], "bank {}", bank);
+        }
+    }
+
+    #[test]
+    fn test_mapper1_prg_mode_0_ignores_ram_disable_bit() {
+        let mut mapper = numbered_mmc1();
+        mmc1_prg_mode(&mut mapper, 0);
+        mmc1_write(&mut mapper, 0xE000, 0x15);
+        assert_eq!(mmc1_prg_windows(&mapper), [4, 4, 5, 5]);
+        assert_eq!(mapper.cpu_read(0x6000), 0);
+    }
+
+    #[test]
+    fn test_mapper1_prg_mode_1_matches_mode_0() {
+        let mut mode_0 = numbered_mmc1();
+        let mut mode_1 = numbered_mmc1();
+        mmc1_prg_mode(&mut mode_0, 0);
+        mmc1_prg_mode(&mut mode_1, 1);
+        for bank in 0..16 {
+            mmc1_write(&mut mode_0, 0xE000, bank);
+            mmc1_write(&mut mode_1, 0xE000, bank);
+            assert_eq!(mmc1_prg_windows(&mode_1), mmc1_prg_windows(&mode_0), "bank {}", bank);
+        }
+    }
+
+    #[test]
+    fn test_mapper1_prg_mode_1_wraps_small_roms() {
+        // 64KB: four 16KB banks, so 32KB bank numbers wrap every two
+        let prg = (0..4u8).flat_map(|bank| vec![bank; 0x4000]).collect();
+        let mut mapper = Mapper1::new(prg, vec![0; 0x2000], true);
+        mmc1_prg_mode(&mut mapper, 1);
+        mmc1_write(&mut mapper, 0xE000, 7);
+        assert_eq!(mmc1_prg_windows(&mapper), [2, 2, 3, 3]);
+        mmc1_write(&mut mapper, 0xE000, 9);
+        assert_eq!(mmc1_prg_windows(&mapper), [0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_mapper1_prg_mode_2_switches_upper_bank() {
+        let mut mapper = numbered_mmc1();
+        mmc1_prg_mode(&mut mapper, 2);
+        for bank in [1, 4, 7, 0] {
+            mmc1_write(&mut mapper, 0xE000, bank);
+            assert_eq!(mmc1_prg_windows(&mapper), [0, 0, bank, bank]);
+        }
+    }
+
+    #[test]
+    fn test_mapper1_prg_mode_2_applies_to_earlier_bank_write() {
+        let mut mapper = numbered_mmc1();
+        mmc1_write(&mut mapper, 0xE000, 5);
+        assert_eq!(mmc1_prg_windows(&mapper), [5, 5, 7, 7]);
+        mmc1_prg_mode(&mut mapper, 2);
+        assert_eq!(mmc1_prg_windows(&mapper), [0, 0, 5, 5]);
+    }
+
+    #[test]
+    fn test_mapper1_prg_mode_3_at_power_on() {
+        let mut mapper = numbered_mmc1();
+        assert_eq!(mmc1_prg_windows(&mapper), [0, 0, 7, 7]);
+        for bank in [3, 6] {
+            mmc1_write(&mut mapper, 0xE000, bank);
+            assert_eq!(mmc1_prg_windows(&mapper), [bank, bank, 7, 7]);
+        }
+    }
+
+    #[test]
+    fn test_mapper1_prg_mode_3_restored_by_reset_bit() {
+        let mut mapper = numbered_mmc1();
+        mmc1_prg_mode(&mut mapper, 0);
+        mmc1_write(&mut mapper, 0xE000, 2);
+        assert_eq!(mmc1_prg_windows(&mapper), [2, 2, 3, 3]);
+        mapper.cpu_write(0x8000, 0x80);
+        assert_eq!(mmc1_prg_windows(&mapper), [2, 2, 7, 7]);
+    }
+
+    #[test]
+    fn test_mapper1_chr_8k_and_4k_modes() {
+        let mut mapper = numbered_mmc1();
+        // 8KB mode: bank 3 is treated as 2, covering 4KB banks 2 and 3
+        mmc1_write(&mut mapper, 0xA000, 3);
+        assert_eq!(mapper.chr_read(0x0000), 2);
+        assert_eq!(mapper.chr_read(0x1000), 3);
+
+        mmc1_write(&mut mapper, 0x8000, 0x1C);
+        mmc1_write(&mut mapper, 0xA000, 5);
+        mmc1_write(&mut mapper, 0xC000, 1);
+        assert_eq!(mapper.chr_read(0x0FFF), 5);
+        assert_eq!(mapper.chr_read(0x1000), 1);
+        assert_eq!(mapper.chr_banks()[4], 0x1000);
+    }
+
+    #[test]
+    fn test_mapper1_chr_ram() {
+        let prg = vec![0; 0x8000];
+        let mut mapper = Mapper1::new(prg, vec![0; 0x2000], true);
+        mapper.chr_write(0x0123, 0x42);
+        mapper.chr_write(0x1FFF, 0x24);
+        assert_eq!(mapper.chr_read(0x0123), 0x42);
+        assert_eq!(mapper.chr_read(0x1FFF), 0x24);
+
+        // Bank writes, in 4KB mode too, leave the 8KB of RAM mapped straight through
+        mmc1_write(&mut mapper, 0x8000, 0x1C);
+        mmc1_write(&mut mapper, 0xA000, 1);
+        mmc1_write(&mut mapper, 0xC000, 0);
+        assert_eq!(mapper.chr_banks(), DEFAULT_CHR_BANKS);
+        assert_eq!(mapper.chr_read(0x0123), 0x42);
+    }
+
+    #[test]
+    fn test_mapper1_chr_rom_ignores_writes() {
+        let mut mapper = numbered_mmc1();
+        mapper.chr_write(0x0010, 0xFF);
+        assert_eq!(mapper.chr_read(0x0010), 0);
+
+        mmc1_write(&mut mapper, 0x8000, 0x1C);
+        mmc1_write(&mut mapper, 0xA000, 6);
+        assert_eq!(mapper.chr_read(0x0010), 6);
+        mapper.chr_write(0x0010, 0xFF);
+        assert_eq!(mapper.chr_read(0x0010), 6);
+    }
+
+    #[test]
+    fn test_mapper1_mirroring() {
+        let mut mapper = numbered_mmc1();
+        for (bits, mirroring) in [
+            (0, Mirroring::SingleScreenA),
+            (1, Mirroring::SingleScreenB),
+            (2, Mirroring::Vertical),
+            (3, Mirroring::Horizontal),
+        ] {
+            mmc1_write(&mut mapper, 0x8000, 0x0C | bits);
+            assert_eq!(mapper.mirroring(), mirroring);
+        }
+    }
+
+    #[test]
+    fn test_mapper1_reset_bit_clears_shift_register() {
+        let mut mapper = numbered_mmc1();
+        mapper.cpu_write(0xE000, 1);
+        mapper.cpu_write(0xE000, 1);
+        mapper.cpu_write(0x8000, 0x80);
+        mmc1_write(&mut mapper, 0xE000, 3);
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+    }
+
+    #[test]
+    fn test_mapper1_prg_ram_disable() {
+        let mut mapper = numbered_mmc1();
+        mapper.cpu_write(0x6000, 0x42);
+        assert_eq!(mapper.cpu_read(0x6000), 0x42);
+        mmc1_write(&mut mapper, 0xE000, 0x10);
+        assert_eq!(mapper.cpu_read(0x6000), 0);
+    }
+
+    #[test]
+    fn test_mapper2_switches_low_bank_and_fixes_last() {
+        let prg = (0..8u8).flat_map(|bank| vec![bank; 0x4000]).collect();
+        let mut mapper = Mapper2::new(prg, vec![0; 8192], Mirroring::Vertical);
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        for bank in [3, 6, 1] {
+            mapper.cpu_write(0xC123, bank);
+            assert_eq!(mapper.cpu_read(0x8000), bank);
+            assert_eq!(mapper.cpu_read(0xBFFF), bank);
+            assert_eq!(mapper.cpu_read(0xC000), 7);
+            assert_eq!(mapper.cpu_read(0xFFFF), 7);
+        }
+        // Bank numbers past the ROM wrap around
+        mapper.cpu_write(0x8000, 9);
+        assert_eq!(mapper.cpu_read(0x8000), 1);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_mapper2_bus_conflicts() {
+        let mut prg: Vec<u8> = (0..4u8).flat_map(|bank| vec![bank; 0x4000]).collect();
+        prg[0xFFF0] = 0x02;
+        let mut mapper = Mapper2::new(prg, vec![0; 8192], Mirroring::Horizontal).with_bus_conflicts(true);
+        mapper.cpu_write(0xFFF0, 0x03); // ROM holds $02 there
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+    }
+
+    #[test]
+    fn test_mapper3_chr_banks() {
+        for bank_count in [2u8, 4] {
+            let mut prg = vec![0xFF; 0x8000];
+            prg[0] = 0x00; // writes to $8000 conflict with $00
+            let chr = (0..bank_count).flat_map(|bank| vec![bank; 0x2000]).collect();
+            let mut mapper = Mapper3::new(prg, chr, Mirroring::Horizontal);
+
+            for bank in 0..bank_count {
+                mapper.cpu_write(0xC000, bank);
+                assert_eq!(mapper.chr_read(0x0000), bank);
+                assert_eq!(mapper.chr_read(0x1FFF), bank);
+                assert_eq!(mapper.chr_banks()[0], bank as usize * 0x2000);
+            }
+            // Unused register bits are masked off
+            mapper.cpu_write(0xC000, 0xFF);
+            assert_eq!(mapper.chr_read(0x0000), bank_count - 1);
+            // Bus conflict with the $00 at $8000 selects bank 0
+            mapper.cpu_write(0x8000, 0x03);
+            assert_eq!(mapper.chr_read(0x0000), 0);
+        }
+    }
+
+    /// 8KB PRG banks and 1KB CHR banks each filled with their bank number.
+    fn numbered_mmc3() -> Mapper4 {
+        let prg = (0..16u8).flat_map(|bank| vec![bank; 0x2000]).collect();
+        let chr = (0..32u8).flat_map(|bank| vec![bank; 0x400]).collect();
+        let mut mapper = Mapper4::new(prg, chr, Mirroring::Vertical);
+        for (register, bank) in [(0, 8), (1, 10), (2, 1), (3, 2), (4, 3), (5, 4), (6, 5), (7, 6)] {
+            mapper.cpu_write(0x8000, register);
+            mapper.cpu_write(0x8001, bank);
+        }
+        mapper
+    }
+
+    fn prg_layout(mapper: &Mapper4) -> [u8; 4] {
+        [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mapper.cpu_read(addr))
+    }
+
+    fn chr_layout(mapper: &Mapper4) -> [u8; 8] {
+        std::array::from_fn(|slot| mapper.chr_read(slot as u16 * 0x400))
+    }
+
+    #[test]
+    fn test_mapper4_prg_modes() {
+        let mut mapper = numbered_mmc3();
+        assert_eq!(prg_layout(&mapper), [5, 6, 14, 15]);
+        mapper.cpu_write(0x8000, 0x40);
+        assert_eq!(prg_layout(&mapper), [14, 6, 5, 15]);
+    }
+
+    #[test]
+    fn test_mapper4_chr_modes() {
+        let mut mapper = numbered_mmc3();
+        assert_eq!(chr_layout(&mapper), [8, 9, 10, 11, 1, 2, 3, 4]);
+        mapper.cpu_write(0x8000, 0x80);
+        assert_eq!(chr_layout(&mapper), [1, 2, 3, 4, 8, 9, 10, 11]);
+        // 2KB registers ignore the low bit
+        mapper.cpu_write(0x8000, 0x80);
+        mapper.cpu_write(0x8001, 13);
+        assert_eq!(mapper.chr_read(0x1000), 12);
+        assert_eq!(mapper.chr_read(0x1400), 13);
+    }
+
+    #[test]
+    fn test_mapper4_chr_layouts_with_both_inversion_bits() {
+        // Each 1KB bank holds its number, and its offset within the bank, at $x3A5
+        let mut chr: Vec<u8> = (0..32u8).flat_map(|bank| vec![bank; 0x400]).collect();
+        for bank in 0..32 {
+            chr[bank * 0x400 + 0x3A5] = 0xA5 ^ bank as u8;
+        }
+        let mut mapper = Mapper4::new(vec![0; 0x20000], chr, Mirroring::Vertical);
+        // Odd values in R0/R1 must still select the 2KB pair starting on the even bank
+        for (register, bank) in [(0, 17), (1, 6), (2, 20), (3, 21), (4, 30), (5, 3)] {
+            mapper.cpu_write(0x8000, register);
+            mapper.cpu_write(0x8001, bank);
+        }
+
+        let normal = [16, 17, 6, 7, 20, 21, 30, 3];
+        let inverted = [20, 21, 30, 3, 16, 17, 6, 7];
+        for (bank_select, expected) in [(0x00, normal), (0x40, normal), (0x80, inverted), (0xC0, inverted)] {
+            mapper.cpu_write(0x8000, bank_select);
+            assert_eq!(chr_layout(&mapper), expected, "bank select {:02X}", bank_select);
+            for (slot, &bank) in expected.iter().enumerate() {
+                let addr = slot as u16 * 0x400;
+                assert_eq!(mapper.chr_read(addr + 0x3A5), 0xA5 ^ bank);
+                assert_eq!(mapper.chr_read(addr + 0x3FF), bank);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mapper4_mirroring() {
+        let mut mapper = numbered_mmc3();
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        mapper.cpu_write(0xA000, 1);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        mapper.cpu_write(0xA000, 0);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+        let mut four_screen = Mapper4::new(vec![0; 0x8000], vec![0; 0x2000], Mirroring::FourScreen);
+        four_screen.cpu_write(0xA000, 1);
+        assert_eq!(four_screen.mirroring(), Mirroring::FourScreen);
+    }
+
+    #[test]
+    fn test_mapper4_scanline_irq() {
+        let mut mapper = numbered_mmc3();
+        mapper.cpu_write(0xC000, 2); // latch
+        mapper.cpu_write(0xC001, 0); // reload
+        mapper.cpu_write(0xE001, 0); // enable
+
+        mapper.notify_a12(); // reload to 2
+        mapper.notify_a12(); // 1
+        assert!(!mapper.irq_pending());
+        mapper.notify_a12(); // 0
+        assert!(mapper.irq_pending());
+
+        mapper.cpu_write(0xE000, 0); // acknowledge and disable
+        assert!(!mapper.irq_pending());
+        mapper.notify_a12(); // reload to 2
+        mapper.notify_a12();
+        mapper.notify_a12();
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_mapper7_prg_bank_and_mirroring() {
+        let prg = (0..8u8).flat_map(|bank| vec![bank; 0x8000]).collect();
+        let mut mapper = Mapper7::new(prg, vec![0; 8192]);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+
+        mapper.cpu_write(0x8000, 0x15); // bank 5, nametable B
+        assert_eq!(mapper.cpu_read(0x8000), 5);
+        assert_eq!(mapper.cpu_read(0xFFFF), 5);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenB);
+
+        mapper.cpu_write(0xFFFF, 0x0B); // bank 3 (bit 3 ignored), nametable A
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+    }
+
+    /// 32KB PRG banks whose bytes are all $FF apart from the bank number at
+    /// $8000, so writes elsewhere don't conflict.
+    fn numbered_gxrom(prg_banks: u8, chr_banks: u8) -> Mapper66 {
+        let prg = (0..prg_banks)
+            .flat_map(|bank| {
+                let mut data = vec![0xFF; 0x8000];
+                data[0] = bank;
+                data
+            })
+            .collect();
+        let chr = (0..chr_banks).flat_map(|bank| vec![bank; 0x2000]).collect();
+        Mapper66::new(prg, chr, Mirroring::Vertical)
+    }
+
+    #[test]
+    fn test_mapper66_chr_banks() {
+        let mut mapper = numbered_gxrom(2, 4);
+        for bank in 0..4 {
+            mapper.cpu_write(0xC000, bank);
+            assert_eq!(mapper.chr_read(0x0000), bank);
+            assert_eq!(mapper.chr_read(0x1FFF), bank);
+            assert_eq!(mapper.chr_banks()[7], bank as usize * 0x2000 + 0x1C00);
+        }
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_mapper66_prg_banks() {
+        let mut mapper = numbered_gxrom(4, 1);
+        for bank in 0..4 {
+            mapper.cpu_write(0xC000, bank << 4);
+            assert_eq!(mapper.cpu_read(0x8000), bank);
+            assert_eq!(mapper.cpu_read(0xFFFF), 0xFF);
+        }
+        // Both registers in one write
+        mapper.cpu_write(0xC000, 0x21);
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+        // Bus conflict with the bank number at $8000 (2) clears bit 5
+        mapper.cpu_write(0x8000, 0x30);
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+    }
+
+    #[test]
+    fn test_mapper66_wraps_undersized_roms() {
+        let mut mapper = numbered_gxrom(2, 2);
+        mapper.cpu_write(0xC000, 0x33); // PRG bank 3, CHR bank 3
+        assert_eq!(mapper.cpu_read(0x8000), 1);
+        assert_eq!(mapper.chr_read(0x0000), 1);
+
+        let mut mapper = Mapper66::new(vec![0x42; 0x4000], vec![0x24; 0x2000], Mirroring::Horizontal);
+        mapper.cpu_write(0xC000, 0x33);
+        assert_eq!(mapper.cpu_read(0xFFFF), 0x42);
+        assert_eq!(mapper.chr_read(0x1FFF), 0x24);
+    }
 }
@@ -1,25 +1,57 @@
+pub mod ips;
 pub mod mapper;
 
 use std::fmt;
+use std::path::Path;
+
+use crate::db::RomDatabase;
+use crate::nes::Region;
+use mapper::{Mapper, Mapper0, Mapper1, Mapper2, Mapper3, Mapper4, Mapper66, Mapper7, PrgRam};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub enum Mirroring {
     Horizontal,
     Vertical,
     FourScreen,
+    /// All four nametables show the first 1KB of VRAM (mapper-controlled).
+    SingleScreenA,
+    /// All four nametables show the second 1KB of VRAM (mapper-controlled).
+    SingleScreenB,
+}
+
+/// Header flavor, which decides how bytes 7-15 are interpreted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RomFormat {
+    Ines1_0,
+    Ines2_0,
+    /// Old headers (or ones with junk like "DiskDude!" in bytes 7-15) where
+    /// byte 7 can't be trusted, so the mapper's upper nibble is ignored.
+    ArchaicInes,
+}
+
+impl RomFormat {
+    /// Detect the header format, following the nesdev wiki heuristic. Expects at least 16 bytes.
+    pub fn detect(raw: &[u8]) -> RomFormat {
+        match raw[7] & 0x0C {
+            0x08 => RomFormat::Ines2_0,
+            0x00 if raw[12..16].iter().all(|&b| b == 0) => RomFormat::Ines1_0,
+            _ => RomFormat::ArchaicInes,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum CartridgeError {
     InvalidHeader,
-    UnsupportedMapper(u8),
+    UnsupportedMapper(u16),
     TruncatedFile,
 }
 
 impl fmt::Display for CartridgeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            CartridgeError::InvalidHeader => write!(f, "Invalid iNES header (missing NES\\x1A magic)"),
+            CartridgeError::InvalidHeader => write!(f, "Invalid iNES header"),
             CartridgeError::UnsupportedMapper(id) => write!(f, "Unsupported mapper: {}", id),
             CartridgeError::TruncatedFile => write!(f, "ROM file is truncated"),
         }
@@ -36,12 +68,155 @@ const TRAINER_SIZE: usize = 512;
 pub struct Cartridge {
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
-    pub mapper_id: u8,
+    /// 12 bits wide on NES 2.0, 8 bits on iNES 1.0, 4 bits on archaic headers.
+    pub mapper_id: u16,
+    /// NES 2.0 submapper (byte 8, high nibble); 0 on older headers.
+    pub submapper_id: u8,
+    pub mirroring: Mirroring,
+    pub format: RomFormat,
+    /// The header declared no CHR ROM, so `chr_rom` is 8KB of CHR RAM.
+    pub chr_is_ram: bool,
+    /// Total PRG RAM, volatile plus battery-backed.
+    pub prg_ram_size: usize,
+    /// How much of `prg_ram_size` is battery-backed. Only NES 2.0 headers say;
+    /// 0 otherwise.
+    pub prg_nvram_size: usize,
+    /// PRG RAM is battery-backed and should persist between sessions.
+    pub has_battery: bool,
+    /// The file had a 512-byte trainer before PRG ROM (not loaded).
+    pub has_trainer: bool,
+    /// Game title, when the ROM database knows the dump.
+    pub rom_title: Option<String>,
+    /// Console region, from the ROM database or an NES 2.0 header.
+    pub region: Option<Region>,
+}
+
+/// Summary of a cartridge's header, as `--info` prints it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CartridgeHeader {
+    pub mapper_id: u16,
+    pub submapper_id: u8,
+    pub prg_rom_size: usize,
+    /// 0 when the cartridge has CHR RAM instead.
+    pub chr_rom_size: usize,
+    pub chr_is_ram: bool,
     pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub has_trainer: bool,
+    /// 2 for NES 2.0 headers, 1 for iNES 1.0 and archaic ones.
+    pub ines_version: u8,
+    pub region: Option<Region>,
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ines = if self.ines_version == 2 { "NES 2.0" } else { "iNES 1.0" };
+        let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+        writeln!(f, "Format:     {}", ines)?;
+        writeln!(f, "Mapper:     {}.{}", self.mapper_id, self.submapper_id)?;
+        writeln!(f, "PRG ROM:    {} KB", self.prg_rom_size / 1024)?;
+        if self.chr_is_ram {
+            writeln!(f, "CHR:        8 KB RAM")?;
+        } else {
+            writeln!(f, "CHR ROM:    {} KB", self.chr_rom_size / 1024)?;
+        }
+        writeln!(f, "Mirroring:  {:?}", self.mirroring)?;
+        writeln!(f, "Battery:    {}", yes_no(self.has_battery))?;
+        writeln!(f, "Trainer:    {}", yes_no(self.has_trainer))?;
+        match self.region {
+            Some(region) => write!(f, "Region:     {:?}", region),
+            None => write!(f, "Region:     unknown"),
+        }
+    }
+}
+
+/// Decode an NES 2.0 ROM size from its LSB (byte 4/5) and MSB nibble (byte 9).
+/// MSB nibble $F selects exponent-multiplier notation: 2^E * (MM * 2 + 1) bytes.
+/// Returns None if the size doesn't fit in a usize.
+fn ines2_rom_size(lsb: u8, msb: u8, page_size: usize) -> Option<usize> {
+    if msb == 0x0F {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        1usize.checked_shl(exponent)?.checked_mul(multiplier)
+    } else {
+        (((msb as usize) << 8) | lsb as usize).checked_mul(page_size)
+    }
+}
+
+/// Decode an NES 2.0 RAM size nibble (byte 10/11): 0 means none, otherwise 64 << n bytes.
+fn ines2_ram_size(shift: u8) -> usize {
+    if shift == 0 { 0 } else { 64 << shift }
+}
+
+/// Inverse of `ines2_ram_size`. Sizes the header can't express exactly are
+/// rounded up, so the RAM never shrinks.
+fn ines2_ram_shift(size: usize) -> u8 {
+    match size {
+        0 => 0,
+        size => (size.next_power_of_two().max(128).trailing_zeros() - 6).min(15) as u8,
+    }
+}
+
+/// PRG and CHR ROM sizes from an NES 2.0 header.
+fn ines2_rom_sizes(raw: &[u8]) -> Option<(usize, usize)> {
+    let prg = ines2_rom_size(raw[4], raw[9] & 0x0F, PRG_ROM_PAGE_SIZE)?;
+    let chr = ines2_rom_size(raw[5], raw[9] >> 4, CHR_ROM_PAGE_SIZE)?;
+    Some((prg, chr))
 }
 
 impl Cartridge {
+    /// A bare NROM board (mapper 0, horizontal mirroring, 8KB of PRG RAM)
+    /// around ROMs built in code, for tests and tools.
+    pub fn nrom(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Cartridge {
+            prg_rom,
+            chr_rom,
+            mapper_id: 0,
+            submapper_id: 0,
+            mirroring: Mirroring::Horizontal,
+            format: RomFormat::Ines1_0,
+            chr_is_ram: false,
+            prg_ram_size: 8192,
+            prg_nvram_size: 0,
+            has_battery: false,
+            has_trainer: false,
+            rom_title: None,
+            region: None,
+        }
+    }
+
+    /// Check that `raw` carries a well-formed NES 2.0 header: magic bytes, the
+    /// NES 2.0 identifier in byte 7, a PRG ROM of whole 16KB pages, and ROM
+    /// sizes that neither overflow nor exceed the file.
+    pub fn is_valid_ines2(raw: &[u8]) -> bool {
+        if raw.len() < 16 || raw[0..4] != INES_MAGIC || RomFormat::detect(raw) != RomFormat::Ines2_0 {
+            return false;
+        }
+        let Some((prg, chr)) = ines2_rom_sizes(raw) else {
+            return false;
+        };
+        let trainer = if raw[6] & 0x04 != 0 { TRAINER_SIZE } else { 0 };
+        prg > 0
+            && prg % PRG_ROM_PAGE_SIZE == 0
+            && (16 + trainer)
+                .checked_add(prg)
+                .and_then(|n| n.checked_add(chr))
+                .is_some_and(|total| total <= raw.len())
+    }
+
+    pub fn format(&self) -> RomFormat {
+        self.format
+    }
+
+    /// Parse an iNES image, correcting its header from the built-in ROM
+    /// database.
     pub fn from_ines(raw: &[u8]) -> Result<Self, CartridgeError> {
+        Self::from_ines_with_db(raw, Some(RomDatabase::builtin()))
+    }
+
+    /// Parse an iNES image. When `db` knows the PRG ROM's CRC32, its mapper,
+    /// submapper, title and region replace what the header says.
+    pub fn from_ines_with_db(raw: &[u8], db: Option<&RomDatabase>) -> Result<Self, CartridgeError> {
         if raw.len() < 16 {
             return Err(CartridgeError::TruncatedFile);
         }
@@ -50,16 +225,17 @@ impl Cartridge {
             return Err(CartridgeError::InvalidHeader);
         }
 
-        let prg_rom_pages = raw[4] as usize;
-        let chr_rom_pages = raw[5] as usize;
+        let format = RomFormat::detect(raw);
         let flags6 = raw[6];
         let flags7 = raw[7];
 
-        let mapper_id = (flags7 & 0xF0) | (flags6 >> 4);
-
-        if mapper_id != 0 {
-            return Err(CartridgeError::UnsupportedMapper(mapper_id));
-        }
+        let mut mapper_id = match format {
+            RomFormat::ArchaicInes => (flags6 >> 4) as u16,
+            RomFormat::Ines1_0 => ((flags7 & 0xF0) | (flags6 >> 4)) as u16,
+            RomFormat::Ines2_0 => {
+                ((raw[8] & 0x0F) as u16) << 8 | ((flags7 & 0xF0) | (flags6 >> 4)) as u16
+            }
+        };
 
         let mirroring = if flags6 & 0x08 != 0 {
             Mirroring::FourScreen
@@ -69,23 +245,74 @@ impl Cartridge {
             Mirroring::Horizontal
         };
 
+        let has_battery = flags6 & 0x02 != 0;
         let has_trainer = flags6 & 0x04 != 0;
 
-        let prg_rom_size = prg_rom_pages * PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = chr_rom_pages * CHR_ROM_PAGE_SIZE;
+        let (mut submapper_id, prg_ram_size, prg_nvram_size) = match format {
+            RomFormat::Ines2_0 => {
+                let nvram = ines2_ram_size(raw[10] >> 4);
+                (raw[8] >> 4, ines2_ram_size(raw[10] & 0x0F) + nvram, nvram)
+            }
+            // Byte 8 counts 8KB units, with 0 meaning 8KB for compatibility
+            RomFormat::Ines1_0 => (0, raw[8].max(1) as usize * 8192, 0),
+            RomFormat::ArchaicInes => (0, 8192, 0),
+        };
+
+        // NES 2.0 byte 12: CPU/PPU timing. Multi-region (2) runs anywhere.
+        let header_region = match (format, raw[12] & 0x03) {
+            (RomFormat::Ines2_0, 0) => Some(Region::Ntsc),
+            (RomFormat::Ines2_0, 1) => Some(Region::Pal),
+            (RomFormat::Ines2_0, 3) => Some(Region::Dendy),
+            _ => None,
+        };
+
+        let (prg_rom_size, chr_rom_size) = match format {
+            RomFormat::Ines2_0 => ines2_rom_sizes(raw).ok_or(CartridgeError::InvalidHeader)?,
+            RomFormat::Ines1_0 | RomFormat::ArchaicInes => {
+                (raw[4] as usize * PRG_ROM_PAGE_SIZE, raw[5] as usize * CHR_ROM_PAGE_SIZE)
+            }
+        };
+        // Every supported mapper banks PRG ROM in 16KB units (MMC3's 8KB banks
+        // come in pairs), and reads assume there's at least one
+        if prg_rom_size == 0 || prg_rom_size % PRG_ROM_PAGE_SIZE != 0 {
+            return Err(CartridgeError::InvalidHeader);
+        }
 
         let mut offset = 16;
         if has_trainer {
             offset += TRAINER_SIZE;
         }
 
-        if raw.len() < offset + prg_rom_size + chr_rom_size {
+        let end = offset
+            .checked_add(prg_rom_size)
+            .and_then(|n| n.checked_add(chr_rom_size))
+            .ok_or(CartridgeError::TruncatedFile)?;
+        if raw.len() < end {
             return Err(CartridgeError::TruncatedFile);
         }
 
         let prg_rom = raw[offset..offset + prg_rom_size].to_vec();
         offset += prg_rom_size;
 
+        let known = db.and_then(|db| db.lookup(crc32(&prg_rom)));
+        if let Some(info) = known {
+            if (info.mapper_id, info.submapper) != (mapper_id, submapper_id) {
+                log::info!(
+                    "ROM database: {} is mapper {}.{}, not {}.{} as the header says",
+                    info.title, info.mapper_id, info.submapper, mapper_id, submapper_id
+                );
+            }
+            // Databases without submappers (like NesCartDB) leave a matching
+            // header's submapper alone
+            if info.mapper_id != mapper_id || info.submapper != 0 {
+                submapper_id = info.submapper;
+            }
+            mapper_id = info.mapper_id;
+        }
+        if !mapper::SUPPORTED_MAPPERS.contains(&mapper_id) {
+            return Err(CartridgeError::UnsupportedMapper(mapper_id));
+        }
+
         let chr_rom = if chr_rom_size > 0 {
             raw[offset..offset + chr_rom_size].to_vec()
         } else {
@@ -97,14 +324,138 @@ impl Cartridge {
             prg_rom,
             chr_rom,
             mapper_id,
+            submapper_id,
             mirroring,
+            format,
+            chr_is_ram: chr_rom_size == 0,
+            prg_ram_size,
+            prg_nvram_size,
+            has_battery,
+            has_trainer,
+            rom_title: known.map(|info| info.title.to_string()),
+            region: known.map(|info| info.region).or(header_region),
         })
     }
+
+    /// Serialize back to an iNES image. NES 2.0 ROMs keep their format; older
+    /// headers are normalized to a clean iNES 1.0 header. Trainers aren't kept
+    /// by `from_ines`, so they aren't written either.
+    pub fn to_ines(&self) -> Vec<u8> {
+        let chr_size = if self.chr_is_ram { 0 } else { self.chr_rom.len() };
+        let prg_pages = self.prg_rom.len() / PRG_ROM_PAGE_SIZE;
+        let chr_pages = chr_size / CHR_ROM_PAGE_SIZE;
+
+        let mut flags6 = ((self.mapper_id & 0x0F) as u8) << 4;
+        if self.has_battery {
+            flags6 |= 0x02;
+        }
+        match self.mirroring {
+            // Single-screen modes are set by the mapper at runtime, not the header
+            Mirroring::Horizontal | Mirroring::SingleScreenA | Mirroring::SingleScreenB => {}
+            Mirroring::Vertical => flags6 |= 0x01,
+            Mirroring::FourScreen => flags6 |= 0x08,
+        }
+        let mut flags7 = (self.mapper_id & 0xF0) as u8;
+
+        let mut raw = Vec::with_capacity(16 + self.prg_rom.len() + chr_size);
+        raw.extend_from_slice(&INES_MAGIC);
+        raw.extend_from_slice(&[prg_pages as u8, chr_pages as u8, flags6]);
+        let mut rest = [0u8; 9];
+        if self.format == RomFormat::Ines2_0 {
+            flags7 |= 0x08;
+            rest[1] = (self.submapper_id << 4) | ((self.mapper_id >> 8) as u8 & 0x0F);
+            rest[2] = ((chr_pages >> 8) as u8 & 0x0F) << 4 | ((prg_pages >> 8) as u8 & 0x0F);
+            let volatile = self.prg_ram_size.saturating_sub(self.prg_nvram_size);
+            rest[3] = ines2_ram_shift(self.prg_nvram_size) << 4 | ines2_ram_shift(volatile);
+        } else if self.prg_ram_size > 8192 {
+            rest[1] = (self.prg_ram_size / 8192) as u8;
+        }
+        rest[0] = flags7;
+        raw.extend_from_slice(&rest);
+        raw.extend_from_slice(&self.prg_rom);
+        raw.extend_from_slice(&self.chr_rom[..chr_size]);
+        raw
+    }
+
+    /// Build the mapper for this cartridge. Panics on a mapper ID outside
+    /// `mapper::SUPPORTED_MAPPERS`, which `from_ines` already rejects.
+    pub fn into_mapper(self) -> Box<dyn Mapper> {
+        let prg_ram = PrgRam::new(self.prg_ram_size, self.has_battery);
+        let mut mapper: Box<dyn Mapper> = match self.mapper_id {
+            0 => Box::new(Mapper0::new(self.prg_rom, self.chr_rom, self.mirroring)),
+            1 => Box::new(Mapper1::new(self.prg_rom, self.chr_rom, self.chr_is_ram)),
+            // Submapper 2 is UxROM with AND-type bus conflicts
+            2 => Box::new(
+                Mapper2::new(self.prg_rom, self.chr_rom, self.mirroring).with_bus_conflicts(self.submapper_id == 2),
+            ),
+            3 => Box::new(Mapper3::new(self.prg_rom, self.chr_rom, self.mirroring)),
+            4 => Box::new(Mapper4::new(self.prg_rom, self.chr_rom, self.mirroring)),
+            7 => Box::new(Mapper7::new(self.prg_rom, self.chr_rom)),
+            66 => Box::new(Mapper66::new(self.prg_rom, self.chr_rom, self.mirroring)),
+            id => panic!("Mapper {} is not supported", id),
+        };
+        if let Some(ram) = mapper.prg_ram_mut() {
+            *ram = prg_ram;
+        }
+        mapper
+    }
+
+    pub fn header_info(&self) -> CartridgeHeader {
+        CartridgeHeader {
+            mapper_id: self.mapper_id,
+            submapper_id: self.submapper_id,
+            prg_rom_size: self.prg_rom.len(),
+            chr_rom_size: if self.chr_is_ram { 0 } else { self.chr_rom.len() },
+            chr_is_ram: self.chr_is_ram,
+            mirroring: self.mirroring,
+            has_battery: self.has_battery,
+            has_trainer: self.has_trainer,
+            ines_version: if self.format == RomFormat::Ines2_0 { 2 } else { 1 },
+            region: self.region,
+        }
+    }
+
+    /// CRC-32 of the PRG ROM followed by the CHR ROM, identifying the game
+    /// independent of its header.
+    pub fn rom_crc32(&self) -> u32 {
+        crc32(&[self.prg_rom.as_slice(), self.chr_rom.as_slice()].concat())
+    }
+
+    /// Write the cartridge to `path` as an iNES file (see `to_ines`).
+    pub fn save_ines(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_ines())
+    }
+}
+
+/// CRC-32 (IEEE, as used by zip and the No-Intro ROM databases).
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Fuzzing entry point for cargo-fuzz (see `fuzz/`). Parsing arbitrary bytes must
+/// never panic: every malformed input has to come back as a `CartridgeError`.
+/// Cartridges that do parse are powered on, which reads the reset vector, and
+/// read back through their mapper.
+pub fn fuzz_from_ines(data: &[u8]) {
+    if let Ok(cartridge) = Cartridge::from_ines(data) {
+        let nes = crate::nes::Nes::new_no_audio(cartridge);
+        nes.bus.peek(0x8000);
+        nes.bus.peek(0xFFFC);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::RomInfo;
 
     fn make_header(prg_pages: u8, chr_pages: u8, flags6: u8, flags7: u8) -> Vec<u8> {
         let mut header = vec![0x4E, 0x45, 0x53, 0x1A, prg_pages, chr_pages, flags6, flags7];
@@ -159,15 +510,81 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_crc32_check_value() {
+        // The standard CRC-32 check value, plus the empty input
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_rom_database_overrides_header() {
+        // Header says mapper 0; the database knows this PRG ROM as UxROM
+        let data = make_header(2, 0, 0x00, 0x00);
+        let prg_crc32 = crc32(&data[16..16 + 2 * PRG_ROM_PAGE_SIZE]);
+        assert_eq!(prg_crc32, crc32(&[0xEA; 2 * PRG_ROM_PAGE_SIZE]));
+        let info = RomInfo { mapper_id: 2, submapper: 2, region: Region::Pal, title: "Test Game".into() };
+        let db = RomDatabase::new([(prg_crc32, info.clone())]);
+        assert_eq!(db.lookup(prg_crc32), Some(&info));
+        assert_eq!(db.lookup(prg_crc32 ^ 1), None);
+
+        let cart = Cartridge::from_ines_with_db(&data, Some(&db)).unwrap();
+        assert_eq!((cart.mapper_id, cart.submapper_id), (2, 2));
+        assert_eq!(cart.rom_title.as_deref(), Some("Test Game"));
+        assert_eq!(cart.region, Some(Region::Pal));
+
+        let cart = Cartridge::from_ines_with_db(&data, None).unwrap();
+        assert_eq!((cart.mapper_id, cart.submapper_id), (0, 0));
+        assert_eq!((cart.rom_title, cart.region), (None, None));
+
+        // A known dump with an unsupported header mapper still loads
+        let mut data = data;
+        data[6] = 0x50;
+        assert_eq!(Cartridge::from_ines_with_db(&data, Some(&db)).unwrap().mapper_id, 2);
+    }
+
     #[test]
     fn test_unsupported_mapper() {
-        let data = make_header(1, 1, 0x10, 0x00); // mapper 1
+        let data = make_header(1, 1, 0x50, 0x00); // mapper 5
         assert!(matches!(
             Cartridge::from_ines(&data),
-            Err(CartridgeError::UnsupportedMapper(1))
+            Err(CartridgeError::UnsupportedMapper(5))
         ));
     }
 
+    #[test]
+    fn test_mapper1_dispatch() {
+        let mut data = make_header(8, 2, 0x10, 0x00); // mapper 1, 128KB PRG
+        data[16 + 7 * PRG_ROM_PAGE_SIZE] = 0x42;
+        let cart = Cartridge::from_ines(&data).unwrap();
+        assert_eq!(cart.mapper_id, 1);
+        // MMC1 powers on with the last bank fixed at $C000
+        let mapper = cart.into_mapper();
+        assert_eq!(mapper.cpu_read(0xC000), 0x42);
+    }
+
+    #[test]
+    fn test_mapper2_dispatch() {
+        let mut data = make_header(4, 0, 0x21, 0x00); // mapper 2, vertical, CHR RAM
+        data[16 + 3 * PRG_ROM_PAGE_SIZE] = 0x42;
+        let cart = Cartridge::from_ines(&data).unwrap();
+        assert!(cart.chr_is_ram);
+        let mut mapper = cart.into_mapper();
+        mapper.cpu_write(0x8000, 1);
+        assert_eq!(mapper.cpu_read(0xC000), 0x42);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_mapper3_dispatch() {
+        let mut data = make_header(1, 4, 0x30, 0x00); // mapper 3, 32KB CHR
+        let chr_start = 16 + PRG_ROM_PAGE_SIZE;
+        data[chr_start + 2 * CHR_ROM_PAGE_SIZE] = 0x42;
+        let mut mapper = Cartridge::from_ines(&data).unwrap().into_mapper();
+        mapper.cpu_write(0x8000, 2); // PRG is all $EA, so no bus conflict on bit 1
+        assert_eq!(mapper.chr_read(0x0000), 0x42);
+    }
+
     #[test]
     fn test_four_screen_mirroring() {
         let data = make_header(1, 1, 0x08, 0x00);
@@ -185,4 +602,292 @@ mod tests {
         let cart = Cartridge::from_ines(&data).unwrap();
         assert_eq!(cart.prg_rom[0], 0xEA); // should be PRG data, not trainer
     }
+
+    #[test]
+    fn test_fuzz_corpus_does_not_panic() {
+        let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fuzz_corpus");
+        let mut count = 0;
+        for entry in std::fs::read_dir(corpus).unwrap() {
+            let data = std::fs::read(entry.unwrap().path()).unwrap();
+            fuzz_from_ines(&data);
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn test_format_ines1() {
+        let data = make_header(1, 1, 0x00, 0x00);
+        assert_eq!(Cartridge::from_ines(&data).unwrap().format(), RomFormat::Ines1_0);
+        assert!(!Cartridge::is_valid_ines2(&data));
+    }
+
+    #[test]
+    fn test_format_ines2() {
+        let mut data = make_header(2, 1, 0x00, 0x08);
+        data[9] = 0x00; // no size MSBs
+        assert!(Cartridge::is_valid_ines2(&data));
+        let cart = Cartridge::from_ines(&data).unwrap();
+        assert_eq!(cart.format(), RomFormat::Ines2_0);
+        assert_eq!(cart.prg_rom.len(), 2 * PRG_ROM_PAGE_SIZE);
+        assert_eq!(cart.chr_rom.len(), CHR_ROM_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_ines2_extended_prg_size() {
+        // 0x100 PRG pages via the byte 9 MSB nibble, far more than the file holds
+        let mut data = make_header(0, 1, 0x00, 0x08);
+        data[9] = 0x01;
+        assert!(!Cartridge::is_valid_ines2(&data));
+        assert!(matches!(Cartridge::from_ines(&data), Err(CartridgeError::TruncatedFile)));
+    }
+
+    #[test]
+    fn test_ines2_exponent_size_overflow() {
+        // Exponent-multiplier notation with 2^63 * 7 bytes of PRG overflows
+        let mut data = make_header(1, 1, 0x00, 0x08);
+        data[4] = 0xFF;
+        data[9] = 0x0F;
+        assert!(!Cartridge::is_valid_ines2(&data));
+        assert!(matches!(Cartridge::from_ines(&data), Err(CartridgeError::InvalidHeader)));
+    }
+
+    #[test]
+    fn test_ines1_rejects_empty_prg() {
+        let data = make_header(0, 1, 0x00, 0x00);
+        assert!(matches!(Cartridge::from_ines(&data), Err(CartridgeError::InvalidHeader)));
+    }
+
+    #[test]
+    fn test_ines2_rejects_partial_prg_pages() {
+        // Exponent-multiplier sizes: 2^0 * 1 = 1 byte and 2^0 * 3 = 3 bytes,
+        // with the bytes present in the file
+        for (lsb, size) in [(0x00, 1), (0x01, 3)] {
+            let mut data = make_header(0, 0, 0x00, 0x08);
+            data[4] = lsb;
+            data[9] = 0x0F;
+            data.extend_from_slice(&vec![0xEA; size]);
+            assert!(!Cartridge::is_valid_ines2(&data), "{} bytes", size);
+            assert!(matches!(Cartridge::from_ines(&data), Err(CartridgeError::InvalidHeader)), "{} bytes", size);
+        }
+    }
+
+    #[test]
+    fn test_ines2_rejects_empty_prg() {
+        let data = make_header(0, 1, 0x00, 0x08);
+        assert!(!Cartridge::is_valid_ines2(&data));
+        assert!(matches!(Cartridge::from_ines(&data), Err(CartridgeError::InvalidHeader)));
+    }
+
+    #[test]
+    fn test_ines2_12bit_mapper() {
+        // Byte 8's low nibble holds mapper bits 8-11: mapper 0x104
+        let mut data = make_header(1, 1, 0x40, 0x08);
+        data[8] = 0x01;
+        assert!(matches!(
+            Cartridge::from_ines(&data),
+            Err(CartridgeError::UnsupportedMapper(0x104))
+        ));
+        // The same bytes under iNES 1.0 are plain mapper 4
+        data[7] = 0x00;
+        data[8] = 0x00;
+        assert_eq!(Cartridge::from_ines(&data).unwrap().mapper_id, 4);
+    }
+
+    #[test]
+    fn test_header_info() {
+        // iNES 1.0, mapper 1, battery, vertical mirroring, CHR RAM
+        let data = make_header(2, 0, 0x13, 0x00);
+        let info = Cartridge::from_ines_with_db(&data, None).unwrap().header_info();
+        assert_eq!(
+            info,
+            CartridgeHeader {
+                mapper_id: 1,
+                submapper_id: 0,
+                prg_rom_size: 32768,
+                chr_rom_size: 0,
+                chr_is_ram: true,
+                mirroring: Mirroring::Vertical,
+                has_battery: true,
+                has_trainer: false,
+                ines_version: 1,
+                region: None,
+            }
+        );
+        assert!(info.to_string().contains("Mapper:     1.0"));
+        assert!(info.to_string().contains("CHR:        8 KB RAM"));
+
+        // NES 2.0, mapper 2 submapper 2, PAL timing, with a trainer
+        let mut data = make_header(1, 1, 0x24, 0x08);
+        data[8] = 0x20;
+        data[12] = 0x01;
+        data.splice(16..16, [0u8; TRAINER_SIZE]);
+        let info = Cartridge::from_ines_with_db(&data, None).unwrap().header_info();
+        assert_eq!((info.mapper_id, info.submapper_id, info.ines_version), (2, 2, 2));
+        assert_eq!((info.prg_rom_size, info.chr_rom_size, info.chr_is_ram), (16384, 8192, false));
+        assert_eq!(info.mirroring, Mirroring::Horizontal);
+        assert!(info.has_trainer && !info.has_battery);
+        assert_eq!(info.region, Some(Region::Pal));
+        assert!(info.to_string().contains("Format:     NES 2.0"));
+        assert!(info.to_string().ends_with("Region:     Pal"));
+
+        // Multi-region NES 2.0 headers don't pin a region
+        data[12] = 0x02;
+        assert_eq!(Cartridge::from_ines_with_db(&data, None).unwrap().header_info().region, None);
+    }
+
+    #[test]
+    fn test_ines2_submapper() {
+        let mut data = make_header(2, 0, 0x20, 0x08); // UxROM
+        data[8] = 0x20; // submapper 2: bus conflicts
+        let cart = Cartridge::from_ines(&data).unwrap();
+        assert_eq!((cart.mapper_id, cart.submapper_id), (2, 2));
+        let raw = cart.to_ines();
+        assert_eq!(raw[8], 0x20);
+        assert_eq!(Cartridge::from_ines(&raw).unwrap().submapper_id, 2);
+    }
+
+    #[test]
+    fn test_ines2_exponent_prg_size() {
+        // 2^14 * (0 * 2 + 1) = 16KB in exponent-multiplier notation
+        let mut data = make_header(1, 1, 0x00, 0x08);
+        data[4] = 14 << 2;
+        data[9] = 0x0F;
+        let cart = Cartridge::from_ines(&data).unwrap();
+        assert_eq!(cart.prg_rom.len(), PRG_ROM_PAGE_SIZE);
+        assert_eq!(cart.prg_rom[0], 0xEA);
+    }
+
+    #[test]
+    fn test_prg_ram_size_and_battery() {
+        // NES 2.0: 8KB battery-backed (shift 7 in the high nibble) plus 2KB volatile
+        let mut data = make_header(1, 1, 0x02, 0x08);
+        data[10] = 0x75;
+        let cart = Cartridge::from_ines(&data).unwrap();
+        assert!(cart.has_battery);
+        assert_eq!((cart.prg_ram_size, cart.prg_nvram_size), (8192 + 2048, 8192));
+        assert_eq!(cart.to_ines(), data);
+
+        // iNES 1.0: byte 8 counts 8KB units, 0 meaning one
+        let mut data = make_header(1, 1, 0x00, 0x00);
+        assert_eq!(Cartridge::from_ines(&data).unwrap().prg_ram_size, 8192);
+        data[8] = 4;
+        let cart = Cartridge::from_ines(&data).unwrap();
+        assert!(!cart.has_battery);
+        assert_eq!(cart.prg_ram_size, 32768);
+        assert_eq!(cart.to_ines(), data);
+    }
+
+    #[test]
+    fn test_ines2_prg_ram_sizes_round_trip() {
+        for byte10 in [0x00, 0x01, 0x07, 0x70, 0x0E, 0xF0, 0x9A] {
+            let mut data = make_header(1, 1, if byte10 >> 4 != 0 { 0x02 } else { 0 }, 0x08);
+            data[10] = byte10;
+            let cart = Cartridge::from_ines(&data).unwrap();
+            assert_eq!(cart.to_ines(), data, "byte 10 = {:02X}", byte10);
+        }
+
+        // Sizes in between round up to the next one the header can hold
+        let mut cart = Cartridge::from_ines(&make_header(1, 1, 0x00, 0x08)).unwrap();
+        cart.prg_ram_size = 3000;
+        assert_eq!(Cartridge::from_ines(&cart.to_ines()).unwrap().prg_ram_size, 4096);
+    }
+
+    #[test]
+    fn test_ines2_rom_size_msbs_in_byte_9() {
+        // 257 16KB PRG pages needs the PRG MSB nibble; byte 8 keeps the submapper
+        let mut cart = Cartridge::from_ines(&make_header(1, 1, 0x00, 0x08)).unwrap();
+        cart.prg_rom = vec![0xEA; 0x101 * PRG_ROM_PAGE_SIZE];
+        cart.submapper_id = 3;
+        let raw = cart.to_ines();
+        assert_eq!((raw[4], raw[8], raw[9]), (0x01, 0x30, 0x01));
+        let reloaded = Cartridge::from_ines(&raw).unwrap();
+        assert_eq!((reloaded.prg_rom.len(), reloaded.submapper_id), (cart.prg_rom.len(), 3));
+    }
+
+    #[test]
+    fn test_mapper_gets_header_prg_ram_size() {
+        // 2KB of PRG RAM mirrors through $6000-$7FFF; none leaves it unmapped
+        let mut data = make_header(1, 1, 0x00, 0x08);
+        data[10] = 0x05;
+        let mut mapper = Cartridge::from_ines(&data).unwrap().into_mapper();
+        mapper.cpu_write(0x6001, 0x42);
+        assert_eq!(mapper.cpu_read(0x6801), 0x42);
+
+        data[10] = 0x00;
+        let mut mapper = Cartridge::from_ines(&data).unwrap().into_mapper();
+        mapper.cpu_write(0x6001, 0x42);
+        assert_eq!(mapper.cpu_read(0x6001), 0);
+    }
+
+    #[test]
+    fn test_format_archaic_ignores_byte7() {
+        // "DiskDude!" in bytes 7-15 sets byte 7 bits 2-3 to 01
+        let mut data = make_header(1, 1, 0x00, 0x00);
+        data[7..16].copy_from_slice(b"DiskDude!");
+        let cart = Cartridge::from_ines(&data).unwrap();
+        assert_eq!(cart.format(), RomFormat::ArchaicInes);
+        assert_eq!(cart.mapper_id, 0);
+    }
+
+    #[test]
+    fn test_to_ines_round_trip() {
+        let mut data = make_header(2, 1, 0x01, 0x00);
+        data[16] = 0x4C;
+        let cart = Cartridge::from_ines(&data).unwrap();
+        assert_eq!(cart.to_ines(), data);
+    }
+
+    #[test]
+    fn test_to_ines_chr_ram_and_ines2() {
+        let cart = Cartridge::from_ines(&make_header(1, 0, 0x00, 0x08)).unwrap();
+        assert!(cart.chr_is_ram);
+        let raw = cart.to_ines();
+        assert_eq!(raw.len(), 16 + PRG_ROM_PAGE_SIZE);
+        assert_eq!(raw[5], 0);
+        assert_eq!(RomFormat::detect(&raw), RomFormat::Ines2_0);
+    }
+
+    #[test]
+    fn test_to_ines_normalizes_archaic_header() {
+        let mut data = make_header(1, 1, 0x00, 0x00);
+        data[7..16].copy_from_slice(b"DiskDude!");
+        let raw = Cartridge::from_ines(&data).unwrap().to_ines();
+        assert_eq!(raw[7..16], [0u8; 9]);
+        assert_eq!(RomFormat::detect(&raw), RomFormat::Ines1_0);
+    }
+
+    #[test]
+    fn test_save_ines_with_ips_patch() {
+        let data = make_header(1, 1, 0x00, 0x00);
+        // Patch PRG offset 0x0010 (file offset 0x20) to LDA #$42
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x20, 0x00, 0x02, 0xA9, 0x42]);
+        patch.extend_from_slice(b"EOF");
+        let patched = ips::apply(&data, &patch).unwrap();
+        let cart = Cartridge::from_ines(&patched).unwrap();
+
+        let path = std::env::temp_dir().join(format!("vines_save_ines_{}.nes", std::process::id()));
+        cart.save_ines(&path).unwrap();
+        let reloaded = Cartridge::from_ines(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(reloaded.prg_rom[0x10..0x12], [0xA9, 0x42]);
+        assert_eq!(reloaded.prg_rom[0x12], 0xEA);
+        assert_eq!(reloaded.prg_rom, cart.prg_rom);
+        assert_eq!(reloaded.chr_rom, cart.chr_rom);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_format_junk_padding_is_archaic() {
+        let mut data = make_header(1, 1, 0x00, 0x00);
+        data[13] = 0x42;
+        assert_eq!(RomFormat::detect(&data), RomFormat::ArchaicInes);
+    }
 }
@@ -0,0 +1,189 @@
+//! Game Genie code decoding, and the engine that applies codes to bus reads.
+
+use std::fmt;
+
+const LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+#[derive(Debug, PartialEq)]
+pub enum CheatError {
+    InvalidLength(usize),
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatError::InvalidLength(len) => {
+                write!(f, "Game Genie codes are 6 or 8 letters, got {}", len)
+            }
+            CheatError::InvalidCharacter(c) => write!(f, "Invalid Game Genie letter '{}'", c),
+        }
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+/// A decoded Game Genie code: reads of `address` return `value`, but only
+/// when the ROM byte there equals `compare` (8-letter codes).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameGenieCode {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl GameGenieCode {
+    /// Decode a 6- or 8-letter code. Letters are case-insensitive.
+    pub fn decode(code: &str) -> Result<Self, CheatError> {
+        let n = code
+            .chars()
+            .map(|c| {
+                LETTERS
+                    .iter()
+                    .position(|&l| l == c.to_ascii_uppercase() as u8)
+                    .map(|i| i as u16)
+                    .ok_or(CheatError::InvalidCharacter(c))
+            })
+            .collect::<Result<Vec<u16>, _>>()?;
+        if n.len() != 6 && n.len() != 8 {
+            return Err(CheatError::InvalidLength(n.len()));
+        }
+
+        let address = 0x8000
+            | (n[3] & 7) << 12
+            | (n[5] & 7) << 8
+            | (n[4] & 8) << 8
+            | (n[2] & 7) << 4
+            | (n[1] & 8) << 4
+            | (n[4] & 7)
+            | (n[3] & 8);
+        // The last letter's high bit belongs to the value for both lengths
+        let value_hi = if n.len() == 8 { n[7] } else { n[5] };
+        let value = (n[1] & 7) << 4 | (n[0] & 8) << 4 | (n[0] & 7) | (value_hi & 8);
+        let compare = (n.len() == 8)
+            .then(|| ((n[7] & 7) << 4 | (n[6] & 8) << 4 | (n[6] & 7) | (n[5] & 8)) as u8);
+
+        Ok(GameGenieCode { address, value: value as u8, compare })
+    }
+
+    /// Bake the code into a PRG ROM image, returning how many bytes changed.
+    /// ROMs up to 32KB map directly onto $8000-$FFFF (mirrored for NROM-128).
+    /// Larger, bank-switched ROMs get every 8KB bank patched at the address's
+    /// offset, so codes without a compare value may hit more than intended.
+    pub fn patch_prg(&self, prg_rom: &mut [u8]) -> usize {
+        if prg_rom.is_empty() {
+            return 0;
+        }
+        let offset = (self.address - 0x8000) as usize;
+        let (start, stride) = if prg_rom.len() <= 0x8000 {
+            (offset % prg_rom.len(), prg_rom.len())
+        } else {
+            (offset & 0x1FFF, 0x2000)
+        };
+
+        let mut patched = 0;
+        for i in (start..prg_rom.len()).step_by(stride) {
+            if self.compare.is_none_or(|c| prg_rom[i] == c) && prg_rom[i] != self.value {
+                prg_rom[i] = self.value;
+                patched += 1;
+            }
+        }
+        patched
+    }
+}
+
+/// Active Game Genie codes, applied to CPU reads of $8000-$FFFF by the bus
+/// rather than by patching the ROM, so they follow bank switching.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheatEngine {
+    codes: Vec<GameGenieCode>,
+}
+
+impl CheatEngine {
+    /// Add a code, replacing any earlier code for the same address.
+    pub fn add(&mut self, code: GameGenieCode) {
+        self.remove(code.address);
+        self.codes.push(code);
+    }
+
+    pub fn remove(&mut self, address: u16) {
+        self.codes.retain(|c| c.address != address);
+    }
+
+    pub fn codes(&self) -> &[GameGenieCode] {
+        &self.codes
+    }
+
+    /// The byte the CPU sees at `address`, given the byte the cartridge returned.
+    pub fn apply(&self, address: u16, original: u8) -> u8 {
+        match self.codes.iter().find(|c| c.address == address) {
+            Some(code) if code.compare.is_none_or(|c| c == original) => code.value,
+            _ => original,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_six_letter() {
+        // Super Mario Bros. infinite lives
+        let code = GameGenieCode::decode("SXIOPO").unwrap();
+        assert_eq!(code, GameGenieCode { address: 0x91D9, value: 0xAD, compare: None });
+        assert_eq!(GameGenieCode::decode("sxiopo").unwrap(), code);
+    }
+
+    #[test]
+    fn test_decode_eight_letter() {
+        let code = GameGenieCode::decode("SLXPLOVS").unwrap();
+        assert_eq!(code, GameGenieCode { address: 0x9123, value: 0xBD, compare: Some(0xDE) });
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(GameGenieCode::decode("SXIOP"), Err(CheatError::InvalidLength(5)));
+        assert_eq!(GameGenieCode::decode("SXIOPB"), Err(CheatError::InvalidCharacter('B')));
+    }
+
+    #[test]
+    fn test_patch_prg_mirrors_nrom128() {
+        let mut prg = vec![0xDE; 0x4000];
+        let code = GameGenieCode { address: 0xD123, value: 0xBD, compare: Some(0xDE) };
+        assert_eq!(code.patch_prg(&mut prg), 1);
+        assert_eq!(prg[0x1123], 0xBD);
+        // Compare no longer matches, so a second pass is a no-op
+        assert_eq!(code.patch_prg(&mut prg), 0);
+    }
+
+    #[test]
+    fn test_patch_prg_bank_switched() {
+        let mut prg = vec![0x00; 0x10000];
+        prg[0x3123] = 0xDE;
+        let code = GameGenieCode { address: 0x9123, value: 0xBD, compare: Some(0xDE) };
+        assert_eq!(code.patch_prg(&mut prg), 1);
+        assert_eq!(prg[0x3123], 0xBD);
+    }
+
+    #[test]
+    fn test_engine_substitutes_reads() {
+        let mut cheats = CheatEngine::default();
+        cheats.add(GameGenieCode::decode("SXIOPO").unwrap());
+        assert_eq!(cheats.apply(0x91D9, 0x00), 0xAD);
+        assert_eq!(cheats.apply(0x91DA, 0x00), 0x00);
+    }
+
+    #[test]
+    fn test_engine_compare_match_and_miss() {
+        let mut cheats = CheatEngine::default();
+        cheats.add(GameGenieCode::decode("SLXPLOVS").unwrap());
+        assert_eq!(cheats.apply(0x9123, 0xDE), 0xBD);
+        assert_eq!(cheats.apply(0x9123, 0xDF), 0xDF);
+
+        cheats.add(GameGenieCode { address: 0x9123, value: 0x01, compare: None });
+        assert_eq!(cheats.codes().len(), 1);
+        cheats.remove(0x9123);
+        assert_eq!(cheats.apply(0x9123, 0xDE), 0xDE);
+    }
+}
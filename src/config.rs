@@ -0,0 +1,144 @@
+//! User configuration loaded from `config.toml`: currently the keyboard
+//! bindings for controller 1.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// Keyboard key for each NES button, by SDL key name (`"Z"`, `"Return"`,
+/// `"Right Shift"`, `"Keypad Enter"`, ...).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub a: String,
+    pub b: String,
+    pub start: String,
+    pub select: String,
+    pub up: String,
+    pub down: String,
+    pub left: String,
+    pub right: String,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            a: "Z".into(),
+            b: "X".into(),
+            start: "Return".into(),
+            select: "Right Shift".into(),
+            up: "Up".into(),
+            down: "Down".into(),
+            left: "Left".into(),
+            right: "Right".into(),
+        }
+    }
+}
+
+/// The whole of `config.toml`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    keys: KeyBindings,
+}
+
+impl KeyBindings {
+    /// Every NES button with the key bound to it.
+    pub fn buttons(&self) -> [(&'static str, &str); 8] {
+        [
+            ("a", &self.a),
+            ("b", &self.b),
+            ("start", &self.start),
+            ("select", &self.select),
+            ("up", &self.up),
+            ("down", &self.down),
+            ("left", &self.left),
+            ("right", &self.right),
+        ]
+    }
+
+    /// Parse a config file's `[keys]` table of `button = "key"` entries.
+    /// Buttons the file leaves out keep their default key.
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let file: ConfigFile = toml::from_str(text).map_err(ConfigError::Parse)?;
+        Ok(file.keys)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Load key bindings from `path`, which has to exist.
+pub fn load_config(path: &Path) -> Result<KeyBindings, ConfigError> {
+    KeyBindings::parse(&fs::read_to_string(path).map_err(ConfigError::Io)?)
+}
+
+/// `load_config`, falling back to the defaults when the file doesn't exist.
+/// For the implicit `default_config_path`.
+pub fn load_config_or_default(path: &Path) -> Result<KeyBindings, ConfigError> {
+    match load_config(path) {
+        Err(ConfigError::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(KeyBindings::default()),
+        result => result,
+    }
+}
+
+/// `$XDG_CONFIG_HOME/vines/config.toml`, or `~/.config/vines/config.toml`.
+pub fn default_config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("vines").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_overrides_listed_buttons() {
+        let text = "# Player 1\n[keys]\na = \"K\"\nstart = \"Keypad Enter\"  # numpad\n";
+        let bindings = KeyBindings::parse(text).unwrap();
+        assert_eq!(bindings.a, "K");
+        assert_eq!(bindings.start, "Keypad Enter");
+        assert_eq!(bindings.b, "X");
+        assert_eq!(KeyBindings::parse("").unwrap(), KeyBindings::default());
+
+        assert!(matches!(KeyBindings::parse("[keys]\nturbo = \"T\""), Err(ConfigError::Parse(_))));
+        assert!(matches!(KeyBindings::parse("[keys]\na = K"), Err(ConfigError::Parse(_))));
+        // Bindings belong in [keys], not at the top level or in another table
+        assert!(matches!(KeyBindings::parse("a = \"K\""), Err(ConfigError::Parse(_))));
+        assert!(matches!(KeyBindings::parse("[player2]\na = \"K\""), Err(ConfigError::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_accepts_any_toml_string() {
+        let text = "[keys]\nselect = \"#\"  # the hash key\nb = '\\'\nstart = \"\\\"\"";
+        let bindings = KeyBindings::parse(text).unwrap();
+        assert_eq!((bindings.select.as_str(), bindings.b.as_str(), bindings.start.as_str()), ("#", "\\", "\""));
+    }
+
+    #[test]
+    fn test_missing_config_uses_defaults_only_when_implicit() {
+        let path = std::env::temp_dir().join("vines-test-no-such-config.toml");
+        assert_eq!(load_config_or_default(&path).unwrap(), KeyBindings::default());
+        assert!(matches!(load_config(&path), Err(ConfigError::Io(_))));
+    }
+}
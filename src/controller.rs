@@ -1,3 +1,5 @@
+use crate::ppu::Ppu;
+
 pub const BUTTON_A: u8 = 0b0000_0001;
 pub const BUTTON_B: u8 = 0b0000_0010;
 pub const BUTTON_SELECT: u8 = 0b0000_0100;
@@ -8,6 +10,7 @@ pub const BUTTON_LEFT: u8 = 0b0100_0000;
 pub const BUTTON_RIGHT: u8 = 0b1000_0000;
 
 #[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub struct Controller {
     pub buttons: u8,
     strobe: bool,
@@ -41,13 +44,70 @@ impl Controller {
     }
 
     pub fn read(&mut self) -> u8 {
-        if self.strobe {
-            return self.buttons & 1;
+        let val = self.peek();
+        if !self.strobe {
+            self.shift_register >>= 1;
         }
-        let val = self.shift_register & 1;
-        self.shift_register >>= 1;
         val
     }
+
+    /// The bit the next `read` returns, without shifting.
+    pub fn peek(&self) -> u8 {
+        if self.strobe {
+            self.buttons & 1
+        } else {
+            self.shift_register & 1
+        }
+    }
+}
+
+/// RGB sum above which the Zapper's photodiode sees light.
+pub const ZAPPER_LIGHT_THRESHOLD: u16 = 600;
+
+/// Scanlines after the beam draws the aimed pixel during which the
+/// photodiode still reacts to it.
+pub const ZAPPER_SENSE_LINES: u16 = 20;
+
+/// NES Zapper light gun on controller port 2. The frontend aims it at the
+/// pixel under the mouse and pulls the trigger on click.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct Zapper {
+    pub trigger: bool,
+    /// Pixel the gun points at.
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn aim(&mut self, x: usize, y: usize) {
+        (self.x, self.y) = (x, y);
+    }
+
+    /// Whether the sensor sees light: the PPU has to have finished the aimed
+    /// scanline of the frame it is drawing within the last
+    /// `ZAPPER_SENSE_LINES` lines, and the pixel has to be bright enough.
+    pub fn sees_light(&self, ppu: &Ppu) -> bool {
+        let y = self.y as u16;
+        let just_drawn = ppu.scanline > y
+            && ppu.scanline - y <= ZAPPER_SENSE_LINES
+            && ppu.scanline < ppu.region.vblank_scanline();
+        let (r, g, b) = ppu.frame.pixel(self.x, self.y);
+        just_drawn && r as u16 + g as u16 + b as u16 > ZAPPER_LIGHT_THRESHOLD
+    }
+
+    /// $4017 as seen with the Zapper plugged in: bit 3 is the sensor, which
+    /// reads 0 while it sees light, and bit 4 is the trigger. Reads have no
+    /// side effects.
+    pub fn read(&self, ppu: &Ppu) -> u8 {
+        let no_light = if self.sees_light(ppu) { 0 } else { 0x08 };
+        let trigger = if self.trigger { 0x10 } else { 0 };
+        no_light | trigger
+    }
 }
 
 #[cfg(test)]
@@ -96,4 +156,72 @@ mod tests {
         // After 8 reads, shift register is 0
         assert_eq!(ctrl.read(), 0);
     }
+
+    #[test]
+    fn test_peek_does_not_shift() {
+        let mut ctrl = Controller::new();
+        ctrl.buttons = 0b0000_0010; // B
+        ctrl.write(1);
+        assert_eq!(ctrl.peek(), 0);
+        ctrl.write(0);
+
+        assert_eq!(ctrl.peek(), 0);
+        assert_eq!(ctrl.peek(), 0);
+        assert_eq!(ctrl.read(), 0); // A
+        assert_eq!(ctrl.peek(), 1);
+        assert_eq!(ctrl.read(), 1); // B
+    }
+
+    #[test]
+    fn test_zapper_trigger_and_sensor() {
+        let mut ppu = Ppu::new(vec![0; 8192], crate::cartridge::Mirroring::Horizontal);
+        ppu.frame.set_pixel(100, 50, (0xFF, 0xFF, 0xFF));
+        ppu.frame.set_pixel(101, 50, (0xBC, 0xBC, 0xBC));
+        ppu.frame.set_pixel(102, 50, (200, 200, 200));
+        ppu.frame.set_pixel(103, 50, (201, 200, 200));
+        ppu.scanline = 51;
+        let mut zapper = Zapper::new();
+
+        // Dark screen, trigger released
+        zapper.aim(0, 50);
+        assert_eq!(zapper.read(&ppu), 0x08);
+
+        // Aimed at a white box with the trigger pulled
+        zapper.aim(100, 50);
+        zapper.trigger = true;
+        assert_eq!(zapper.read(&ppu), 0x10);
+
+        // Mid-grey stays under the threshold; exactly 600 doesn't count either
+        zapper.aim(101, 50);
+        assert_eq!(zapper.read(&ppu), 0x18);
+        zapper.aim(102, 50);
+        assert!(!zapper.sees_light(&ppu));
+        zapper.aim(103, 50);
+        assert!(zapper.sees_light(&ppu));
+    }
+
+    #[test]
+    fn test_zapper_only_senses_just_drawn_lines() {
+        let mut ppu = Ppu::new(vec![0; 8192], crate::cartridge::Mirroring::Horizontal);
+        ppu.frame.set_pixel(100, 50, (0xFF, 0xFF, 0xFF));
+        ppu.frame.set_pixel(100, 239, (0xFF, 0xFF, 0xFF));
+        let mut zapper = Zapper::new();
+        let mut sees_light_on = |zapper: &Zapper, scanline| {
+            ppu.scanline = scanline;
+            zapper.sees_light(&ppu)
+        };
+
+        // Not drawn yet, still drawing, and long gone
+        zapper.aim(100, 50);
+        assert!(!sees_light_on(&zapper, 10));
+        assert!(!sees_light_on(&zapper, 50));
+        assert!(sees_light_on(&zapper, 51));
+        assert!(sees_light_on(&zapper, 50 + ZAPPER_SENSE_LINES));
+        assert!(!sees_light_on(&zapper, 51 + ZAPPER_SENSE_LINES));
+
+        // Once the frame is swapped out at vblank, the back buffer is stale
+        zapper.aim(100, 239);
+        assert!(sees_light_on(&zapper, 240));
+        assert!(!sees_light_on(&zapper, 241));
+    }
 }
@@ -1,4 +1,4 @@
-use crate::bus::Bus;
+use crate::bus::{Bus, ReadKind};
 use super::Cpu;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -52,19 +52,13 @@ pub fn resolve(cpu: &mut Cpu, bus: &mut Bus, mode: AddressingMode) -> (u16, u8)
             let lo = bus.cpu_read(cpu.pc) as u16;
             let hi = bus.cpu_read(cpu.pc.wrapping_add(1)) as u16;
             cpu.pc = cpu.pc.wrapping_add(2);
-            let base = (hi << 8) | lo;
-            let addr = base.wrapping_add(cpu.x as u16);
-            let extra = if Cpu::pages_differ(base, addr) { 1 } else { 0 };
-            (addr, extra)
+            index(bus, (hi << 8) | lo, cpu.x)
         }
         AddressingMode::AbsoluteY => {
             let lo = bus.cpu_read(cpu.pc) as u16;
             let hi = bus.cpu_read(cpu.pc.wrapping_add(1)) as u16;
             cpu.pc = cpu.pc.wrapping_add(2);
-            let base = (hi << 8) | lo;
-            let addr = base.wrapping_add(cpu.y as u16);
-            let extra = if Cpu::pages_differ(base, addr) { 1 } else { 0 };
-            (addr, extra)
+            index(bus, (hi << 8) | lo, cpu.y)
         }
         AddressingMode::Indirect => {
             // Only used by JMP - handled inline in CPU, but provide for completeness
@@ -90,10 +84,7 @@ pub fn resolve(cpu: &mut Cpu, bus: &mut Bus, mode: AddressingMode) -> (u16, u8)
             cpu.pc = cpu.pc.wrapping_add(1);
             let lo = bus.cpu_read(ptr as u16) as u16;
             let hi = bus.cpu_read(ptr.wrapping_add(1) as u16) as u16;
-            let base = (hi << 8) | lo;
-            let addr = base.wrapping_add(cpu.y as u16);
-            let extra = if Cpu::pages_differ(base, addr) { 1 } else { 0 };
-            (addr, extra)
+            index(bus, (hi << 8) | lo, cpu.y)
         }
         AddressingMode::Relative | AddressingMode::Implied | AddressingMode::Accumulator | AddressingMode::None => {
             // These don't resolve to a memory address through the normal path
@@ -101,3 +92,15 @@ pub fn resolve(cpu: &mut Cpu, bus: &mut Bus, mode: AddressingMode) -> (u16, u8)
         }
     }
 }
+
+/// `base + index` for the indexed modes, with the extra cycle when it crosses
+/// a page. The CPU adds the index to the low byte first and fixes the high
+/// byte a cycle later, reading from the uncorrected address in between.
+fn index(bus: &mut Bus, base: u16, index: u8) -> (u16, u8) {
+    let addr = base.wrapping_add(index as u16);
+    if !Cpu::pages_differ(base, addr) {
+        return (addr, 0);
+    }
+    bus.cpu_read_typed((base & 0xFF00) | (addr & 0x00FF), ReadKind::Dummy);
+    (addr, 1)
+}
@@ -0,0 +1,133 @@
+/// PC breakpoints checked by `Cpu::step` before each instruction.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Debugger {
+    breakpoints: Vec<u16>,
+    // Breakpoint the CPU is stopped at; the next step runs that instruction
+    // instead of firing again, so execution can resume past it.
+    hit: Option<u16>,
+}
+
+impl Debugger {
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        if !self.breakpoints.contains(&addr) {
+            self.breakpoints.push(addr);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.retain(|&bp| bp != addr);
+    }
+
+    pub fn is_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    pub fn breakpoints(&self) -> &[u16] {
+        &self.breakpoints
+    }
+
+    /// The breakpoint execution is currently stopped at, if any.
+    pub fn hit(&self) -> Option<u16> {
+        self.hit
+    }
+
+    /// Called with the PC before each instruction. Returns true if execution
+    /// should stop there.
+    pub(super) fn check(&mut self, pc: u16) -> bool {
+        if self.hit == Some(pc) || !self.is_breakpoint(pc) {
+            self.hit = None;
+            return false;
+        }
+        self.hit = Some(pc);
+        true
+    }
+}
+
+/// Write watchpoints checked by `Bus::cpu_write`. `Nes` polls the trigger
+/// after each step and stops the frame on it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Watchpoint {
+    addrs: Vec<u16>,
+    // Address and value of the last watched write since the trigger was cleared.
+    triggered: Option<(u16, u8)>,
+}
+
+impl Watchpoint {
+    pub fn add(&mut self, addr: u16) {
+        if !self.addrs.contains(&addr) {
+            self.addrs.push(addr);
+        }
+    }
+
+    pub fn remove(&mut self, addr: u16) {
+        self.addrs.retain(|&a| a != addr);
+    }
+
+    pub fn is_watched(&self, addr: u16) -> bool {
+        self.addrs.contains(&addr)
+    }
+
+    pub fn addrs(&self) -> &[u16] {
+        &self.addrs
+    }
+
+    pub fn watchpoint_triggered(&self) -> bool {
+        self.triggered.is_some()
+    }
+
+    /// The watched write that stopped execution, as `(addr, value)`.
+    pub fn triggered(&self) -> Option<(u16, u8)> {
+        self.triggered
+    }
+
+    pub(crate) fn clear_trigger(&mut self) {
+        self.triggered = None;
+    }
+
+    /// Called with every CPU bus write.
+    pub(crate) fn check_write(&mut self, addr: u16, val: u8) {
+        if self.is_watched(addr) {
+            self.triggered = Some((addr, val));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breakpoint_fires_once_then_resumes() {
+        let mut debugger = Debugger::default();
+        debugger.add_breakpoint(0xC000);
+        debugger.add_breakpoint(0xC000);
+        assert_eq!(debugger.breakpoints(), &[0xC000]);
+
+        assert!(!debugger.check(0xBFFF));
+        assert!(debugger.check(0xC000));
+        assert_eq!(debugger.hit(), Some(0xC000));
+        assert!(!debugger.check(0xC000)); // resuming runs the instruction
+        assert_eq!(debugger.hit(), None);
+        assert!(debugger.check(0xC000)); // and it fires again next time around
+
+        debugger.remove_breakpoint(0xC000);
+        assert!(!debugger.is_breakpoint(0xC000));
+    }
+
+    #[test]
+    fn test_watchpoint_records_watched_writes() {
+        let mut watch = Watchpoint::default();
+        watch.add(0x0300);
+        watch.check_write(0x0301, 1);
+        assert!(!watch.watchpoint_triggered());
+
+        watch.check_write(0x0300, 0x42);
+        assert_eq!(watch.triggered(), Some((0x0300, 0x42)));
+        watch.clear_trigger();
+
+        watch.remove(0x0300);
+        watch.check_write(0x0300, 0x43);
+        assert_eq!(watch.triggered(), None);
+        assert!(watch.addrs().is_empty());
+    }
+}
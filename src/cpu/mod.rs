@@ -1,12 +1,18 @@
 pub mod opcodes;
 pub mod addressing;
 pub mod trace;
+pub mod debugger;
 
 use bitflags::bitflags;
-use crate::bus::Bus;
+use crate::bus::{Bus, ReadKind};
+use debugger::Debugger;
+
+const NMI_VECTOR: u16 = 0xFFFA;
+const IRQ_VECTOR: u16 = 0xFFFE;
 
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
     pub struct CpuFlags: u8 {
         const CARRY     = 0b0000_0001;
         const ZERO      = 0b0000_0010;
@@ -19,7 +25,67 @@ bitflags! {
     }
 }
 
+impl CpuFlags {
+    /// Bit 5 has no latch on the 6502 and always reads back as 1.
+    pub const ALWAYS_SET: CpuFlags = CpuFlags::BREAK2;
+}
+
+/// Which 2A03 flavor the CPU core models.
+///
+/// # CPU quirks
+///
+/// The 2A03 is an NMOS 6502 with the decimal-mode circuitry cut out:
+///
+/// 1. No decimal mode. SED/CLD still toggle the D flag, but ADC and SBC always
+///    do binary arithmetic.
+/// 2. No PHX/PHY/PLX/PLY. On the 65C02 those are $DA/$5A/$FA/$7A; here they
+///    are one-byte NOPs.
+/// 3. No STZ, TRB or TSB. Their 65C02 opcodes decode to NOPs or to the
+///    unofficial SHY/SHX stores.
+///
+/// NMOS bugs that games rely on ARE replicated: `JMP ($xxFF)` fetches the high
+/// byte from $xx00 instead of crossing the page, and SBC sets V the same way
+/// ADC does on the complemented operand. The CMOS 65C02 fixes for these (and
+/// its extra cycle on page-crossing JMP) are NOT modeled.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub enum CpuVariant {
+    #[default]
+    Nes2A03,
+    /// Same instruction set and the same binary-only ADC/SBC; selectable so
+    /// callers can name the chip revision they're emulating.
+    Ricoh2A03E,
+}
+
+/// How many times each opcode has executed (`profiling` feature).
+#[cfg(feature = "profiling")]
+#[derive(Debug, Clone)]
+pub struct CpuProfiler {
+    pub counts: [u64; 256],
+}
+
+#[cfg(feature = "profiling")]
+impl Default for CpuProfiler {
+    fn default() -> Self {
+        CpuProfiler { counts: [0; 256] }
+    }
+}
+
+#[cfg(feature = "profiling")]
+impl CpuProfiler {
+    /// `(opcode, count, mnemonic)` for every opcode that ran, most frequent first.
+    pub fn report(&self) -> Vec<(u8, u64, &'static str)> {
+        let mut report: Vec<_> = (0..=255u8)
+            .map(|opcode| (opcode, self.counts[opcode as usize], opcodes::OPCODES[opcode as usize].mnemonic))
+            .filter(|&(_, count, _)| count > 0)
+            .collect();
+        report.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        report
+    }
+}
+
 #[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cpu {
     pub a: u8,
     pub x: u8,
@@ -29,6 +95,47 @@ pub struct Cpu {
     pub status: CpuFlags,
     pub cycles: u64,
     pub stall: u16,
+    /// `cycles` at the last `mark_reset_cycles`. A debugging aid, so save
+    /// states leave it and `last_instruction_cycles` out.
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    reset_cycle_mark: u64,
+    /// Cycles taken by the most recent instruction, page-cross and branch
+    /// penalties included; stalls and interrupt sequences don't update it.
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    pub last_instruction_cycles: u8,
+    pub halted: bool, // set by a KIL/JAM opcode; only reset (or un_halt) recovers
+    irq_flag_was_set_this_instruction: bool,
+    /// An NMI edge not serviced yet; taken before the next instruction.
+    pub nmi_pending: bool,
+    /// Level of the IRQ line at the end of the last instruction.
+    pub irq_pending: bool,
+    /// The last step fetched the BRK/IRQ vector, which a late NMI can hijack.
+    vectored_irq: bool,
+    pub variant: CpuVariant,
+    // A debugging aid rather than machine state, so save states leave it out
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    pub debugger: Debugger,
+    #[cfg(feature = "profiling")]
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    pub profiler: CpuProfiler,
+}
+
+/// Outcome of `Cpu::step`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepResult {
+    /// An instruction ran (or the CPU stalled or stayed jammed) for this many cycles.
+    Normal(u8),
+    /// Stopped before executing the instruction at this breakpoint.
+    Breakpoint(u16),
+}
+
+impl StepResult {
+    pub fn cycles(self) -> u8 {
+        match self {
+            StepResult::Normal(cycles) => cycles,
+            StepResult::Breakpoint(_) => 0,
+        }
+    }
 }
 
 impl Default for Cpu {
@@ -45,50 +152,141 @@ impl Cpu {
             y: 0,
             sp: 0xFD,
             pc: 0,
-            status: CpuFlags::from_bits_truncate(0x24), // IRQ disabled, BREAK2 set
+            status: CpuFlags::ALWAYS_SET | CpuFlags::IRQ_DIS,
             cycles: 0,
             stall: 0,
+            reset_cycle_mark: 0,
+            last_instruction_cycles: 0,
+            halted: false,
+            irq_flag_was_set_this_instruction: false,
+            nmi_pending: false,
+            irq_pending: false,
+            vectored_irq: false,
+            variant: CpuVariant::default(),
+            debugger: Debugger::default(),
+            #[cfg(feature = "profiling")]
+            profiler: CpuProfiler::default(),
         }
     }
 
-    pub fn reset(&mut self, bus: &mut Bus) {
+    /// Select the CPU variant to model. See [`CpuVariant`] for the differences.
+    pub fn configure(&mut self, variant: CpuVariant) {
+        self.variant = variant;
+    }
+
+    /// Registers as they come up at power-on, then the reset sequence.
+    pub fn power_on_reset(&mut self, bus: &mut Bus) {
         self.a = 0;
         self.x = 0;
         self.y = 0;
         self.sp = 0xFD;
-        self.status = CpuFlags::from_bits_truncate(0x24);
+        self.status = CpuFlags::ALWAYS_SET | CpuFlags::IRQ_DIS;
+        self.halted = false;
+        self.nmi_pending = false;
+        self.irq_pending = false;
+        self.pc = Self::reset_vector(bus);
+        self.cycles = 7;
+        self.reset_cycle_mark = 0;
+    }
+
+    /// The reset button. A, X and Y keep their values; the reset sequence runs
+    /// like an interrupt whose three stack pushes are reads, so SP drops by 3
+    /// with nothing written, and I is forced on.
+    pub fn reset(&mut self, bus: &mut Bus) {
+        self.sp = self.sp.wrapping_sub(3);
+        self.status.insert(CpuFlags::ALWAYS_SET | CpuFlags::IRQ_DIS);
+        self.halted = false;
+        self.nmi_pending = false;
+        self.irq_pending = false;
+        self.pc = Self::reset_vector(bus);
+        self.cycles += 7;
+    }
 
+    fn reset_vector(bus: &mut Bus) -> u16 {
         let lo = bus.cpu_read(0xFFFC) as u16;
         let hi = bus.cpu_read(0xFFFD) as u16;
-        self.pc = (hi << 8) | lo;
-        self.cycles = 7;
+        (hi << 8) | lo
     }
 
-    pub fn nmi(&mut self, bus: &mut Bus) {
-        self.push_u16(bus, self.pc);
-        let flags = (self.status.bits() | 0x20) & !0x10; // set bit 5, clear bit 4
-        self.push(bus, flags);
-        self.status.insert(CpuFlags::IRQ_DIS);
+    /// The status register as the hardware holds it: bit 5 always set, and no B
+    /// flag (B only exists in the copy pushed to the stack by BRK/PHP).
+    pub fn status_hardware_bits(&self) -> CpuFlags {
+        (self.status | CpuFlags::ALWAYS_SET) - CpuFlags::BREAK
+    }
+
+    /// Start measuring cycles from here; see `cycles_since_mark`.
+    pub fn mark_reset_cycles(&mut self) {
+        self.reset_cycle_mark = self.cycles;
+    }
+
+    /// Cycles run since the last `mark_reset_cycles`, or since power-on.
+    pub fn cycles_since_mark(&self) -> u64 {
+        self.cycles - self.reset_cycle_mark
+    }
+
+    /// Resume execution after a KIL opcode without a full reset.
+    pub fn un_halt(&mut self) {
+        self.halted = false;
+    }
 
-        let lo = bus.cpu_read(0xFFFA) as u16;
-        let hi = bus.cpu_read(0xFFFB) as u16;
-        self.pc = (hi << 8) | lo;
+    pub fn nmi(&mut self, bus: &mut Bus) {
+        self.interrupt(bus, NMI_VECTOR, false);
         self.cycles += 7;
     }
 
     pub fn irq(&mut self, bus: &mut Bus) {
-        if self.status.contains(CpuFlags::IRQ_DIS) {
+        if !self.irq_allowed() {
             return;
         }
+        self.interrupt(bus, IRQ_VECTOR, false);
+        self.cycles += 7;
+    }
+
+    /// The 6502 polls for IRQs before the last cycle of an instruction, so an SEI
+    /// that just executed hasn't masked the poll yet: use the I flag from before it.
+    fn irq_allowed(&self) -> bool {
+        !self.status.contains(CpuFlags::IRQ_DIS) || self.irq_flag_was_set_this_instruction
+    }
+
+    /// Whether the next `step` runs an instruction, rather than a DMA stall
+    /// cycle, a jammed cycle or an interrupt sequence. Breakpoints aren't
+    /// considered.
+    pub fn next_step_is_instruction(&self) -> bool {
+        !(self.halted || self.stall > 0 || self.nmi_pending || (self.irq_pending && self.irq_allowed()))
+    }
+
+    /// The NMI line rose `cycle` cycles into the last step. In the first four
+    /// cycles of BRK or an IRQ it hijacks that sequence: the return address and
+    /// status (B included, for BRK) are pushed as usual, but the vector comes
+    /// from $FFFA. Otherwise the NMI is taken before the next instruction.
+    /// Returns true if it hijacked.
+    pub fn signal_nmi(&mut self, bus: &mut Bus, cycle: u32) -> bool {
+        if self.vectored_irq && cycle < 4 {
+            self.vectored_irq = false;
+            self.pc = Self::read_vector(bus, NMI_VECTOR);
+            true
+        } else {
+            self.nmi_pending = true;
+            false
+        }
+    }
+
+    /// Push PC and status, set I, and jump through `vector`. B is only set in
+    /// the pushed status for BRK.
+    fn interrupt(&mut self, bus: &mut Bus, vector: u16, brk: bool) {
         self.push_u16(bus, self.pc);
-        let flags = (self.status.bits() | 0x20) & !0x10;
+        let flags = (self.status.bits() | 0x20) & !0x10 | if brk { 0x10 } else { 0 };
         self.push(bus, flags);
         self.status.insert(CpuFlags::IRQ_DIS);
+        self.irq_flag_was_set_this_instruction = false;
+        self.pc = Self::read_vector(bus, vector);
+        self.vectored_irq = vector == IRQ_VECTOR;
+    }
 
-        let lo = bus.cpu_read(0xFFFE) as u16;
-        let hi = bus.cpu_read(0xFFFF) as u16;
-        self.pc = (hi << 8) | lo;
-        self.cycles += 7;
+    fn read_vector(bus: &mut Bus, vector: u16) -> u16 {
+        let lo = bus.cpu_read(vector) as u16;
+        let hi = bus.cpu_read(vector + 1) as u16;
+        (hi << 8) | lo
     }
 
     fn push(&mut self, bus: &mut Bus, val: u8) {
@@ -112,6 +310,15 @@ impl Cpu {
         (hi << 8) | lo
     }
 
+    /// Operand read of a read-modify-write instruction. The 6502 writes the
+    /// unmodified value back while it computes the result, so the target sees
+    /// two writes on consecutive cycles: this one, then the result.
+    fn read_for_modify(bus: &mut Bus, addr: u16) -> u8 {
+        let val = bus.cpu_read(addr);
+        bus.cpu_write(addr, val);
+        val
+    }
+
     fn update_zero_negative(&mut self, val: u8) {
         self.status.set(CpuFlags::ZERO, val == 0);
         self.status.set(CpuFlags::NEGATIVE, val & 0x80 != 0);
@@ -134,20 +341,66 @@ impl Cpu {
         }
     }
 
-    pub fn step(&mut self, bus: &mut Bus) -> u8 {
+    pub fn step(&mut self, bus: &mut Bus) -> StepResult {
+        if self.halted {
+            self.cycles += 1;
+            return StepResult::Normal(1);
+        }
+
         if self.stall > 0 {
             self.stall -= 1;
             self.cycles += 1;
-            return 1;
+            return StepResult::Normal(1);
+        }
+
+        // Interrupts are serviced between instructions, NMI first
+        self.vectored_irq = false;
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.nmi(bus);
+            return StepResult::Normal(7);
+        }
+        if self.irq_pending && self.irq_allowed() {
+            self.irq(bus);
+            return StepResult::Normal(7);
+        }
+
+        if self.debugger.check(self.pc) {
+            return StepResult::Breakpoint(self.pc);
         }
 
-        let opcode = bus.cpu_read(self.pc);
+        self.irq_flag_was_set_this_instruction = false;
+
+        let opcode = bus.cpu_read_typed(self.pc, ReadKind::Instruction);
         self.pc = self.pc.wrapping_add(1);
+        #[cfg(feature = "profiling")]
+        {
+            self.profiler.counts[opcode as usize] += 1;
+        }
 
         let (cycles, extra) = self.execute(bus, opcode);
         let total = cycles + extra;
         self.cycles += total as u64;
-        total
+        self.last_instruction_cycles = total;
+        StepResult::Normal(total)
+    }
+
+    /// Check whether the instruction at PC branches or jumps to itself, the idle loop
+    /// test ROMs typically end in: `JMP *`, or `BEQ *`/`BNE *` when the branch is taken.
+    pub fn is_in_infinite_loop(&self, bus: &Bus) -> bool {
+        let opcode = bus.peek(self.pc);
+        match opcode {
+            0x4C => {
+                let lo = bus.peek(self.pc.wrapping_add(1)) as u16;
+                let hi = bus.peek(self.pc.wrapping_add(2)) as u16;
+                (hi << 8) | lo == self.pc
+            }
+            0xF0 | 0xD0 => {
+                let taken = self.status.contains(CpuFlags::ZERO) == (opcode == 0xF0);
+                taken && bus.peek(self.pc.wrapping_add(1)) == 0xFE
+            }
+            _ => false,
+        }
     }
 
     fn execute(&mut self, bus: &mut Bus, opcode: u8) -> (u8, u8) {
@@ -250,7 +503,7 @@ impl Cpu {
             }
             0x06 | 0x16 | 0x0E | 0x1E => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let mut val = bus.cpu_read(addr);
+                let mut val = Self::read_for_modify(bus, addr);
                 let carry = val & 0x80 != 0;
                 val <<= 1;
                 bus.cpu_write(addr, val);
@@ -268,7 +521,7 @@ impl Cpu {
             }
             0x46 | 0x56 | 0x4E | 0x5E => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let mut val = bus.cpu_read(addr);
+                let mut val = Self::read_for_modify(bus, addr);
                 let carry = val & 0x01 != 0;
                 val >>= 1;
                 bus.cpu_write(addr, val);
@@ -287,7 +540,7 @@ impl Cpu {
             }
             0x26 | 0x36 | 0x2E | 0x3E => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let mut val = bus.cpu_read(addr);
+                let mut val = Self::read_for_modify(bus, addr);
                 let old_carry = self.status.contains(CpuFlags::CARRY) as u8;
                 let new_carry = val & 0x80 != 0;
                 val = (val << 1) | old_carry;
@@ -307,7 +560,7 @@ impl Cpu {
             }
             0x66 | 0x76 | 0x6E | 0x7E => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let mut val = bus.cpu_read(addr);
+                let mut val = Self::read_for_modify(bus, addr);
                 let old_carry = self.status.contains(CpuFlags::CARRY) as u8;
                 let new_carry = val & 0x01 != 0;
                 val = (val >> 1) | (old_carry << 7);
@@ -342,7 +595,7 @@ impl Cpu {
             // === INC ===
             0xE6 | 0xF6 | 0xEE | 0xFE => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let val = bus.cpu_read(addr).wrapping_add(1);
+                let val = Self::read_for_modify(bus, addr).wrapping_add(1);
                 bus.cpu_write(addr, val);
                 self.update_zero_negative(val);
                 (info.cycles, 0)
@@ -350,7 +603,7 @@ impl Cpu {
             // === DEC ===
             0xC6 | 0xD6 | 0xCE | 0xDE => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let val = bus.cpu_read(addr).wrapping_sub(1);
+                let val = Self::read_for_modify(bus, addr).wrapping_sub(1);
                 bus.cpu_write(addr, val);
                 self.update_zero_negative(val);
                 (info.cycles, 0)
@@ -447,7 +700,11 @@ impl Cpu {
             0xB8 => { self.status.remove(CpuFlags::OVERFLOW); (info.cycles, 0) } // CLV
             0x38 => { self.status.insert(CpuFlags::CARRY); (info.cycles, 0) }    // SEC
             0xF8 => { self.status.insert(CpuFlags::DECIMAL); (info.cycles, 0) }  // SED
-            0x78 => { self.status.insert(CpuFlags::IRQ_DIS); (info.cycles, 0) }  // SEI
+            0x78 => { // SEI
+                self.irq_flag_was_set_this_instruction = !self.status.contains(CpuFlags::IRQ_DIS);
+                self.status.insert(CpuFlags::IRQ_DIS);
+                (info.cycles, 0)
+            }
 
             // === BIT ===
             0x24 | 0x2C => {
@@ -462,14 +719,7 @@ impl Cpu {
             // === BRK ===
             0x00 => {
                 self.pc = self.pc.wrapping_add(1); // BRK skips the byte after it
-                self.push_u16(bus, self.pc);
-                let flags = self.status.bits() | 0x30; // set B and bit 5
-                self.push(bus, flags);
-                self.status.insert(CpuFlags::IRQ_DIS);
-
-                let lo = bus.cpu_read(0xFFFE) as u16;
-                let hi = bus.cpu_read(0xFFFF) as u16;
-                self.pc = (hi << 8) | lo;
+                self.interrupt(bus, IRQ_VECTOR, true);
                 (info.cycles, 0)
             }
 
@@ -518,7 +768,7 @@ impl Cpu {
             // DCP (DEC + CMP)
             0xC7 | 0xD7 | 0xCF | 0xDF | 0xDB | 0xC3 | 0xD3 => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let val = bus.cpu_read(addr).wrapping_sub(1);
+                let val = Self::read_for_modify(bus, addr).wrapping_sub(1);
                 bus.cpu_write(addr, val);
                 self.compare(self.a, val);
                 (info.cycles, 0)
@@ -526,7 +776,7 @@ impl Cpu {
             // ISB/ISC (INC + SBC)
             0xE7 | 0xF7 | 0xEF | 0xFF | 0xFB | 0xE3 | 0xF3 => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let val = bus.cpu_read(addr).wrapping_add(1);
+                let val = Self::read_for_modify(bus, addr).wrapping_add(1);
                 bus.cpu_write(addr, val);
                 self.sbc(val);
                 (info.cycles, 0)
@@ -534,7 +784,7 @@ impl Cpu {
             // SLO (ASL + ORA)
             0x07 | 0x17 | 0x0F | 0x1F | 0x1B | 0x03 | 0x13 => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let mut val = bus.cpu_read(addr);
+                let mut val = Self::read_for_modify(bus, addr);
                 self.status.set(CpuFlags::CARRY, val & 0x80 != 0);
                 val <<= 1;
                 bus.cpu_write(addr, val);
@@ -545,7 +795,7 @@ impl Cpu {
             // RLA (ROL + AND)
             0x27 | 0x37 | 0x2F | 0x3F | 0x3B | 0x23 | 0x33 => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let mut val = bus.cpu_read(addr);
+                let mut val = Self::read_for_modify(bus, addr);
                 let old_carry = self.status.contains(CpuFlags::CARRY) as u8;
                 self.status.set(CpuFlags::CARRY, val & 0x80 != 0);
                 val = (val << 1) | old_carry;
@@ -557,7 +807,7 @@ impl Cpu {
             // SRE (LSR + EOR)
             0x47 | 0x57 | 0x4F | 0x5F | 0x5B | 0x43 | 0x53 => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let mut val = bus.cpu_read(addr);
+                let mut val = Self::read_for_modify(bus, addr);
                 self.status.set(CpuFlags::CARRY, val & 0x01 != 0);
                 val >>= 1;
                 bus.cpu_write(addr, val);
@@ -568,7 +818,7 @@ impl Cpu {
             // RRA (ROR + ADC)
             0x67 | 0x77 | 0x6F | 0x7F | 0x7B | 0x63 | 0x73 => {
                 let (addr, _) = self.resolve_address(bus, mode);
-                let mut val = bus.cpu_read(addr);
+                let mut val = Self::read_for_modify(bus, addr);
                 let old_carry = self.status.contains(CpuFlags::CARRY) as u8;
                 self.status.set(CpuFlags::CARRY, val & 0x01 != 0);
                 val = (val >> 1) | (old_carry << 7);
@@ -576,6 +826,54 @@ impl Cpu {
                 self.adc(val);
                 (info.cycles, 0)
             }
+            // ANC (AND + set carry from bit 7)
+            0x0B | 0x2B => {
+                let (addr, _) = self.resolve_address(bus, mode);
+                self.a &= bus.cpu_read(addr);
+                self.update_zero_negative(self.a);
+                self.status.set(CpuFlags::CARRY, self.a & 0x80 != 0);
+                (info.cycles, 0)
+            }
+            // ALR (AND + LSR)
+            0x4B => {
+                let (addr, _) = self.resolve_address(bus, mode);
+                let val = self.a & bus.cpu_read(addr);
+                self.status.set(CpuFlags::CARRY, val & 0x01 != 0);
+                self.a = val >> 1;
+                self.update_zero_negative(self.a);
+                (info.cycles, 0)
+            }
+            // ARR (AND + ROR, with C = bit 6 and V = bit 6 ^ bit 5 of the result)
+            0x6B => {
+                let (addr, _) = self.resolve_address(bus, mode);
+                let val = bus.cpu_read(addr);
+                let old_carry = self.status.contains(CpuFlags::CARRY) as u8;
+                self.a = ((self.a & val) >> 1) | (old_carry << 7);
+                self.status.set(CpuFlags::CARRY, self.a & 0x40 != 0);
+                self.status.set(CpuFlags::OVERFLOW, (self.a & 0x40) ^ ((self.a & 0x20) << 1) != 0);
+                self.update_zero_negative(self.a);
+                (info.cycles, 0)
+            }
+            // AXS/SBX (X = (A & X) - operand, setting flags like CMP)
+            0xCB => {
+                let (addr, _) = self.resolve_address(bus, mode);
+                let val = bus.cpu_read(addr);
+                let and = self.a & self.x;
+                self.status.set(CpuFlags::CARRY, and >= val);
+                self.x = and.wrapping_sub(val);
+                self.update_zero_negative(self.x);
+                (info.cycles, 0)
+            }
+            // LAS (memory & SP into A, X and SP)
+            0xBB => {
+                let (addr, extra) = self.resolve_address(bus, mode);
+                let val = bus.cpu_read(addr) & self.sp;
+                self.a = val;
+                self.x = val;
+                self.sp = val;
+                self.update_zero_negative(val);
+                (info.cycles, extra)
+            }
             // SBC unofficial duplicate
             0xEB => {
                 let (addr, extra) = self.resolve_address(bus, mode);
@@ -584,6 +882,13 @@ impl Cpu {
                 (info.cycles, extra)
             }
 
+            // KIL/JAM: lock up the CPU with PC left on the offending opcode
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => {
+                self.pc = self.pc.wrapping_sub(1);
+                self.halted = true;
+                (info.cycles, 0)
+            }
+
             // Catch-all for remaining unofficial opcodes - treat as NOP
             _ => {
                 // Advance PC past operand bytes
@@ -623,3 +928,554 @@ impl Cpu {
         addressing::resolve(self, bus, mode)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{Cartridge, Mirroring};
+    use crossbeam::queue::ArrayQueue;
+    use std::sync::Arc;
+
+    /// Build a CPU and bus with `program` loaded into RAM at $0600 and PC pointing at it.
+    fn setup(program: &[u8]) -> (Cpu, Bus) {
+        let cartridge = Cartridge::nrom(vec![0; 16384], vec![0; 8192]);
+        let mut bus = Bus::new(cartridge, Arc::new(ArrayQueue::new(16)));
+        bus.ram[0x0600..0x0600 + program.len()].copy_from_slice(program);
+        let mut cpu = Cpu::new();
+        cpu.pc = 0x0600;
+        (cpu, bus)
+    }
+
+    #[test]
+    fn test_instruction_cycle_counts() {
+        // LDA #$01; LDA $0200; LDA $02FF,X
+        let (mut cpu, mut bus) = setup(&[0xA9, 0x01, 0xAD, 0x00, 0x02, 0xBD, 0xFF, 0x02]);
+        cpu.x = 1;
+        cpu.stall = 3;
+        for _ in 0..3 {
+            cpu.step(&mut bus);
+        }
+        assert_eq!(cpu.last_instruction_cycles, 0, "stall cycles aren't instructions");
+        cpu.mark_reset_cycles();
+        for expected in [2, 4, 5] {
+            cpu.step(&mut bus);
+            assert_eq!(cpu.last_instruction_cycles, expected);
+        }
+        assert_eq!(cpu.cycles_since_mark(), 11);
+
+        // BNE taken from $06FD to $070F crosses a page
+        bus.ram[0x06FD..0x06FF].copy_from_slice(&[0xD0, 0x10]);
+        cpu.pc = 0x06FD;
+        cpu.status.remove(CpuFlags::ZERO);
+        cpu.step(&mut bus);
+        assert_eq!((cpu.pc, cpu.last_instruction_cycles), (0x070F, 4));
+
+        // Powering on again starts the count over rather than underflowing
+        cpu.power_on_reset(&mut bus);
+        assert_eq!(cpu.cycles_since_mark(), 7);
+    }
+
+    #[test]
+    fn test_anc_sets_carry_from_bit7() {
+        for opcode in [0x0B, 0x2B] {
+            let (mut cpu, mut bus) = setup(&[opcode, 0xF0]);
+            cpu.a = 0x8F;
+            cpu.step(&mut bus);
+            assert_eq!(cpu.a, 0x80);
+            assert!(cpu.status.contains(CpuFlags::CARRY));
+            assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+            assert!(!cpu.status.contains(CpuFlags::ZERO));
+        }
+    }
+
+    #[test]
+    fn test_anc_clears_carry_without_bit7() {
+        for opcode in [0x0B, 0x2B] {
+            let (mut cpu, mut bus) = setup(&[opcode, 0x7F]);
+            cpu.a = 0xC3;
+            cpu.status.insert(CpuFlags::CARRY | CpuFlags::NEGATIVE);
+            assert_eq!(cpu.step(&mut bus), StepResult::Normal(2));
+            assert_eq!(cpu.a, 0x43);
+            assert_eq!(cpu.pc, 0x0602);
+            assert!(!cpu.status.contains(CpuFlags::CARRY));
+            assert!(!cpu.status.contains(CpuFlags::NEGATIVE));
+
+            // A zero result clears carry too and sets Z
+            let (mut cpu, mut bus) = setup(&[opcode, 0x00]);
+            cpu.a = 0xFF;
+            cpu.status.insert(CpuFlags::CARRY);
+            cpu.step(&mut bus);
+            assert!(!cpu.status.contains(CpuFlags::CARRY));
+            assert!(cpu.status.contains(CpuFlags::ZERO));
+        }
+    }
+
+    #[test]
+    fn test_alr_ands_then_shifts_right() {
+        let (mut cpu, mut bus) = setup(&[0x4B, 0x0F]);
+        cpu.a = 0xFF;
+        cpu.step(&mut bus);
+        assert_eq!(cpu.a, 0x07);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        assert!(!cpu.status.contains(CpuFlags::NEGATIVE));
+
+        // Bit 0 of the AND, not of A, goes to carry
+        let (mut cpu, mut bus) = setup(&[0x4B, 0xFE]);
+        cpu.a = 0x01;
+        cpu.status.insert(CpuFlags::CARRY);
+        assert_eq!(cpu.step(&mut bus), StepResult::Normal(2));
+        assert_eq!(cpu.a, 0x00);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+    }
+
+    #[test]
+    fn test_axs_subtracts_from_a_and_x() {
+        // (A, X, operand, X out, C, Z, N)
+        let cases = [
+            (0xFF, 0x0F, 0x05, 0x0A, true, false, false),
+            (0x0F, 0xF0, 0x01, 0xFF, false, false, true), // borrow
+            (0x33, 0x3F, 0x33, 0x00, true, true, false),  // equal
+            (0xF0, 0xFF, 0x70, 0x80, true, false, true),
+        ];
+        for (a, x, operand, result, carry, zero, negative) in cases {
+            let (mut cpu, mut bus) = setup(&[0xCB, operand]);
+            cpu.a = a;
+            cpu.x = x;
+            // Neither carry in, overflow nor decimal mode affect it
+            cpu.status.insert(CpuFlags::CARRY | CpuFlags::OVERFLOW | CpuFlags::DECIMAL);
+            assert_eq!(cpu.step(&mut bus), StepResult::Normal(2));
+            assert_eq!(cpu.x, result);
+            assert_eq!(cpu.a, a);
+            assert_eq!(cpu.pc, 0x0602);
+            assert_eq!(cpu.status.contains(CpuFlags::CARRY), carry);
+            assert_eq!(cpu.status.contains(CpuFlags::ZERO), zero);
+            assert_eq!(cpu.status.contains(CpuFlags::NEGATIVE), negative);
+            assert!(cpu.status.contains(CpuFlags::OVERFLOW));
+        }
+    }
+
+    #[test]
+    fn test_arr_flags() {
+        // (A, operand, carry in, result, C, V)
+        let cases = [
+            (0xFF, 0xFF, true, 0xFF, true, false),  // C = bit 6, V = bit 6 ^ bit 5
+            (0xC0, 0xFF, false, 0x60, true, false),
+            (0x80, 0xFF, false, 0x40, true, true),
+            (0x01, 0x01, false, 0x00, false, false),
+        ];
+        for (a, operand, carry_in, result, carry, overflow) in cases {
+            let (mut cpu, mut bus) = setup(&[0x6B, operand]);
+            cpu.a = a;
+            cpu.status.set(CpuFlags::CARRY, carry_in);
+            cpu.step(&mut bus);
+            assert_eq!(cpu.a, result);
+            assert_eq!(cpu.status.contains(CpuFlags::CARRY), carry);
+            assert_eq!(cpu.status.contains(CpuFlags::OVERFLOW), overflow);
+            assert_eq!(cpu.status.contains(CpuFlags::ZERO), result == 0);
+            assert_eq!(cpu.status.contains(CpuFlags::NEGATIVE), result & 0x80 != 0);
+        }
+    }
+
+    #[test]
+    fn test_las_ands_memory_with_sp() {
+        for (sp, result) in [(0xF0, 0xF0), (0x0F, 0x0F)] {
+            let (mut cpu, mut bus) = setup(&[0xBB, 0x00, 0x07]); // LAS $0700,Y
+            bus.ram[0x0700] = 0xFF;
+            cpu.sp = sp;
+            cpu.y = 0;
+            cpu.step(&mut bus);
+            assert_eq!((cpu.a, cpu.x, cpu.sp), (result, result, result));
+            assert_eq!(cpu.status.contains(CpuFlags::NEGATIVE), result & 0x80 != 0);
+        }
+    }
+
+    #[test]
+    fn test_decimal_flag_ignored_on_ricoh_variant() {
+        // With D set, ADC and SBC give the binary result and carry on both
+        // variants: $09 + $01 is $0A (not BCD $10), $FF + $01 wraps to $00
+        // with carry, and $10 - $01 is $0F (not BCD $09).
+        for variant in [CpuVariant::Nes2A03, CpuVariant::Ricoh2A03E] {
+            for (opcode, a, expected_a, expected_carry) in
+                [(0x69, 0x09, 0x0A, false), (0x69, 0xFF, 0x00, true), (0xE9, 0x10, 0x0F, true)]
+            {
+                // SED; SEC for SBC / CLC for ADC; ADC or SBC #$01
+                let set_carry = if opcode == 0xE9 { 0x38 } else { 0x18 };
+                let (mut cpu, mut bus) = setup(&[0xF8, set_carry, opcode, 0x01]);
+                cpu.configure(variant);
+                cpu.a = a;
+                for _ in 0..3 {
+                    cpu.step(&mut bus);
+                }
+                assert!(cpu.status.contains(CpuFlags::DECIMAL));
+                assert_eq!(
+                    (cpu.a, cpu.status.contains(CpuFlags::CARRY)),
+                    (expected_a, expected_carry),
+                    "{:?} opcode {:02X} with A={:02X}",
+                    variant,
+                    opcode,
+                    a
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_kil_halts_cpu() {
+        let (mut cpu, mut bus) = setup(&[0x02, 0xA9, 0x42]);
+        cpu.a = 0x11;
+        cpu.step(&mut bus);
+        assert!(cpu.halted);
+        assert_eq!(cpu.pc, 0x0600);
+
+        let status = cpu.status;
+        for _ in 0..3 {
+            assert_eq!(cpu.step(&mut bus), StepResult::Normal(1));
+        }
+        assert_eq!(cpu.pc, 0x0600);
+        assert_eq!(cpu.a, 0x11);
+        assert_eq!(cpu.status, status);
+
+        cpu.un_halt();
+        cpu.pc = 0x0601;
+        cpu.step(&mut bus);
+        assert_eq!(cpu.a, 0x42);
+    }
+
+    #[test]
+    fn test_irq_taken_right_after_sei() {
+        let (mut cpu, mut bus) = setup(&[0x78, 0xEA]); // SEI; NOP
+        cpu.status.remove(CpuFlags::IRQ_DIS);
+        cpu.step(&mut bus);
+        cpu.irq(&mut bus);
+        // Vector at $FFFE in the blank test cartridge is $0000
+        assert_eq!(cpu.pc, 0x0000);
+        assert_eq!(cpu.sp, 0xFA);
+        assert_eq!(bus.ram[0x01FD], 0x06);
+        assert_eq!(bus.ram[0x01FC], 0x01);
+    }
+
+    #[test]
+    fn test_irq_masked_one_instruction_after_sei() {
+        let (mut cpu, mut bus) = setup(&[0x78, 0xEA]); // SEI; NOP
+        cpu.status.remove(CpuFlags::IRQ_DIS);
+        cpu.step(&mut bus);
+        cpu.step(&mut bus);
+        cpu.irq(&mut bus);
+        assert_eq!(cpu.pc, 0x0602);
+        assert_eq!(cpu.sp, 0xFD);
+    }
+
+    /// `setup`, with the NMI vector pointing at $0700 and IRQ/BRK at $0800.
+    fn setup_with_vectors(program: &[u8]) -> (Cpu, Bus) {
+        let (cpu, mut bus) = setup(program);
+        let mut prg_rom = vec![0; 16384];
+        prg_rom[0x3FFA..].copy_from_slice(&[0x00, 0x07, 0x00, 0x06, 0x00, 0x08]);
+        bus.mapper = Box::new(crate::cartridge::mapper::Mapper0::new(prg_rom, vec![0; 8192], Mirroring::Horizontal));
+        (cpu, bus)
+    }
+
+    #[test]
+    fn test_nmi_during_brk_hijacks_vector() {
+        let (mut cpu, mut bus) = setup_with_vectors(&[0x00, 0xFF]); // BRK
+        cpu.step(&mut bus);
+        assert_eq!(cpu.pc, 0x0800);
+
+        assert!(cpu.signal_nmi(&mut bus, 3));
+        assert_eq!(cpu.pc, 0x0700);
+        assert!(!cpu.nmi_pending);
+        // Still BRK's stack frame: return past the padding byte, B set
+        assert_eq!(bus.ram[0x01FD], 0x06);
+        assert_eq!(bus.ram[0x01FC], 0x02);
+        assert_ne!(bus.ram[0x01FB] & 0x10, 0);
+    }
+
+    #[test]
+    fn test_nmi_after_brk_vector_fetch_runs_next() {
+        let (mut cpu, mut bus) = setup_with_vectors(&[0x00, 0xFF]); // BRK
+        cpu.step(&mut bus);
+        assert!(!cpu.signal_nmi(&mut bus, 4));
+        assert_eq!(cpu.pc, 0x0800);
+        assert!(cpu.nmi_pending);
+
+        // Taken in place of the handler's first instruction
+        assert_eq!(cpu.step(&mut bus), StepResult::Normal(7));
+        assert_eq!(cpu.pc, 0x0700);
+        assert_eq!(bus.ram[0x01FA], 0x08);
+        assert_eq!(bus.ram[0x01F9], 0x00);
+        assert_eq!(bus.ram[0x01F8] & 0x10, 0);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_irq_and_takes_priority() {
+        let (mut cpu, mut bus) = setup_with_vectors(&[0xEA]); // NOP
+        cpu.status.remove(CpuFlags::IRQ_DIS);
+        cpu.irq_pending = true;
+        cpu.step(&mut bus);
+        assert_eq!(cpu.pc, 0x0800);
+        assert!(cpu.signal_nmi(&mut bus, 0));
+        assert_eq!(cpu.pc, 0x0700);
+        assert_eq!(bus.ram[0x01FB] & 0x10, 0);
+
+        // With both pending, NMI goes first
+        let (mut cpu, mut bus) = setup_with_vectors(&[0xEA]);
+        cpu.status.remove(CpuFlags::IRQ_DIS);
+        cpu.irq_pending = true;
+        cpu.nmi_pending = true;
+        cpu.step(&mut bus);
+        assert_eq!(cpu.pc, 0x0700);
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Access {
+        Read,
+        Write(u8),
+    }
+
+    /// Cartridge-space memory that logs every CPU access to `addr`.
+    #[derive(Clone)]
+    struct BusMonitor {
+        addr: u16,
+        value: Arc<std::sync::Mutex<u8>>,
+        accesses: Arc<std::sync::Mutex<Vec<Access>>>,
+    }
+
+    impl BusMonitor {
+        fn install(bus: &mut Bus, addr: u16, value: u8) -> Self {
+            let monitor = BusMonitor { addr, value: Arc::default(), accesses: Arc::default() };
+            *monitor.value.lock().unwrap() = value;
+            bus.mapper = Box::new(monitor.clone());
+            monitor
+        }
+
+        fn accesses(&self) -> Vec<Access> {
+            self.accesses.lock().unwrap().clone()
+        }
+    }
+
+    impl crate::cartridge::mapper::Mapper for BusMonitor {
+        fn cpu_read(&self, addr: u16) -> u8 {
+            if addr != self.addr {
+                return 0;
+            }
+            self.accesses.lock().unwrap().push(Access::Read);
+            *self.value.lock().unwrap()
+        }
+        fn cpu_write(&mut self, addr: u16, val: u8) {
+            if addr == self.addr {
+                self.accesses.lock().unwrap().push(Access::Write(val));
+                *self.value.lock().unwrap() = val;
+            }
+        }
+        fn chr_read(&self, _addr: u16) -> u8 {
+            0
+        }
+        fn chr_write(&mut self, _addr: u16, _val: u8) {}
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Horizontal
+        }
+        fn clone_box(&self) -> Box<dyn crate::cartridge::mapper::Mapper> {
+            Box::new(self.clone())
+        }
+        #[cfg(feature = "save_states")]
+        fn state(&self) -> crate::cartridge::mapper::MapperState {
+            unreachable!("never saved")
+        }
+    }
+
+    #[test]
+    fn test_read_modify_write_writes_old_value_first() {
+        use addressing::AddressingMode::*;
+
+        let rmw_opcodes = [
+            0x06, 0x16, 0x0E, 0x1E, 0x46, 0x56, 0x4E, 0x5E, 0x26, 0x36, 0x2E, 0x3E, 0x66, 0x76, 0x6E, 0x7E, 0xE6,
+            0xF6, 0xEE, 0xFE, 0xC6, 0xD6, 0xCE, 0xDE, 0x07, 0x17, 0x0F, 0x1F, 0x1B, 0x03, 0x13, 0x27, 0x37, 0x2F,
+            0x3F, 0x3B, 0x23, 0x33, 0x47, 0x57, 0x4F, 0x5F, 0x5B, 0x43, 0x53, 0x67, 0x77, 0x6F, 0x7F, 0x7B, 0x63,
+            0x73, 0xC7, 0xD7, 0xCF, 0xDF, 0xDB, 0xC3, 0xD3, 0xE7, 0xF7, 0xEF, 0xFF, 0xFB, 0xE3, 0xF3,
+        ];
+        let mut checked = 0;
+        for opcode in rmw_opcodes {
+            // Point every mode that can reach cartridge space at $6010; with X
+            // and Y zero, indexing doesn't move it. $20 holds the pointer.
+            let program = match opcodes::OPCODES[opcode as usize].mode {
+                Absolute | AbsoluteX | AbsoluteY => vec![opcode, 0x10, 0x60],
+                IndirectX | IndirectY => vec![opcode, 0x20],
+                _ => continue,
+            };
+            let (mut cpu, mut bus) = setup(&program);
+            bus.ram[0x20] = 0x10;
+            bus.ram[0x21] = 0x60;
+            let monitor = BusMonitor::install(&mut bus, 0x6010, 0x41);
+            cpu.step(&mut bus);
+
+            let accesses = monitor.accesses();
+            assert_eq!(accesses.len(), 3, "opcode {:02X}: {:?}", opcode, accesses);
+            assert_eq!(accesses[..2], [Access::Read, Access::Write(0x41)], "opcode {:02X}", opcode);
+            assert!(matches!(accesses[2], Access::Write(val) if val != 0x41), "opcode {:02X}: {:?}", opcode, accesses);
+            checked += 1;
+        }
+        assert_eq!(checked, 42);
+    }
+
+    #[test]
+    fn test_read_modify_write_shifts_mmc1_with_the_old_value() {
+        use crate::cartridge::mapper::Mapper1;
+
+        // 16KB PRG banks filled with their bank number, except $8000 = $80
+        let mut prg: Vec<u8> = (0..4u8).flat_map(|bank| vec![bank; 0x4000]).collect();
+        prg[0] = 0x80;
+        // INC $8000 writes $80 back, which resets the shift register. INC
+        // $C000 reads 3 from the fixed last bank and writes it back, shifting
+        // in a 1; MMC1 drops the result written on the next cycle. Four more
+        // writes of 1, 0, 0, 0 then select PRG bank 3. Taking the result too
+        // would shift in an extra 0 and pick bank 1.
+        let (mut cpu, mut bus) = setup(&[
+            0xEE, 0x00, 0x80, // INC $8000
+            0xEE, 0x00, 0xC0, // INC $C000
+            0xA9, 0x01, 0x8D, 0x00, 0xE0, // LDA #1; STA $E000
+            0xA9, 0x00, 0x8D, 0x00, 0xE0, // LDA #0; STA $E000
+            0x8D, 0x00, 0xE0, 0x8D, 0x00, 0xE0, // STA $E000; STA $E000
+        ]);
+        bus.mapper = Box::new(Mapper1::new(prg, vec![0; 0x2000], true));
+        for _ in 0..8 {
+            cpu.step(&mut bus);
+        }
+        assert_eq!(bus.peek(0x8001), 3);
+    }
+
+    #[test]
+    fn test_infinite_loop_check_has_no_bus_side_effects() {
+        let (cpu, mut bus) = setup(&[0x4C, 0x00, 0x06]); // JMP $0600
+        bus.open_bus_latch = 0x99;
+        assert!(cpu.is_in_infinite_loop(&bus));
+        assert_eq!(bus.open_bus_latch, 0x99);
+    }
+
+    #[test]
+    fn test_page_crossing_dummy_read_leaves_open_bus() {
+        // LDA $3FF8,X with X = $20: the uncorrected address $3F18 mirrors
+        // PPUCTRL, then $4018 is unmapped and reads back open bus
+        let (mut cpu, mut bus) = setup(&[0xBD, 0xF8, 0x3F]);
+        cpu.x = 0x20;
+        bus.cpu_write(0x2003, 0x55);
+        assert_eq!(bus.ppu.cpu_read(0x2000), 0x55);
+        cpu.step(&mut bus);
+        // The last real transfer was the operand's high byte
+        assert_eq!(cpu.a, 0x3F);
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test]
+    fn test_profiler_counts_executed_opcodes() {
+        // LDX #3; loop: DEX; BNE loop; NOP
+        let (mut cpu, mut bus) = setup(&[0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0xEA]);
+        for _ in 0..8 {
+            cpu.step(&mut bus);
+        }
+        assert_eq!(cpu.profiler.counts[0xA2], 1);
+        assert_eq!(cpu.profiler.counts[0xCA], 3);
+        assert_eq!(cpu.profiler.counts[0xD0], 3);
+        assert_eq!(cpu.profiler.counts[0xEA], 1);
+        assert_eq!(
+            cpu.profiler.report(),
+            vec![(0xCA, 3, "DEX"), (0xD0, 3, "BNE"), (0xA2, 1, "LDX"), (0xEA, 1, "NOP")]
+        );
+    }
+
+    #[test]
+    fn test_mesen_status_string() {
+        assert_eq!(CpuFlags::all().to_mesen_string(), "NV-BDIZC");
+        assert_eq!(CpuFlags::from_bits_truncate(0x24).to_mesen_string(), "nv-bdIzc");
+    }
+
+    #[test]
+    fn test_trace_formats() {
+        let (mut cpu, bus) = setup(&[0xA9, 0x42]); // LDA #$42
+        cpu.status = CpuFlags::from_bits_truncate(0xE5);
+        cpu.cycles = 7;
+
+        let nestest = cpu.trace_format(&bus, trace::TraceFormat::Nestest, 241);
+        assert!(nestest.starts_with("0600  A9 42     LDA"));
+        assert!(nestest.contains("P:E5 SP:FD CYC:7"));
+        assert!(!nestest.contains("SL:"));
+        assert_eq!(nestest, cpu.trace(&bus));
+
+        let mesen = cpu.trace_format(&bus, trace::TraceFormat::Mesen, 241);
+        assert!(mesen.starts_with("0600 A9 42    LDA"));
+        assert!(mesen.contains("S:FD P:NV-bdIzC CYC:7 SL:241"));
+    }
+
+    #[test]
+    fn test_trace_operands() {
+        let (mut cpu, mut bus) = setup(&[]);
+        bus.ram[0x0010] = 0x00;
+        bus.ram[0x0011] = 0x02; // pointer $0200
+        bus.ram[0x0201] = 0x40;
+        bus.ram[0x0205] = 0x5A;
+        cpu.x = 0x01;
+        cpu.y = 0x05;
+        let cases: [(&[u8], &str); 10] = [
+            (&[0x4C, 0xF5, 0xC5], "JMP $C5F5"),
+            (&[0xA9, 0x42], "LDA #$42"),
+            (&[0xA5, 0x11], "LDA $11 = 02"),
+            (&[0xBD, 0x00, 0x02], "LDA $0200,X @ 0201 = 40"),
+            (&[0xB6, 0x0C], "LDX $0C,Y @ 11 = 02"),
+            (&[0x6C, 0x10, 0x00], "JMP ($0010) = 0200"),
+            (&[0xA1, 0x0F], "LDA ($0F,X) @ 10 = 0200 = 00"),
+            (&[0xB1, 0x10], "LDA ($10),Y = 0200 @ 0205 = 5A"),
+            (&[0xD0, 0xFE], "BNE $0600"),
+            (&[0x04, 0x11], "*NOP $11 = 02"),
+        ];
+        for (program, expected) in cases {
+            bus.ram[0x0600..0x0600 + program.len()].copy_from_slice(program);
+            let line = cpu.trace(&bus);
+            assert_eq!(line[15..48].trim(), expected);
+            assert_eq!(&line[48..50], "A:");
+        }
+        // Accumulator mode and the unofficial SBC encoding
+        bus.ram[0x0600] = 0x4A;
+        assert_eq!(cpu.trace(&bus)[15..48].trim(), "LSR A");
+        bus.ram[0x0600..0x0602].copy_from_slice(&[0xEB, 0x01]);
+        assert_eq!(cpu.trace(&bus)[15..48].trim(), "*SBC #$01");
+    }
+
+    #[test]
+    fn test_power_on_and_reset_status() {
+        let (mut cpu, mut bus) = setup(&[]);
+        assert_eq!(cpu.status.bits(), 0x24);
+        cpu.status = CpuFlags::empty();
+        cpu.reset(&mut bus);
+        assert!(cpu.status.contains(CpuFlags::IRQ_DIS));
+        assert!(cpu.status.contains(CpuFlags::ALWAYS_SET));
+        assert_eq!(cpu.status.bits(), 0x24);
+
+        cpu.status = CpuFlags::CARRY;
+        cpu.power_on_reset(&mut bus);
+        assert_eq!(cpu.status.bits(), 0x24);
+    }
+
+    #[test]
+    fn test_soft_reset_keeps_registers() {
+        let (mut cpu, mut bus) = setup(&[]);
+        (cpu.a, cpu.x, cpu.y, cpu.sp) = (0x12, 0x34, 0x56, 0xF0);
+        cpu.status = CpuFlags::ALWAYS_SET | CpuFlags::CARRY;
+        bus.ram[0x01E0..0x0200].fill(0xAA);
+        cpu.reset(&mut bus);
+        assert_eq!((cpu.a, cpu.x, cpu.y, cpu.sp), (0x12, 0x34, 0x56, 0xED));
+        assert!(cpu.status.contains(CpuFlags::CARRY | CpuFlags::IRQ_DIS));
+        assert!(bus.ram[0x01E0..0x0200].iter().all(|&b| b == 0xAA), "reset pushes don't write");
+
+        cpu.power_on_reset(&mut bus);
+        assert_eq!((cpu.a, cpu.x, cpu.y, cpu.sp), (0, 0, 0, 0xFD));
+    }
+
+    #[test]
+    fn test_status_hardware_bits() {
+        let (mut cpu, _bus) = setup(&[]);
+        cpu.status = CpuFlags::BREAK | CpuFlags::CARRY;
+        let hw = cpu.status_hardware_bits();
+        assert!(hw.contains(CpuFlags::ALWAYS_SET));
+        assert!(!hw.contains(CpuFlags::BREAK));
+        assert_eq!(hw.bits(), 0x21);
+    }
+}
@@ -7,7 +7,9 @@ pub struct OpCode {
     pub mode: AddressingMode,
 }
 
-/// All 256 opcodes. Unofficial ones are included with approximate timing.
+/// All 256 opcodes. Unofficial ones are included with approximate timing. Slots the
+/// 65C02 reuses for new instructions are marked; the 2A03 doesn't have them (see
+/// `CpuVariant`).
 pub static OPCODES: [OpCode; 256] = {
     use AddressingMode::*;
 
@@ -21,7 +23,7 @@ pub static OPCODES: [OpCode; 256] = {
         op("ORA", 2, 6, IndirectX),
         op("KIL", 1, 2, Implied),
         op("SLO", 2, 8, IndirectX),
-        op("NOP", 2, 3, ZeroPage),
+        op("NOP", 2, 3, ZeroPage), // TSB zp on the 65C02
         op("ORA", 2, 3, ZeroPage),
         op("ASL", 2, 5, ZeroPage),
         op("SLO", 2, 5, ZeroPage),
@@ -29,7 +31,7 @@ pub static OPCODES: [OpCode; 256] = {
         op("ORA", 2, 2, Immediate),
         op("ASL", 1, 2, Accumulator),
         op("ANC", 2, 2, Immediate),
-        op("NOP", 3, 4, Absolute),
+        op("NOP", 3, 4, Absolute), // TSB abs on the 65C02
         op("ORA", 3, 4, Absolute),
         op("ASL", 3, 6, Absolute),
         op("SLO", 3, 6, Absolute),
@@ -38,7 +40,7 @@ pub static OPCODES: [OpCode; 256] = {
         op("ORA", 2, 5, IndirectY),
         op("KIL", 1, 2, Implied),
         op("SLO", 2, 8, IndirectY),
-        op("NOP", 2, 4, ZeroPageX),
+        op("NOP", 2, 4, ZeroPageX), // TRB zp on the 65C02
         op("ORA", 2, 4, ZeroPageX),
         op("ASL", 2, 6, ZeroPageX),
         op("SLO", 2, 6, ZeroPageX),
@@ -46,7 +48,7 @@ pub static OPCODES: [OpCode; 256] = {
         op("ORA", 3, 4, AbsoluteY),
         op("NOP", 1, 2, Implied),
         op("SLO", 3, 7, AbsoluteY),
-        op("NOP", 3, 4, AbsoluteX),
+        op("NOP", 3, 4, AbsoluteX), // TRB abs on the 65C02
         op("ORA", 3, 4, AbsoluteX),
         op("ASL", 3, 7, AbsoluteX),
         op("SLO", 3, 7, AbsoluteX),
@@ -112,7 +114,7 @@ pub static OPCODES: [OpCode; 256] = {
         op("SRE", 2, 6, ZeroPageX),
         op("CLI", 1, 2, Implied),
         op("EOR", 3, 4, AbsoluteY),
-        op("NOP", 1, 2, Implied),
+        op("NOP", 1, 2, Implied), // PHY on the 65C02
         op("SRE", 3, 7, AbsoluteY),
         op("NOP", 3, 4, AbsoluteX),
         op("EOR", 3, 4, AbsoluteX),
@@ -123,7 +125,7 @@ pub static OPCODES: [OpCode; 256] = {
         op("ADC", 2, 6, IndirectX),
         op("KIL", 1, 2, Implied),
         op("RRA", 2, 8, IndirectX),
-        op("NOP", 2, 3, ZeroPage),
+        op("NOP", 2, 3, ZeroPage), // STZ zp on the 65C02
         op("ADC", 2, 3, ZeroPage),
         op("ROR", 2, 5, ZeroPage),
         op("RRA", 2, 5, ZeroPage),
@@ -140,13 +142,13 @@ pub static OPCODES: [OpCode; 256] = {
         op("ADC", 2, 5, IndirectY),
         op("KIL", 1, 2, Implied),
         op("RRA", 2, 8, IndirectY),
-        op("NOP", 2, 4, ZeroPageX),
+        op("NOP", 2, 4, ZeroPageX), // STZ zp,X on the 65C02
         op("ADC", 2, 4, ZeroPageX),
         op("ROR", 2, 6, ZeroPageX),
         op("RRA", 2, 6, ZeroPageX),
         op("SEI", 1, 2, Implied),
         op("ADC", 3, 4, AbsoluteY),
-        op("NOP", 1, 2, Implied),
+        op("NOP", 1, 2, Implied), // PLY on the 65C02
         op("RRA", 3, 7, AbsoluteY),
         op("NOP", 3, 4, AbsoluteX),
         op("ADC", 3, 4, AbsoluteX),
@@ -182,9 +184,9 @@ pub static OPCODES: [OpCode; 256] = {
         op("STA", 3, 5, AbsoluteY),
         op("TXS", 1, 2, Implied),
         op("TAS", 3, 5, AbsoluteY),
-        op("SHY", 3, 5, AbsoluteX),
+        op("SHY", 3, 5, AbsoluteX), // STZ abs on the 65C02
         op("STA", 3, 5, AbsoluteX),
-        op("SHX", 3, 5, AbsoluteY),
+        op("SHX", 3, 5, AbsoluteY), // STZ abs,X on the 65C02
         op("AHX", 3, 5, AbsoluteY),
         // 0xA0
         op("LDY", 2, 2, Immediate),
@@ -248,7 +250,7 @@ pub static OPCODES: [OpCode; 256] = {
         op("DCP", 2, 6, ZeroPageX),
         op("CLD", 1, 2, Implied),
         op("CMP", 3, 4, AbsoluteY),
-        op("NOP", 1, 2, Implied),
+        op("NOP", 1, 2, Implied), // PHX on the 65C02
         op("DCP", 3, 7, AbsoluteY),
         op("NOP", 3, 4, AbsoluteX),
         op("CMP", 3, 4, AbsoluteX),
@@ -282,7 +284,7 @@ pub static OPCODES: [OpCode; 256] = {
         op("ISB", 2, 6, ZeroPageX),
         op("SED", 1, 2, Implied),
         op("SBC", 3, 4, AbsoluteY),
-        op("NOP", 1, 2, Implied),
+        op("NOP", 1, 2, Implied), // PLX on the 65C02
         op("ISB", 3, 7, AbsoluteY),
         op("NOP", 3, 4, AbsoluteX),
         op("SBC", 3, 4, AbsoluteX),
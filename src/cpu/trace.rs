@@ -1,18 +1,76 @@
 use crate::bus::Bus;
-use super::Cpu;
-use super::opcodes::OPCODES;
+use super::{Cpu, CpuFlags};
+use super::addressing::AddressingMode;
+use super::opcodes::{OpCode, OPCODES};
+
+/// The 56 documented 6502 mnemonics. Anything else (and the extra NOP and SBC
+/// encodings) is shown with nestest's `*` prefix.
+const OFFICIAL_MNEMONICS: [&str; 56] = [
+    "ADC", "AND", "ASL", "BCC", "BCS", "BEQ", "BIT", "BMI", "BNE", "BPL", "BRK", "BVC", "BVS", "CLC",
+    "CLD", "CLI", "CLV", "CMP", "CPX", "CPY", "DEC", "DEX", "DEY", "EOR", "INC", "INX", "INY", "JMP",
+    "JSR", "LDA", "LDX", "LDY", "LSR", "NOP", "ORA", "PHA", "PHP", "PLA", "PLP", "ROL", "ROR", "RTI",
+    "RTS", "SBC", "SEC", "SED", "SEI", "STA", "STX", "STY", "TAX", "TAY", "TSX", "TXA", "TXS", "TYA",
+];
+
+fn is_unofficial(opcode: u8) -> bool {
+    let mnemonic = OPCODES[opcode as usize].mnemonic;
+    !OFFICIAL_MNEMONICS.contains(&mnemonic) || (mnemonic == "NOP" && opcode != 0xEA) || opcode == 0xEB
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    /// "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7"
+    Nestest,
+    /// "C000 4C F5 C5 JMP $C5F5 A:00 X:00 Y:00 S:FD P:nv-bdIzc CYC:7 SL:241"
+    Mesen,
+}
+
+impl CpuFlags {
+    /// Status as Mesen's "NV-BDIZC" string: uppercase letters for set flags,
+    /// lowercase for clear ones, and '-' for the unused bit 5.
+    pub fn to_mesen_string(&self) -> String {
+        const LETTERS: [(CpuFlags, char); 8] = [
+            (CpuFlags::NEGATIVE, 'N'),
+            (CpuFlags::OVERFLOW, 'V'),
+            (CpuFlags::BREAK2, '-'),
+            (CpuFlags::BREAK, 'B'),
+            (CpuFlags::DECIMAL, 'D'),
+            (CpuFlags::IRQ_DIS, 'I'),
+            (CpuFlags::ZERO, 'Z'),
+            (CpuFlags::CARRY, 'C'),
+        ];
+        LETTERS
+            .iter()
+            .map(|&(flag, letter)| {
+                if self.contains(flag) { letter } else { letter.to_ascii_lowercase() }
+            })
+            .collect()
+    }
+}
 
 impl Cpu {
     /// Generate a nestest-compatible trace line for the current instruction.
     /// Format: "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7"
-    pub fn trace(&self, bus: &mut Bus) -> String {
+    pub fn trace(&self, bus: &Bus) -> String {
+        self.trace_format(bus, TraceFormat::Nestest, 0)
+    }
+
+    /// Generate a trace line for the current instruction in the given format.
+    /// `ppu_scanline` is only shown by formats that include it (Mesen's `SL:`).
+    pub fn trace_format(&self, bus: &Bus, format: TraceFormat, ppu_scanline: u16) -> String {
         let pc = self.pc;
-        let opcode = bus.cpu_read(pc);
+        let opcode = bus.peek(pc);
         let info = &OPCODES[opcode as usize];
 
         let bytes: Vec<u8> = (0..info.bytes as u16)
-            .map(|i| bus.cpu_read(pc.wrapping_add(i)))
+            .map(|i| bus.peek(pc.wrapping_add(i)))
             .collect();
+        let operand = self.disassemble_operand(bus, info, &bytes);
+        let mnemonic = if is_unofficial(opcode) {
+            format!("*{}", info.mnemonic)
+        } else {
+            info.mnemonic.to_string()
+        };
 
         let hex_bytes = match info.bytes {
             1 => format!("{:02X}      ", bytes[0]),
@@ -21,18 +79,85 @@ impl Cpu {
             _ => format!("{:02X}      ", bytes[0]),
         };
 
-        format!(
-            "{:04X}  {}  {:4} {:27}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
-            pc,
-            hex_bytes,
-            info.mnemonic,
-            "", // operand disassembly placeholder
-            self.a,
-            self.x,
-            self.y,
-            self.status.bits(),
-            self.sp,
-            self.cycles,
-        )
+        match format {
+            TraceFormat::Nestest => format!(
+                "{:04X}  {} {:>4} {:27} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                pc,
+                hex_bytes,
+                mnemonic,
+                operand,
+                self.a,
+                self.x,
+                self.y,
+                self.status.bits(),
+                self.sp,
+                self.cycles,
+            ),
+            TraceFormat::Mesen => format!(
+                "{:04X} {} {:4} {:27} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{} CYC:{} SL:{}",
+                pc,
+                hex_bytes,
+                mnemonic,
+                operand,
+                self.a,
+                self.x,
+                self.y,
+                self.sp,
+                self.status.to_mesen_string(),
+                self.cycles,
+                ppu_scanline,
+            ),
+        }
+    }
+}
+
+impl Cpu {
+    /// Operand in nestest notation: the written operand, then the effective
+    /// address (for indexed and indirect modes) and the value stored there.
+    fn disassemble_operand(&self, bus: &Bus, info: &OpCode, bytes: &[u8]) -> String {
+        let byte = bytes.get(1).copied().unwrap_or(0);
+        let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or(0)]);
+        let peek_word_zp = |addr: u8| {
+            u16::from_le_bytes([bus.peek(addr as u16), bus.peek(addr.wrapping_add(1) as u16)])
+        };
+
+        match info.mode {
+            AddressingMode::Implied | AddressingMode::None => String::new(),
+            AddressingMode::Accumulator => "A".to_string(),
+            AddressingMode::Immediate => format!("#${:02X}", byte),
+            AddressingMode::ZeroPage => format!("${:02X} = {:02X}", byte, bus.peek(byte as u16)),
+            AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+                let (index, reg) = if info.mode == AddressingMode::ZeroPageX { (self.x, 'X') } else { (self.y, 'Y') };
+                let addr = byte.wrapping_add(index);
+                format!("${:02X},{} @ {:02X} = {:02X}", byte, reg, addr, bus.peek(addr as u16))
+            }
+            AddressingMode::Absolute if matches!(info.mnemonic, "JMP" | "JSR") => format!("${:04X}", word),
+            AddressingMode::Absolute => format!("${:04X} = {:02X}", word, bus.peek(word)),
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+                let (index, reg) = if info.mode == AddressingMode::AbsoluteX { (self.x, 'X') } else { (self.y, 'Y') };
+                let addr = word.wrapping_add(index as u16);
+                format!("${:04X},{} @ {:04X} = {:02X}", word, reg, addr, bus.peek(addr))
+            }
+            AddressingMode::Indirect => {
+                // JMP ($xxFF) reads the high byte from $xx00, not the next page
+                let hi_addr = (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF);
+                let target = u16::from_le_bytes([bus.peek(word), bus.peek(hi_addr)]);
+                format!("(${:04X}) = {:04X}", word, target)
+            }
+            AddressingMode::IndirectX => {
+                let pointer = byte.wrapping_add(self.x);
+                let addr = peek_word_zp(pointer);
+                format!("(${:02X},X) @ {:02X} = {:04X} = {:02X}", byte, pointer, addr, bus.peek(addr))
+            }
+            AddressingMode::IndirectY => {
+                let base = peek_word_zp(byte);
+                let addr = base.wrapping_add(self.y as u16);
+                format!("(${:02X}),Y = {:04X} @ {:04X} = {:02X}", byte, base, addr, bus.peek(addr))
+            }
+            AddressingMode::Relative => {
+                let target = self.pc.wrapping_add(2).wrapping_add(byte as i8 as u16);
+                format!("${:04X}", target)
+            }
+        }
     }
 }
@@ -0,0 +1,146 @@
+//! Known games by PRG ROM CRC32, used to correct the mapper of dumps with
+//! bad iNES headers and to pick their region.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::nes::Region;
+
+/// What the database knows about one game.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub mapper_id: u16,
+    pub submapper: u8,
+    pub region: Region,
+    pub title: Cow<'static, str>,
+}
+
+#[derive(Debug)]
+pub enum DbError {
+    Xml(roxmltree::Error),
+    /// A cartridge whose board or PRG chip is missing an attribute, or has
+    /// one that doesn't parse.
+    BadCartridge { game: String, attribute: &'static str },
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Xml(e) => write!(f, "{}", e),
+            DbError::BadCartridge { game, attribute } => write!(f, "{}: missing or invalid {}", game, attribute),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+/// Built-in entries, keyed by `cartridge::crc32` of the PRG ROM alone (the
+/// header and CHR ROM are left out). Only add dumps verified against a known
+/// good set such as No-Intro. Empty until that data lands; until then header
+/// correction needs a database loaded with [`RomDatabase::from_nescartdb`].
+const BUILTIN_ENTRIES: &[(u32, RomInfo)] = &[];
+
+pub struct RomDatabase {
+    entries: HashMap<u32, RomInfo>,
+}
+
+impl RomDatabase {
+    pub fn new(entries: impl IntoIterator<Item = (u32, RomInfo)>) -> Self {
+        RomDatabase { entries: entries.into_iter().collect() }
+    }
+
+    /// Parse a NesCartDB XML export. Every cartridge with a single PRG chip
+    /// gives an entry keyed by that chip's CRC32, which is the CRC32 of the
+    /// whole PRG ROM; boards split across several PRG chips are skipped.
+    /// NesCartDB has no submappers, so those are all 0.
+    pub fn from_nescartdb(xml: &str) -> Result<Self, DbError> {
+        let document = roxmltree::Document::parse(xml).map_err(DbError::Xml)?;
+        let mut entries = HashMap::new();
+        for game in document.descendants().filter(|n| n.has_tag_name("game")) {
+            let title = game.attribute("name").unwrap_or_default();
+            let bad = |attribute| DbError::BadCartridge { game: title.to_string(), attribute };
+            for cartridge in game.children().filter(|n| n.has_tag_name("cartridge")) {
+                let system = cartridge.attribute("system").unwrap_or_default();
+                let region = if system.contains("PAL") {
+                    Region::Pal
+                } else if system == "Dendy" {
+                    Region::Dendy
+                } else {
+                    Region::Ntsc
+                };
+                let board = cartridge.children().find(|n| n.has_tag_name("board")).ok_or_else(|| bad("board"))?;
+                let mapper_id = board.attribute("mapper").and_then(|m| m.parse().ok()).ok_or_else(|| bad("mapper"))?;
+                let mut prg = board.children().filter(|n| n.has_tag_name("prg"));
+                let (Some(chip), None) = (prg.next(), prg.next()) else { continue };
+                let crc = chip
+                    .attribute("crc")
+                    .and_then(|crc| u32::from_str_radix(crc, 16).ok())
+                    .ok_or_else(|| bad("prg crc"))?;
+                let info = RomInfo { mapper_id, submapper: 0, region, title: Cow::Owned(title.to_string()) };
+                entries.insert(crc, info);
+            }
+        }
+        Ok(RomDatabase { entries })
+    }
+
+    /// The database compiled into the emulator.
+    pub fn builtin() -> &'static RomDatabase {
+        static BUILTIN: OnceLock<RomDatabase> = OnceLock::new();
+        BUILTIN.get_or_init(|| RomDatabase::new(BUILTIN_ENTRIES.iter().cloned()))
+    }
+
+    pub fn lookup(&self, prg_crc32: u32) -> Option<&RomInfo> {
+        self.entries.get(&prg_crc32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NESCARTDB: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<database version="1.0" conformance="strict">
+  <game name="Test Game" region="USA">
+    <cartridge system="NES-NTSC" crc="01234567">
+      <board type="NES-UNROM" mapper="2">
+        <prg size="128k" crc="89ABCDEF"/>
+        <chr size="8k"/>
+      </board>
+    </cartridge>
+    <cartridge system="NES-PAL-B" crc="76543210">
+      <board type="NES-UNROM" mapper="2">
+        <prg size="128k" crc="FEDCBA98"/>
+      </board>
+    </cartridge>
+  </game>
+  <game name="Split PRG">
+    <cartridge system="Famicom">
+      <board mapper="0">
+        <prg size="16k" crc="11111111"/>
+        <prg size="16k" crc="22222222"/>
+      </board>
+    </cartridge>
+  </game>
+</database>"#;
+
+    #[test]
+    fn test_nescartdb_entries_keyed_by_prg_crc() {
+        let db = RomDatabase::from_nescartdb(NESCARTDB).unwrap();
+        let info = |region| RomInfo { mapper_id: 2, submapper: 0, region, title: "Test Game".into() };
+        assert_eq!(db.lookup(0x89AB_CDEF), Some(&info(Region::Ntsc)));
+        assert_eq!(db.lookup(0xFEDC_BA98), Some(&info(Region::Pal)));
+        // Not the whole-cartridge CRC, and not a chip of a split PRG ROM
+        assert_eq!(db.lookup(0x0123_4567), None);
+        assert_eq!(db.lookup(0x1111_1111), None);
+    }
+
+    #[test]
+    fn test_nescartdb_errors() {
+        assert!(matches!(RomDatabase::from_nescartdb("<database>"), Err(DbError::Xml(_))));
+        let no_mapper = r#"<database><game name="G"><cartridge><board><prg crc="1"/></board></cartridge></game></database>"#;
+        let error = RomDatabase::from_nescartdb(no_mapper).err().unwrap();
+        assert_eq!(error.to_string(), "G: missing or invalid mapper");
+    }
+}
@@ -2,38 +2,89 @@ use crossbeam::queue::ArrayQueue;
 use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
 use std::sync::Arc;
 
-const SAMPLE_RATE: i32 = 44_100;
+use crate::apu::DEFAULT_SAMPLE_RATE;
+
 const BUFFER_CAPACITY: usize = 4096;
 
-struct NesAudio {
+/// Playback device settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioConfig {
+    /// Output rate in Hz; the APU must be resampling to the same rate.
+    pub sample_rate: u32,
+    /// Device buffer length, in frames.
+    pub buffer_size: u32,
+    /// One channel instead of interleaved stereo.
+    pub mono: bool,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig { sample_rate: DEFAULT_SAMPLE_RATE, buffer_size: 1024, mono: false }
+    }
+}
+
+impl AudioConfig {
+    pub fn channels(&self) -> usize {
+        if self.mono { 1 } else { 2 }
+    }
+}
+
+pub struct NesAudio {
     sample_buffer: Arc<ArrayQueue<f32>>,
+    channels: usize,
 }
 
 impl AudioCallback for NesAudio {
     type Channel = f32;
 
+    /// Fill interleaved frames, one sample per channel. A frame is only taken
+    /// once all its samples are queued, so left and right never swap.
     fn callback(&mut self, out: &mut [f32]) {
-        for sample in out.iter_mut() {
-            *sample = self.sample_buffer.pop().unwrap_or(0.0);
+        for frame in out.chunks_mut(self.channels) {
+            if self.sample_buffer.len() >= frame.len() {
+                for sample in frame.iter_mut() {
+                    *sample = self.sample_buffer.pop().unwrap_or(0.0);
+                }
+            } else {
+                frame.fill(0.0);
+            }
         }
     }
 }
 
+/// Open the playback device described by `config`, paused. The returned
+/// queue feeds it interleaved samples.
 pub fn init(
     sdl_context: &sdl2::Sdl,
-) -> Result<(AudioDevice<impl AudioCallback>, Arc<ArrayQueue<f32>>), String> {
-    let audio = sdl_context.audio()?;
+    config: &AudioConfig,
+) -> Result<(AudioDevice<NesAudio>, Arc<ArrayQueue<f32>>), String> {
     let sample_buffer = Arc::new(ArrayQueue::new(BUFFER_CAPACITY));
+    let device = open(sdl_context, config, sample_buffer.clone())?;
+    Ok((device, sample_buffer))
+}
 
+/// Replace `device` with one opened for `config`, fed from the same queue,
+/// and start it playing. The old device closes once the new one is open, so
+/// a failure leaves it in place. Samples already queued were made for the
+/// old settings; drain the queue and switch the APU to the new rate too.
+pub fn reinit(device: &mut AudioDevice<NesAudio>, config: &AudioConfig, sdl_context: &sdl2::Sdl) -> Result<(), String> {
+    let sample_buffer = device.lock().sample_buffer.clone();
+    let reopened = open(sdl_context, config, sample_buffer)?;
+    reopened.resume();
+    *device = reopened;
+    Ok(())
+}
+
+fn open(
+    sdl_context: &sdl2::Sdl,
+    config: &AudioConfig,
+    sample_buffer: Arc<ArrayQueue<f32>>,
+) -> Result<AudioDevice<NesAudio>, String> {
+    let channels = config.channels();
     let spec = AudioSpecDesired {
-        freq: Some(SAMPLE_RATE),
-        channels: Some(1),
-        samples: Some(1024),
+        freq: Some(config.sample_rate as i32),
+        channels: Some(channels as u8),
+        samples: Some(config.buffer_size as u16),
     };
-
-    let device = audio.open_playback(None, &spec, |_obtained| NesAudio {
-        sample_buffer: sample_buffer.clone(),
-    })?;
-
-    Ok((device, sample_buffer))
+    sdl_context.audio()?.open_playback(None, &spec, |_obtained| NesAudio { sample_buffer, channels })
 }
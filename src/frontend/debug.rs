@@ -0,0 +1,19 @@
+//! Debug windows that can be opened outside the main loop.
+
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use crate::ppu::debug_view::{self, PATTERN_TABLES_HEIGHT, PATTERN_TABLES_WIDTH};
+use crate::ppu::Ppu;
+
+/// Open a window showing both CHR pages side by side at 2x, $0000 on the
+/// left, in background palette 0. The picture isn't redrawn; present the
+/// returned canvas again to update it.
+pub fn show_chr_window(ppu: &Ppu, sdl: &sdl2::Sdl) -> Result<Canvas<Window>, String> {
+    let (width, height) = (PATTERN_TABLES_WIDTH as u32 * 2, PATTERN_TABLES_HEIGHT as u32 * 2);
+    let window = sdl.video()?.window("viNES — CHR", width, height).build().map_err(|e| e.to_string())?;
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let pixels = debug_view::render_pattern_tables(ppu);
+    super::present_rgb(&mut canvas, &pixels, PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT)?;
+    Ok(canvas)
+}
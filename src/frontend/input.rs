@@ -1,17 +1,350 @@
+use sdl2::controller::{Axis, Button};
+use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use crate::controller;
-
-/// Map an SDL keycode to an NES button bitmask, or None if unmapped.
-pub fn keycode_to_button(key: Keycode) -> Option<u8> {
-    match key {
-        Keycode::Z => Some(controller::BUTTON_A),
-        Keycode::X => Some(controller::BUTTON_B),
-        Keycode::Return => Some(controller::BUTTON_START),
-        Keycode::RShift => Some(controller::BUTTON_SELECT),
-        Keycode::Up => Some(controller::BUTTON_UP),
-        Keycode::Down => Some(controller::BUTTON_DOWN),
-        Keycode::Left => Some(controller::BUTTON_LEFT),
-        Keycode::Right => Some(controller::BUTTON_RIGHT),
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config::KeyBindings;
+use crate::controller::{self, Controller};
+
+/// Stick deflection past which an analog axis counts as a D-pad press.
+const AXIS_THRESHOLD: i16 = 16384;
+
+/// NES buttons as named in `controller_map.toml`.
+const NES_BUTTONS: [(&str, u8); 8] = [
+    ("a", controller::BUTTON_A),
+    ("b", controller::BUTTON_B),
+    ("select", controller::BUTTON_SELECT),
+    ("start", controller::BUTTON_START),
+    ("up", controller::BUTTON_UP),
+    ("down", controller::BUTTON_DOWN),
+    ("left", controller::BUTTON_LEFT),
+    ("right", controller::BUTTON_RIGHT),
+];
+
+/// SDL game controller buttons, by SDL's own mapping names.
+const SDL_BUTTONS: [(&str, Button); 15] = [
+    ("a", Button::A),
+    ("b", Button::B),
+    ("x", Button::X),
+    ("y", Button::Y),
+    ("back", Button::Back),
+    ("guide", Button::Guide),
+    ("start", Button::Start),
+    ("leftstick", Button::LeftStick),
+    ("rightstick", Button::RightStick),
+    ("leftshoulder", Button::LeftShoulder),
+    ("rightshoulder", Button::RightShoulder),
+    ("dpup", Button::DPadUp),
+    ("dpdown", Button::DPadDown),
+    ("dpleft", Button::DPadLeft),
+    ("dpright", Button::DPadRight),
+];
+
+/// Named keys accepted in `config.toml`, by SDL key name in lower case.
+/// Single printable characters (`z`, `1`, `/`) are accepted directly.
+const KEY_NAMES: [(&str, Keycode); 26] = [
+    ("return", Keycode::Return),
+    ("space", Keycode::Space),
+    ("tab", Keycode::Tab),
+    ("backspace", Keycode::Backspace),
+    ("up", Keycode::Up),
+    ("down", Keycode::Down),
+    ("left", Keycode::Left),
+    ("right", Keycode::Right),
+    ("left shift", Keycode::LShift),
+    ("right shift", Keycode::RShift),
+    ("left ctrl", Keycode::LCtrl),
+    ("right ctrl", Keycode::RCtrl),
+    ("left alt", Keycode::LAlt),
+    ("right alt", Keycode::RAlt),
+    ("keypad enter", Keycode::KpEnter),
+    ("keypad +", Keycode::KpPlus),
+    ("keypad 0", Keycode::Kp0),
+    ("keypad 1", Keycode::Kp1),
+    ("keypad 2", Keycode::Kp2),
+    ("keypad 3", Keycode::Kp3),
+    ("keypad 4", Keycode::Kp4),
+    ("keypad 5", Keycode::Kp5),
+    ("keypad 6", Keycode::Kp6),
+    ("keypad 7", Keycode::Kp7),
+    ("keypad 8", Keycode::Kp8),
+    ("keypad 9", Keycode::Kp9),
+];
+
+/// Look up a key by SDL name, ignoring case. `KP_Enter` style names with
+/// underscores and a `KP` prefix are accepted too.
+pub fn keycode_from_name(name: &str) -> Option<Keycode> {
+    let name = name.trim().to_ascii_lowercase().replace('_', " ");
+    let name = match name.strip_prefix("kp ") {
+        Some(rest) => format!("keypad {}", rest),
+        None => name,
+    };
+    match name.as_bytes() {
+        &[c] if c.is_ascii_graphic() => Keycode::from_i32(c as i32),
+        _ => KEY_NAMES.iter().find(|(n, _)| *n == name).map(|&(_, key)| key),
+    }
+}
+
+/// Keyboard keys mapped to controller 1's buttons.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMap {
+    keys: Vec<(Keycode, u8)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::new(&KeyBindings::default()).expect("default key names are known")
+    }
+}
+
+impl KeyMap {
+    pub fn new(bindings: &KeyBindings) -> Result<Self, String> {
+        let keys = bindings
+            .buttons()
+            .iter()
+            .map(|&(button, name)| {
+                let key = keycode_from_name(name).ok_or_else(|| format!("unknown key '{}' for {}", name, button))?;
+                let (_, nes) = NES_BUTTONS.iter().find(|(n, _)| *n == button).expect("KeyBindings names NES buttons");
+                Ok((key, *nes))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(KeyMap { keys })
+    }
+
+    /// NES button bitmask for a key, or None if unmapped. A key bound to
+    /// several buttons presses all of them.
+    pub fn lookup(&self, key: Keycode) -> Option<u8> {
+        let buttons = self.keys.iter().filter(|&&(k, _)| k == key).fold(0, |acc, &(_, nes)| acc | nes);
+        (buttons != 0).then_some(buttons)
+    }
+}
+
+/// Default game controller mapping: the face buttons keep their labels.
+pub fn gamepad_button_to_nes(button: Button) -> Option<u8> {
+    match button {
+        Button::A => Some(controller::BUTTON_A),
+        Button::B => Some(controller::BUTTON_B),
+        Button::Start => Some(controller::BUTTON_START),
+        Button::Back => Some(controller::BUTTON_SELECT),
+        Button::DPadUp => Some(controller::BUTTON_UP),
+        Button::DPadDown => Some(controller::BUTTON_DOWN),
+        Button::DPadLeft => Some(controller::BUTTON_LEFT),
+        Button::DPadRight => Some(controller::BUTTON_RIGHT),
+        _ => None,
+    }
+}
+
+/// D-pad direction for a left stick position, or None inside the dead zone
+/// (and for every other axis).
+pub fn gamepad_axis_to_nes(axis: Axis, value: i16) -> Option<u8> {
+    match axis {
+        Axis::LeftX if value <= -AXIS_THRESHOLD => Some(controller::BUTTON_LEFT),
+        Axis::LeftX if value >= AXIS_THRESHOLD => Some(controller::BUTTON_RIGHT),
+        Axis::LeftY if value <= -AXIS_THRESHOLD => Some(controller::BUTTON_UP),
+        Axis::LeftY if value >= AXIS_THRESHOLD => Some(controller::BUTTON_DOWN),
         _ => None,
     }
 }
+
+/// Both directions an axis can press, cleared whenever the stick moves.
+fn axis_buttons(axis: Axis) -> u8 {
+    match axis {
+        Axis::LeftX => controller::BUTTON_LEFT | controller::BUTTON_RIGHT,
+        Axis::LeftY => controller::BUTTON_UP | controller::BUTTON_DOWN,
+        _ => 0,
+    }
+}
+
+/// Game controller buttons mapped to NES buttons, loaded from
+/// `controller_map.toml`. The file holds one `nes_button = "sdl_button"` key
+/// per NES button, using SDL's button names (`a`, `x`, `back`, `dpup`, ...).
+/// Buttons it leaves out keep their default mapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GamepadMapping {
+    buttons: Vec<(Button, u8)>,
+}
+
+impl Default for GamepadMapping {
+    fn default() -> Self {
+        let buttons = SDL_BUTTONS
+            .iter()
+            .filter_map(|&(_, button)| gamepad_button_to_nes(button).map(|nes| (button, nes)))
+            .collect();
+        GamepadMapping { buttons }
+    }
+}
+
+impl GamepadMapping {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let entries: BTreeMap<String, String> = toml::from_str(text).map_err(|e| e.to_string())?;
+        if let Some(key) = entries.keys().find(|key| !NES_BUTTONS.iter().any(|(name, _)| name == key)) {
+            return Err(format!("unknown NES button '{}'", key));
+        }
+        let mut mapping = Self::default();
+        for (name, nes) in NES_BUTTONS {
+            let Some(value) = entries.get(name) else { continue };
+            let (_, button) = SDL_BUTTONS
+                .iter()
+                .find(|(sdl_name, _)| sdl_name == value)
+                .ok_or_else(|| format!("unknown controller button '{}' for {}", value, name))?;
+            mapping.buttons.retain(|&(b, n)| n != nes && b != *button);
+            mapping.buttons.push((*button, nes));
+        }
+        Ok(mapping)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn button(&self, button: Button) -> Option<u8> {
+        self.buttons.iter().find(|&&(b, _)| b == button).map(|&(_, nes)| nes)
+    }
+}
+
+/// Routes SDL game controller events to the two NES controller ports.
+#[derive(Debug, Clone, Default)]
+pub struct Gamepads {
+    pub mapping: GamepadMapping,
+    /// SDL joystick instance id plugged into each port.
+    ports: [Option<u32>; 2],
+}
+
+impl Gamepads {
+    pub fn new(mapping: GamepadMapping) -> Self {
+        Gamepads { mapping, ports: [None; 2] }
+    }
+
+    /// Plug a controller into the first free port. Returns the port, or None
+    /// if both are taken.
+    pub fn connect(&mut self, instance_id: u32) -> Option<usize> {
+        let port = self.ports.iter().position(Option::is_none)?;
+        self.ports[port] = Some(instance_id);
+        Some(port)
+    }
+
+    pub fn disconnect(&mut self, instance_id: u32) {
+        for port in &mut self.ports {
+            if *port == Some(instance_id) {
+                *port = None;
+            }
+        }
+    }
+
+    fn port(&self, instance_id: u32) -> Option<usize> {
+        self.ports.iter().position(|&p| p == Some(instance_id))
+    }
+
+    /// Apply a controller button or axis event to the matching NES controller.
+    /// Returns false for events that aren't from a connected game controller.
+    pub fn handle_event(&self, event: &Event, controllers: [&mut Controller; 2]) -> bool {
+        let (which, pressed, released) = match *event {
+            Event::ControllerButtonDown { which, button, .. } => (which, self.mapping.button(button), 0),
+            Event::ControllerButtonUp { which, button, .. } => {
+                (which, None, self.mapping.button(button).unwrap_or(0))
+            }
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                (which, gamepad_axis_to_nes(axis, value), axis_buttons(axis))
+            }
+            _ => return false,
+        };
+        let Some(port) = self.port(which) else { return false };
+        let [controller1, controller2] = controllers;
+        let controller = if port == 0 { controller1 } else { controller2 };
+        controller.buttons &= !released;
+        controller.buttons |= pressed.unwrap_or(0);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_register(controller: &mut Controller) -> u8 {
+        controller.write(1);
+        controller.write(0);
+        (0..8).fold(0, |acc, bit| acc | (controller.read() & 1) << bit)
+    }
+
+    #[test]
+    fn test_gamepad_events_drive_controller_ports() {
+        let mut gamepads = Gamepads::default();
+        assert_eq!(gamepads.connect(7), Some(0));
+        assert_eq!(gamepads.connect(9), Some(1));
+        assert_eq!(gamepads.connect(11), None);
+        let (mut p1, mut p2) = (Controller::new(), Controller::new());
+
+        let events = [
+            Event::ControllerButtonDown { timestamp: 0, which: 7, button: Button::A },
+            Event::ControllerButtonDown { timestamp: 1, which: 9, button: Button::Start },
+            Event::ControllerAxisMotion { timestamp: 2, which: 7, axis: Axis::LeftX, value: -20000 },
+            Event::ControllerAxisMotion { timestamp: 3, which: 9, axis: Axis::LeftY, value: 16000 },
+        ];
+        for event in &events {
+            assert!(gamepads.handle_event(event, [&mut p1, &mut p2]));
+        }
+        assert_eq!(read_register(&mut p1), controller::BUTTON_A | controller::BUTTON_LEFT);
+        assert_eq!(read_register(&mut p2), controller::BUTTON_START);
+
+        let releases = [
+            Event::ControllerButtonUp { timestamp: 4, which: 7, button: Button::A },
+            Event::ControllerAxisMotion { timestamp: 5, which: 7, axis: Axis::LeftX, value: 0 },
+        ];
+        for event in &releases {
+            gamepads.handle_event(event, [&mut p1, &mut p2]);
+        }
+        assert_eq!(read_register(&mut p1), 0);
+
+        gamepads.disconnect(7);
+        let event = Event::ControllerButtonDown { timestamp: 6, which: 7, button: Button::B };
+        assert!(!gamepads.handle_event(&event, [&mut p1, &mut p2]));
+    }
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(keycode_from_name("z"), Some(Keycode::Z));
+        assert_eq!(keycode_from_name("Z"), Some(Keycode::Z));
+        assert_eq!(keycode_from_name("Return"), Some(Keycode::Return));
+        assert_eq!(keycode_from_name("KP_Enter"), Some(Keycode::KpEnter));
+        assert_eq!(keycode_from_name("Keypad 5"), Some(Keycode::Kp5));
+        assert_eq!(keycode_from_name("right shift"), Some(Keycode::RShift));
+        assert_eq!(keycode_from_name("Hyper"), None);
+        assert_eq!(keycode_from_name(""), None);
+    }
+
+    #[test]
+    fn test_key_map_from_bindings() {
+        let default = KeyMap::default();
+        assert_eq!(default.lookup(Keycode::Z), Some(controller::BUTTON_A));
+        assert_eq!(default.lookup(Keycode::RShift), Some(controller::BUTTON_SELECT));
+        assert_eq!(default.lookup(Keycode::K), None);
+
+        let bindings = KeyBindings { a: "K".into(), start: "KP_Enter".into(), ..KeyBindings::default() };
+        let map = KeyMap::new(&bindings).unwrap();
+        assert_eq!(map.lookup(Keycode::K), Some(controller::BUTTON_A));
+        assert_eq!(map.lookup(Keycode::KpEnter), Some(controller::BUTTON_START));
+        assert_eq!(map.lookup(Keycode::Z), None);
+
+        let unknown = KeyBindings { b: "Hyper".into(), ..KeyBindings::default() };
+        assert_eq!(KeyMap::new(&unknown), Err("unknown key 'Hyper' for b".to_string()));
+    }
+
+    #[test]
+    fn test_gamepad_mapping_file() {
+        let text = "# swap A and B\na = \"b\"\nb = 'a'  # trailing comment\n";
+        let mapping = GamepadMapping::parse(text).unwrap();
+        assert_eq!(mapping.button(Button::B), Some(controller::BUTTON_A));
+        assert_eq!(mapping.button(Button::A), Some(controller::BUTTON_B));
+        assert_eq!(mapping.button(Button::Start), Some(controller::BUTTON_START));
+
+        assert!(GamepadMapping::parse("a = b").is_err());
+        assert!(GamepadMapping::parse("turbo = \"a\"").is_err());
+        assert!(GamepadMapping::parse("a = \"paddle\"").is_err());
+        // The buttons are top-level keys; a table isn't skipped over
+        assert!(GamepadMapping::parse("[buttons]\na = \"b\"").is_err());
+    }
+}
@@ -1,34 +1,481 @@
 pub mod input;
 pub mod audio;
+pub mod debug;
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::PixelFormatEnum;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::{FullscreenType, Window};
+use sdl2::VideoSubsystem;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::mpsc;
 use std::time::{Duration, Instant};
 
+use crate::apu::DEFAULT_PANNING;
+use crate::bus::PowerOnMode;
 use crate::cartridge::Cartridge;
-use crate::nes::Nes;
+use crate::cheat::GameGenieCode;
+use crate::controller::Zapper;
+use crate::movie::{is_fm2_path, Fm2Header, Fm2Movie, Movie, MovieMode};
+use crate::nes::{Nes, Region};
+#[cfg(feature = "save_states")]
+use crate::save_states::SaveStateManager;
+use crate::ppu::frame::{HEIGHT, WIDTH};
+use crate::ppu::debug_view::{
+    self, NAMETABLES_HEIGHT, NAMETABLES_WIDTH, OAM_VIEW_SIZE, PATTERN_TABLES_HEIGHT, PATTERN_TABLES_WIDTH,
+};
+use crate::ppu::OverscanConfig;
 
-const SCALE: u32 = 3;
-const WINDOW_WIDTH: u32 = 256 * SCALE;
-const WINDOW_HEIGHT: u32 = 240 * SCALE;
-const NANOS_PER_FRAME: u64 = 16_639_267; // ~60.0988 FPS (NTSC)
+const WINDOW_TITLE: &str = "viNES — vibe-coded NES emulator in Rust";
+const DEFAULT_SCALE: u32 = 3;
+/// Window scale factors `--scale` accepts.
+pub const SCALE_RANGE: RangeInclusive<u32> = 1..=8;
+/// While R is held, step back one rewind snapshot every this many frames.
+#[cfg(feature = "rewind")]
+const REWIND_STEP_FRAMES: u32 = 4;
+/// Debug viewer windows redraw at 10 FPS.
+const VIEWER_REFRESH: Duration = Duration::from_millis(100);
 
-pub fn run(cartridge: Cartridge) -> Result<(), String> {
+/// Options chosen on the command line.
+#[derive(Debug, Clone)]
+pub struct FrontendConfig {
+    pub overscan: OverscanConfig,
+    /// Battery save file, loaded at startup and written on exit.
+    pub save_path: Option<PathBuf>,
+    /// PC breakpoints that drop into the step debugger.
+    pub breakpoints: Vec<u16>,
+    /// Addresses whose writes drop into the step debugger.
+    pub watchpoints: Vec<u16>,
+    /// Game Genie codes active from power-on.
+    pub cheats: Vec<GameGenieCode>,
+    /// Input movie to play back instead of reading the keyboard.
+    pub playback: Option<Movie>,
+    /// Record input to this movie file, written on exit.
+    pub record_path: Option<PathBuf>,
+    /// Game controller buttons for both NES controller ports.
+    pub gamepad_mapping: input::GamepadMapping,
+    /// Keyboard keys for controller 1.
+    pub key_map: input::KeyMap,
+    /// Plug a Zapper into port 2, aimed with the mouse.
+    pub zapper: bool,
+    /// Console timing: NTSC at ~60.1fps, or PAL and Dendy at ~50.0fps.
+    pub region: Region,
+    /// Work RAM contents at power-on.
+    pub ram_init: PowerOnMode,
+    /// Output device settings. Mono mixes every APU channel to a single
+    /// channel instead of panning them in stereo.
+    pub audio: audio::AudioConfig,
+    /// Integer window scale, within `SCALE_RANGE`.
+    pub scale: u32,
+    /// Start in desktop fullscreen; F11 toggles it at runtime.
+    pub fullscreen: bool,
+    /// Sync presents to the display's refresh.
+    pub vsync: bool,
+    /// Stretch pixels to the NTSC 8:7 pixel aspect ratio.
+    pub par_correct: bool,
+    /// Where F9 screenshots are written (`png` feature).
+    pub screenshots_dir: PathBuf,
+    /// Where save state slots are kept (`save_states` feature).
+    pub states_dir: PathBuf,
+    /// Run in turbo mode the whole time, not just while Tab is held.
+    pub turbo: bool,
+    /// Turbo speed as a multiple of the normal frame rate; infinity runs as
+    /// fast as the host allows.
+    pub turbo_speed_multiplier: f32,
+    /// Initial master volume, 0.0-1.0; `[` and `]` change it at runtime.
+    pub volume: f32,
+    /// Print the most executed opcodes on exit (`profiling` feature).
+    pub profile: bool,
+}
+
+impl Default for FrontendConfig {
+    fn default() -> Self {
+        FrontendConfig {
+            overscan: OverscanConfig::default(),
+            save_path: None,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            cheats: Vec::new(),
+            playback: None,
+            record_path: None,
+            gamepad_mapping: input::GamepadMapping::default(),
+            key_map: input::KeyMap::default(),
+            zapper: false,
+            region: Region::default(),
+            ram_init: PowerOnMode::default(),
+            audio: audio::AudioConfig::default(),
+            scale: DEFAULT_SCALE,
+            fullscreen: false,
+            vsync: false,
+            par_correct: false,
+            screenshots_dir: PathBuf::from("."),
+            states_dir: PathBuf::from("."),
+            turbo: false,
+            turbo_speed_multiplier: f32::INFINITY,
+            volume: 1.0,
+            profile: false,
+        }
+    }
+}
+
+/// `YYYYMMDD_HHMMSS` in UTC for a time `unix_secs` after the epoch.
+#[cfg(feature = "png")]
+fn utc_timestamp(unix_secs: u64) -> String {
+    let (days, secs) = ((unix_secs / 86400) as i64, unix_secs % 86400);
+    // Days since 1970-01-01 to a civil date, counting years from March so the
+    // leap day falls at the end
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}{:02}{:02}_{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// `screenshot_YYYYMMDD_HHMMSS_frameN.png` in `dir`.
+#[cfg(feature = "png")]
+fn screenshot_path(dir: &std::path::Path, unix_secs: u64, frame: u64) -> PathBuf {
+    dir.join(format!("screenshot_{}_frame{}.png", utc_timestamp(unix_secs), frame))
+}
+
+/// Save the last completed frame as a PNG in `dir`.
+#[cfg(feature = "png")]
+fn save_screenshot(nes: &Nes, dir: &std::path::Path) {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let path = screenshot_path(dir, now, nes.bus.ppu.frame_count);
+    match nes.frame().save_png(&path) {
+        Ok(()) => log::info!("Saved screenshot {}", path.display()),
+        Err(e) => log::warn!("Failed to save screenshot '{}': {}", path.display(), e),
+    }
+}
+
+/// Time between frames at `multiplier` times normal speed. Infinite (or
+/// invalid) multipliers don't wait at all, and the speed is never below 1x.
+pub fn turbo_frame_duration(frame_duration: Duration, multiplier: f32) -> Duration {
+    if multiplier.is_finite() && multiplier > 0.0 {
+        frame_duration.div_f64(multiplier.max(1.0) as f64)
+    } else {
+        Duration::ZERO
+    }
+}
+
+/// Parse a `--turbo-speed` argument: a multiplier of at least 1, or `max`.
+pub fn parse_turbo_speed(s: &str) -> Option<f32> {
+    if s.eq_ignore_ascii_case("max") {
+        return Some(f32::INFINITY);
+    }
+    s.parse::<f32>().ok().filter(|&speed| speed >= 1.0)
+}
+
+/// Parse a `--volume` argument: a master volume from 0 to 1.
+pub fn parse_volume(s: &str) -> Option<f32> {
+    s.parse::<f32>().ok().filter(|volume| (0.0..=1.0).contains(volume))
+}
+
+/// Output rates `--sample-rate` accepts, in Hz.
+pub const SAMPLE_RATE_RANGE: RangeInclusive<u32> = 8_000..=192_000;
+
+pub fn parse_sample_rate(s: &str) -> Option<u32> {
+    s.parse().ok().filter(|rate| SAMPLE_RATE_RANGE.contains(rate))
+}
+
+/// Master volume one `[` or `]` press (`step` of -1 or 1) away, in tenths.
+pub fn step_volume(volume: f32, step: i32) -> f32 {
+    (((volume * 10.0).round() as i32 + step).clamp(0, 10)) as f32 / 10.0
+}
+
+/// Window title, led by the game's title when the ROM database knows it,
+/// with the save slot, turbo and muted-channel indicators.
+pub fn window_title(rom_title: Option<&str>, slot: Option<usize>, turbo: bool, channel_mute: [bool; 5]) -> String {
+    let mut title = match rom_title {
+        Some(rom_title) => format!("{} — {}", rom_title, WINDOW_TITLE),
+        None => WINDOW_TITLE.to_string(),
+    };
+    if let Some(slot) = slot {
+        title.push_str(&format!(" (slot {})", slot));
+    }
+    if turbo {
+        title.push_str(" (TURBO)");
+    }
+    let muted: Vec<_> = ["P1", "P2", "T", "N", "D"]
+        .into_iter()
+        .zip(channel_mute)
+        .filter_map(|(name, muted)| muted.then_some(name))
+        .collect();
+    if !muted.is_empty() {
+        title.push_str(&format!(" [{}]", muted.join(" ")));
+    }
+    title
+}
+
+/// Parse a `--scale` argument.
+pub fn parse_scale(s: &str) -> Option<u32> {
+    s.parse().ok().filter(|scale| SCALE_RANGE.contains(scale))
+}
+
+/// Displayed width of the picture at `scale`. NTSC pixels are about 8:7
+/// wide, so with `correct` the 256 columns span 256 * 8/7 * scale, rounded
+/// to the nearest even number.
+pub fn pixel_aspect_width(scale: u32, correct: bool) -> u32 {
+    if correct {
+        (WIDTH as u32 * 8 * scale + 7) / 14 * 2
+    } else {
+        WIDTH as u32 * scale
+    }
+}
+
+/// Where to draw the 256x240 picture on a `width` x `height` output: the
+/// largest integer scale that fits, centered, leaving black bars rather than
+/// stretching. Outputs smaller than the picture get it at 1x.
+pub fn letterbox(width: u32, height: u32, par_correct: bool) -> Rect {
+    let scale = (1..)
+        .take_while(|&s| pixel_aspect_width(s, par_correct) <= width && HEIGHT as u32 * s <= height)
+        .last()
+        .unwrap_or(1);
+    let (w, h) = (pixel_aspect_width(scale, par_correct), HEIGHT as u32 * scale);
+    Rect::new((width as i32 - w as i32) / 2, (height as i32 - h as i32) / 2, w, h)
+}
+
+/// NES pixel under window position (`x`, `y`), clamped to the picture.
+fn window_to_nes(canvas: &Canvas<Window>, par_correct: bool, x: i32, y: i32) -> (usize, usize) {
+    let window = canvas.window().size();
+    let output = canvas.output_size().unwrap_or(window);
+    point_to_nes(output, window, par_correct, x, y)
+}
+
+/// `window_to_nes` for a window `window` points in size drawn at `output`
+/// pixels. Mouse events come in points, but `present_frame` letterboxes in
+/// pixels, and the two differ on HiDPI displays.
+fn point_to_nes(output: (u32, u32), window: (u32, u32), par_correct: bool, x: i32, y: i32) -> (usize, usize) {
+    let dest = letterbox(output.0, output.1, par_correct);
+    let to_nes = |v: i32, points: u32, pixels: u32, start: i32, span: u32, max: usize| {
+        let v = (v as i64 * pixels as i64 / points.max(1) as i64) as i32;
+        (((v - start).max(0) as usize * max) / span as usize).min(max - 1)
+    };
+    (
+        to_nes(x, window.0, output.0, dest.x(), dest.width(), WIDTH),
+        to_nes(y, window.1, output.1, dest.y(), dest.height(), HEIGHT),
+    )
+}
+
+/// Draw the NES picture in the main window, letterboxed in black.
+fn present_frame(canvas: &mut Canvas<Window>, texture: &Texture, par_correct: bool) -> Result<(), String> {
+    let (width, height) = canvas.output_size()?;
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
+    canvas.copy(texture, None, letterbox(width, height, par_correct))?;
+    canvas.present();
+    Ok(())
+}
+
+/// Open a debug viewer window, or close it if it's already open.
+fn toggle_viewer(
+    viewer: &mut Option<Canvas<Window>>,
+    video: &VideoSubsystem,
+    title: &str,
+    (width, height): (u32, u32),
+) -> Result<(), String> {
+    if viewer.take().is_none() {
+        let window = video.window(title, width, height).build().map_err(|e| e.to_string())?;
+        *viewer = Some(window.into_canvas().build().map_err(|e| e.to_string())?);
+    }
+    Ok(())
+}
+
+/// Draw an RGB24 image stretched over a viewer window.
+fn present_rgb(canvas: &mut Canvas<Window>, pixels: &[u8], width: usize, height: usize) -> Result<(), String> {
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+        .map_err(|e| e.to_string())?;
+    texture.update(None, pixels, width * 3).map_err(|e| e.to_string())?;
+    canvas.copy(&texture, None, None)?;
+    canvas.present();
+    Ok(())
+}
+
+/// Parse a 16-bit address written as `C000`, `$C000` or `0xC000`.
+pub fn parse_hex_addr(s: &str) -> Option<u16> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix('$')).unwrap_or(s);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// Debugger commands typed on stdin, read on a separate thread so the
+/// window keeps running while it waits for input.
+fn spawn_command_reader() -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Run one stdin debugger command: `w XXXX` watches writes to an address.
+fn run_command(nes: &mut Nes, line: &str) {
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next().and_then(parse_hex_addr)) {
+        (Some("w"), Some(addr)) => {
+            nes.bus.watchpoint.add(addr);
+            eprintln!("Watching writes to ${:04X}", addr);
+        }
+        (None, _) => {}
+        _ => eprintln!("Unknown command '{}'; try w <hex addr> or rate <hz>", line.trim()),
+    }
+}
+
+/// Step-debugger state. F1 pauses, then steps one instruction at a time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DebugMode {
+    Running,
+    Paused,
+    /// Execute a single instruction on the next loop iteration, then pause.
+    StepOne,
+}
+
+/// 3x5 glyphs for the characters that appear in trace lines; each row's low
+/// three bits are its pixels, left to right.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '$' => [0b011, 0b110, 0b010, 0b011, 0b110],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '@' => [0b010, 0b101, 0b111, 0b100, 0b011],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '*' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => [0; 5],
+    }
+}
+
+/// Draws the most recent trace lines over the bottom of the picture while the
+/// step debugger is active.
+struct OverlayRenderer {
+    lines: VecDeque<String>,
+}
+
+impl OverlayRenderer {
+    const MAX_LINES: usize = 8;
+    const CHAR_WIDTH: usize = 4;
+    const LINE_HEIGHT: usize = 6;
+
+    fn new() -> Self {
+        OverlayRenderer { lines: VecDeque::with_capacity(Self::MAX_LINES) }
+    }
+
+    fn push(&mut self, line: String) {
+        if self.lines.len() == Self::MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Blit the lines into a 256x240 RGB buffer over a darkened strip. Lines
+    /// are cut off at 64 characters, which keeps the registers but drops CYC.
+    fn draw(&self, pixels: &mut [u8]) {
+        let top = HEIGHT - Self::MAX_LINES * Self::LINE_HEIGHT - 1;
+        for byte in &mut pixels[top * WIDTH * 3..] {
+            *byte /= 4;
+        }
+        for (row, line) in self.lines.iter().enumerate() {
+            let y0 = top + 1 + row * Self::LINE_HEIGHT;
+            for (col, c) in line.chars().take(WIDTH / Self::CHAR_WIDTH).enumerate() {
+                for (dy, bits) in glyph(c).iter().enumerate() {
+                    for dx in 0..3 {
+                        if bits & (0b100 >> dx) != 0 {
+                            let offset = ((y0 + dy) * WIDTH + col * Self::CHAR_WIDTH + dx) * 3;
+                            pixels[offset..offset + 3].fill(0xFF);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn run(cartridge: Cartridge, config: FrontendConfig) -> Result<(), String> {
     let sdl_context = sdl2::init()?;
     let video = sdl_context.video()?;
 
-    let window = video
-        .window("viNES — vibe-coded NES emulator in Rust", WINDOW_WIDTH, WINDOW_HEIGHT)
-        .position_centered()
-        .build()
-        .map_err(|e| e.to_string())?;
+    let rom_title = cartridge.rom_title.clone();
+    let mut window = video.window(
+        &window_title(rom_title.as_deref(), None, false, [false; 5]),
+        pixel_aspect_width(config.scale, config.par_correct),
+        HEIGHT as u32 * config.scale,
+    );
+    window.position_centered();
+    if config.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().map_err(|e| e.to_string())?;
 
-    let mut canvas = window
-        .into_canvas()
-        .accelerated()
-        .build()
-        .map_err(|e| e.to_string())?;
+    let mut canvas = window.into_canvas().accelerated();
+    if config.vsync {
+        canvas = canvas.present_vsync();
+    }
+    let mut canvas = canvas.build().map_err(|e| e.to_string())?;
 
     let texture_creator = canvas.texture_creator();
     let mut texture = texture_creator
@@ -37,52 +484,271 @@ pub fn run(cartridge: Cartridge) -> Result<(), String> {
 
     let mut event_pump = sdl_context.event_pump()?;
 
+    // The first two game controllers drive controller ports 1 and 2
+    let game_controller = sdl_context.game_controller()?;
+    let mut gamepads = input::Gamepads::new(config.gamepad_mapping.clone());
+    let mut open_pads = Vec::new();
+    for index in 0..game_controller.num_joysticks()? {
+        if open_pads.len() == 2 {
+            break;
+        }
+        if game_controller.is_game_controller(index) {
+            match game_controller.open(index) {
+                Ok(pad) => {
+                    gamepads.connect(pad.instance_id());
+                    log::info!("Controller {}: {}", open_pads.len() + 1, pad.name());
+                    open_pads.push(pad);
+                }
+                Err(e) => log::warn!("Failed to open game controller {}: {}", index, e),
+            }
+        }
+    }
+
+    let rom_crc32 = cartridge.rom_crc32();
+    let mut nes = Nes::new_headless(cartridge);
+    if !config.audio.mono {
+        nes.bus.apu.panning = Some(DEFAULT_PANNING);
+    }
+    nes.bus.apu.master_volume = config.volume;
+    let channels = config.audio.channels();
+
     // Init audio
-    let (_audio_device, sample_buffer) = audio::init(&sdl_context)?;
-    _audio_device.resume();
+    let mut audio_config = config.audio;
+    let (mut audio_device, sample_buffer) = audio::init(&sdl_context, &audio_config)?;
+    audio_device.resume();
 
-    let mut nes = Nes::new(cartridge, sample_buffer);
-    nes.reset();
+    nes.set_region(config.region);
+    nes.set_sample_rate(audio_config.sample_rate);
+    nes.bus.power_on(config.ram_init);
+    nes.bus.ppu.set_overscan(config.overscan);
+    if let Some(path) = config.save_path {
+        if let Err(e) = nes.set_save_path(path.clone()) {
+            log::warn!("Failed to load save file '{}': {}", path.display(), e);
+        }
+    }
+    for &addr in &config.breakpoints {
+        nes.cpu.debugger.add_breakpoint(addr);
+    }
+    for &addr in &config.watchpoints {
+        nes.bus.watchpoint.add(addr);
+    }
+    for &code in &config.cheats {
+        nes.bus.cheats.add(code);
+    }
+    if config.zapper {
+        nes.bus.zapper = Some(Zapper::new());
+    }
+    // Zapper aim, in NES pixels
+    let mut mouse = (0usize, 0usize);
+    if let Some(movie) = config.playback {
+        nes.movie = MovieMode::Playback { movie, frame: 0 };
+    } else if config.record_path.is_some() {
+        nes.movie = MovieMode::Recording(Movie::new());
+    }
+    let commands = spawn_command_reader();
 
     let mut next_frame_time = Instant::now();
-    let frame_duration = Duration::from_nanos(NANOS_PER_FRAME);
+    let normal_frame_duration = Duration::from_nanos(config.region.nanos_per_frame());
+    let turbo_frame_duration = turbo_frame_duration(normal_frame_duration, config.turbo_speed_multiplier);
+    // Tab held, on top of --turbo
+    let mut turbo_held = false;
+    let mut turbo = false;
+    #[cfg(feature = "save_states")]
+    let mut save_slots = SaveStateManager::with_dir(config.states_dir.clone(), rom_crc32);
+    #[cfg(not(feature = "save_states"))]
     let mut save_state: Option<Nes> = None;
+    let mut debug_mode = DebugMode::Running;
+    let mut overlay = OverlayRenderer::new();
+    let mut redraw = false;
+    let mut nametable_viewer: Option<Canvas<Window>> = None;
+    let mut pattern_viewer: Option<Canvas<Window>> = None;
+    let mut oam_viewer: Option<Canvas<Window>> = None;
+    let mut last_viewer_refresh = Instant::now();
+    #[cfg(feature = "rewind")]
+    let mut rewind_frames: Option<u32> = None;
 
     'running: loop {
         // Handle input — always pump events to keep macOS happy
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
+                Event::Quit { .. } => break 'running,
+                Event::MouseMotion { x, y, .. } => {
+                    mouse = window_to_nes(&canvas, config.par_correct, x, y);
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    // Held for the next frame only
+                    if let Some(zapper) = nes.bus.zapper.as_mut() {
+                        zapper.trigger = true;
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } if open_pads.len() < 2 => {
+                    if let Ok(pad) = game_controller.open(which) {
+                        if !open_pads.iter().any(|p| p.instance_id() == pad.instance_id()) {
+                            gamepads.connect(pad.instance_id());
+                            open_pads.push(pad);
+                        }
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    gamepads.disconnect(which);
+                    open_pads.retain(|pad| pad.instance_id() != which);
+                }
+                _ if !nes.movie.is_playing()
+                    && gamepads.handle_event(&event, [&mut nes.bus.controller1, &mut nes.bus.controller2]) => {}
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    // With viewers open, closing the main window doesn't send Quit
+                    if window_id == canvas.window().id() {
+                        break 'running;
+                    }
+                    for viewer in [&mut nametable_viewer, &mut pattern_viewer, &mut oam_viewer] {
+                        if viewer.as_ref().is_some_and(|c| c.window().id() == window_id) {
+                            *viewer = None;
+                        }
+                    }
+                }
+                Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => break 'running,
+                } => {
+                    if debug_mode == DebugMode::Running {
+                        break 'running;
+                    }
+                    debug_mode = DebugMode::Running;
+                    next_frame_time = Instant::now();
+                }
                 Event::KeyDown {
-                    keycode: Some(key), ..
+                    keycode: Some(key),
+                    keymod,
+                    ..
                 } => {
+                    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
                     match key {
+                        Keycode::F1 if debug_mode == DebugMode::Running => {
+                            debug_mode = DebugMode::Paused;
+                            overlay.push(nes.cpu.trace(&nes.bus));
+                            redraw = true;
+                        }
+                        Keycode::F1 => debug_mode = DebugMode::StepOne,
+                        Keycode::F2 | Keycode::F3 if debug_mode != DebugMode::Running => {
+                            if key == Keycode::F2 {
+                                nes.run_until_nmi();
+                            } else {
+                                nes.step_frame();
+                            }
+                            let trace = nes.cpu.trace(&nes.bus);
+                            if let Some(addr) = nes.cpu.debugger.hit() {
+                                eprintln!("Breakpoint at ${:04X}", addr);
+                            }
+                            if let Some((addr, value)) = nes.bus.watchpoint.triggered() {
+                                eprintln!("Watchpoint: ${:02X} written to ${:04X}", value, addr);
+                            }
+                            eprintln!("{}", trace);
+                            overlay.push(trace);
+                            redraw = true;
+                        }
+                        Keycode::F2 => {
+                            let size = (NAMETABLES_WIDTH as u32, NAMETABLES_HEIGHT as u32);
+                            toggle_viewer(&mut nametable_viewer, &video, "viNES — nametables", size)?;
+                            last_viewer_refresh -= VIEWER_REFRESH;
+                        }
+                        Keycode::F3 => {
+                            let size = (PATTERN_TABLES_WIDTH as u32 * config.scale, PATTERN_TABLES_HEIGHT as u32 * config.scale);
+                            toggle_viewer(&mut pattern_viewer, &video, "viNES — pattern tables", size)?;
+                            last_viewer_refresh -= VIEWER_REFRESH;
+                        }
+                        Keycode::F4 => {
+                            let size = (OAM_VIEW_SIZE as u32 * 2, OAM_VIEW_SIZE as u32 * 2);
+                            toggle_viewer(&mut oam_viewer, &video, "viNES — sprites", size)?;
+                            redraw = true;
+                        }
+                        #[cfg(feature = "save_states")]
+                        Keycode::F5 => match save_slots.save(&nes, save_slots.current_slot) {
+                            Ok(()) => log::info!("State saved to slot {}", save_slots.current_slot),
+                            Err(e) => log::warn!("Failed to save slot {}: {}", save_slots.current_slot, e),
+                        },
+                        #[cfg(feature = "save_states")]
+                        Keycode::F8 => match save_slots.load(&mut nes, save_slots.current_slot) {
+                            Ok(()) => {
+                                while sample_buffer.pop().is_some() {}
+                                redraw = true;
+                                log::info!("State loaded from slot {}", save_slots.current_slot);
+                            }
+                            Err(e) => log::warn!("Failed to load slot {}: {}", save_slots.current_slot, e),
+                        },
+                        #[cfg(feature = "save_states")]
+                        Keycode::Num0 | Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 | Keycode::Num5
+                        | Keycode::Num6 | Keycode::Num7 | Keycode::Num8 | Keycode::Num9
+                            if !shift =>
+                        {
+                            save_slots.current_slot = (key.into_i32() - Keycode::Num0.into_i32()) as usize;
+                        }
+                        #[cfg(not(feature = "save_states"))]
                         Keycode::F5 => {
                             save_state = Some(nes.clone());
                             log::info!("State saved");
                         }
-                        Keycode::F9 => {
+                        #[cfg(not(feature = "save_states"))]
+                        Keycode::F8 => {
                             if let Some(ref state) = save_state {
+                                let mixer = (nes.bus.apu.master_volume, nes.bus.apu.channel_mute);
                                 nes = state.clone();
-                                while nes.bus.apu.sample_buffer.pop().is_some() {}
+                                (nes.bus.apu.master_volume, nes.bus.apu.channel_mute) = mixer;
+                                while sample_buffer.pop().is_some() {}
+                                redraw = true;
                                 log::info!("State loaded");
                             }
                         }
+                        Keycode::F11 => {
+                            let mode = match canvas.window().fullscreen_state() {
+                                FullscreenType::Off => FullscreenType::Desktop,
+                                _ => FullscreenType::Off,
+                            };
+                            canvas.window_mut().set_fullscreen(mode)?;
+                            redraw = true;
+                        }
+                        #[cfg(feature = "png")]
+                        Keycode::F9 => save_screenshot(&nes, &config.screenshots_dir),
+                        #[cfg(feature = "rewind")]
+                        Keycode::R => rewind_frames = rewind_frames.or(Some(0)),
+                        Keycode::Tab => turbo_held = true,
+                        // Plain 1-5 are free when there are no slots to select
+                        Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 | Keycode::Num5
+                            if shift || cfg!(not(feature = "save_states")) =>
+                        {
+                            let channel = (key.into_i32() - Keycode::Num1.into_i32()) as usize;
+                            nes.bus.apu.channel_mute[channel] ^= true;
+                        }
+                        Keycode::LeftBracket | Keycode::RightBracket => {
+                            let step = if key == Keycode::LeftBracket { -1 } else { 1 };
+                            nes.bus.apu.master_volume = step_volume(nes.bus.apu.master_volume, step);
+                            log::info!("Volume {:.0}%", nes.bus.apu.master_volume * 100.0);
+                        }
+                        _ if nes.movie.is_playing() => {}
                         _ => {
-                            if let Some(button) = input::keycode_to_button(key) {
+                            if let Some(button) = config.key_map.lookup(key) {
                                 nes.bus.controller1.buttons |= button;
                             }
                         }
                     }
                 }
+                #[cfg(feature = "rewind")]
+                Event::KeyUp {
+                    keycode: Some(Keycode::R), ..
+                } => rewind_frames = None,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab), ..
+                } => turbo_held = false,
                 Event::KeyUp {
                     keycode: Some(key), ..
-                } => {
-                    if let Some(button) = input::keycode_to_button(key) {
+                } if !nes.movie.is_playing() => {
+                    if let Some(button) = config.key_map.lookup(key) {
                         nes.bus.controller1.buttons &= !button;
                     }
                 }
@@ -90,16 +756,122 @@ pub fn run(cartridge: Cartridge) -> Result<(), String> {
             }
         }
 
+        while let Ok(line) = commands.try_recv() {
+            let Some(rate) = line.strip_prefix("rate ") else {
+                run_command(&mut nes, &line);
+                continue;
+            };
+            match parse_sample_rate(rate.trim()) {
+                Some(sample_rate) => {
+                    let reopened = audio::AudioConfig { sample_rate, ..audio_config };
+                    match audio::reinit(&mut audio_device, &reopened, &sdl_context) {
+                        Ok(()) => {
+                            audio_config = reopened;
+                            nes.set_sample_rate(sample_rate);
+                            while sample_buffer.pop().is_some() {}
+                            eprintln!("Audio at {}Hz", sample_rate);
+                        }
+                        Err(e) => eprintln!("Failed to reopen audio at {}Hz: {}", sample_rate, e),
+                    }
+                }
+                None => eprintln!("rate expects a sample rate from 8000 to 192000 Hz"),
+            }
+        }
+
+        if turbo != (config.turbo || turbo_held) {
+            turbo = !turbo;
+            next_frame_time = Instant::now();
+        }
+        #[cfg(feature = "save_states")]
+        let slot = Some(save_slots.current_slot);
+        #[cfg(not(feature = "save_states"))]
+        let slot = None;
+        let title = window_title(rom_title.as_deref(), slot, turbo, nes.bus.apu.channel_mute);
+        if title != canvas.window().title() {
+            canvas.window_mut().set_title(&title).map_err(|e| e.to_string())?;
+        }
+        let frame_duration = if turbo { turbo_frame_duration } else { normal_frame_duration };
+
+        if debug_mode == DebugMode::StepOne {
+            let (_, trace) = nes.step_one();
+            eprintln!("{}", trace);
+            overlay.push(trace);
+            debug_mode = DebugMode::Paused;
+            redraw = true;
+        }
+
         // Only run emulation + render when it's time for the next frame
         let now = Instant::now();
-        if now >= next_frame_time {
-            nes.step_frame();
 
-            texture
-                .update(None, &nes.bus.ppu.frame.data, 256 * 3)
-                .map_err(|e| e.to_string())?;
-            canvas.copy(&texture, None, None)?;
-            canvas.present();
+        // Holding R replaces emulation with stepping back through snapshots
+        #[cfg(feature = "rewind")]
+        if let Some(frames) = rewind_frames.as_mut() {
+            if debug_mode == DebugMode::Running && now >= next_frame_time {
+                if frames.is_multiple_of(REWIND_STEP_FRAMES) && nes.rewind() {
+                    texture.update(None, nes.frame().front_slice(), 256 * 3).map_err(|e| e.to_string())?;
+                    present_frame(&mut canvas, &texture, config.par_correct)?;
+                }
+                *frames += 1;
+                next_frame_time = now + frame_duration;
+                continue;
+            }
+        }
+
+        if debug_mode != DebugMode::Running {
+            if redraw {
+                let mut pixels = nes.frame().front_slice().to_vec();
+                overlay.draw(&mut pixels);
+                texture.update(None, &pixels, 256 * 3).map_err(|e| e.to_string())?;
+                present_frame(&mut canvas, &texture, config.par_correct)?;
+                redraw = false;
+                if let Some(viewer) = oam_viewer.as_mut() {
+                    let (pixels, _) = debug_view::render_oam_debug(&nes.bus.ppu);
+                    present_rgb(viewer, &pixels, OAM_VIEW_SIZE, OAM_VIEW_SIZE)?;
+                }
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        } else if now >= next_frame_time {
+            if let Some(zapper) = nes.bus.zapper.as_mut() {
+                zapper.aim(mouse.0, mouse.1);
+            }
+            let (pixels, samples) = nes.step_frame_headless();
+            // Turbo frames come too fast to play their audio; drop it
+            for frame in samples.chunks(channels).filter(|_| !turbo) {
+                // Drop samples rather than block if the device falls behind, a whole
+                // stereo pair at a time
+                if sample_buffer.capacity() - sample_buffer.len() >= frame.len() {
+                    for &sample in frame {
+                        let _ = sample_buffer.push(sample);
+                    }
+                }
+            }
+
+            texture.update(None, pixels, 256 * 3).map_err(|e| e.to_string())?;
+            present_frame(&mut canvas, &texture, config.par_correct)?;
+
+            if let Some(zapper) = nes.bus.zapper.as_mut() {
+                zapper.trigger = false;
+            }
+
+            if let Some(viewer) = oam_viewer.as_mut() {
+                let (pixels, _) = debug_view::render_oam_debug(&nes.bus.ppu);
+                present_rgb(viewer, &pixels, OAM_VIEW_SIZE, OAM_VIEW_SIZE)?;
+            }
+
+            if let Some(addr) = nes.cpu.debugger.hit() {
+                let trace = nes.cpu.trace(&nes.bus);
+                eprintln!("Breakpoint at ${:04X}\n{}", addr, trace);
+                overlay.push(trace);
+                debug_mode = DebugMode::Paused;
+                redraw = true;
+            }
+            if let Some((addr, value)) = nes.bus.watchpoint.triggered() {
+                let trace = nes.cpu.trace(&nes.bus);
+                eprintln!("Watchpoint: ${:02X} written to ${:04X}\n{}", value, addr, trace);
+                overlay.push(trace);
+                debug_mode = DebugMode::Paused;
+                redraw = true;
+            }
 
             // Schedule next frame; skip ahead if we fell behind
             next_frame_time += frame_duration;
@@ -110,7 +882,155 @@ pub fn run(cartridge: Cartridge) -> Result<(), String> {
             // Yield CPU while waiting — short sleep to stay responsive
             std::thread::sleep(Duration::from_millis(1));
         }
+
+        if last_viewer_refresh.elapsed() >= VIEWER_REFRESH {
+            last_viewer_refresh = Instant::now();
+            if let Some(viewer) = nametable_viewer.as_mut() {
+                let pixels = debug_view::render_nametables(&nes.bus.ppu);
+                present_rgb(viewer, &pixels, NAMETABLES_WIDTH, NAMETABLES_HEIGHT)?;
+            }
+            if let Some(viewer) = pattern_viewer.as_mut() {
+                let pixels = debug_view::render_pattern_tables(&nes.bus.ppu);
+                present_rgb(viewer, &pixels, PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT)?;
+            }
+        }
     }
 
+    nes.save().map_err(|e| format!("Failed to write save file: {}", e))?;
+    if let (Some(path), MovieMode::Recording(movie)) = (config.record_path, &nes.movie) {
+        let saved = if is_fm2_path(&path) {
+            let header = Fm2Header { rom_crc32: Some(rom_crc32), pal: config.region != Region::Ntsc, ..Fm2Header::default() };
+            Fm2Movie::from_movie(movie, header).save_to(&path)
+        } else {
+            movie.save(&path)
+        };
+        saved.map_err(|e| format!("Failed to write movie '{}': {}", path.display(), e))?;
+    }
+    #[cfg(feature = "profiling")]
+    if config.profile {
+        print_profile(&nes.cpu.profiler);
+    }
     Ok(())
 }
+
+/// Print the 20 most executed opcodes with their share of all instructions.
+#[cfg(feature = "profiling")]
+fn print_profile(profiler: &crate::cpu::CpuProfiler) {
+    let report = profiler.report();
+    let total: u64 = report.iter().map(|&(_, count, _)| count).sum();
+    println!("opcode  mnemonic  {:>12}  share", "count");
+    for (opcode, count, mnemonic) in report.into_iter().take(20) {
+        println!("  ${:02X}    {:<8}  {:>12}  {:5.1}%", opcode, mnemonic, count, count as f64 * 100.0 / total as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_controls() {
+        assert_eq!(parse_volume("0.5"), Some(0.5));
+        assert_eq!(parse_volume("0"), Some(0.0));
+        assert_eq!(parse_volume("1.5"), None);
+        assert_eq!(parse_volume("loud"), None);
+        assert_eq!(step_volume(1.0, 1), 1.0);
+        assert_eq!(step_volume(1.0, -1), 0.9);
+        assert_eq!(step_volume(0.1, -1), 0.0);
+        assert_eq!(step_volume(0.0, -1), 0.0);
+        assert_eq!(step_volume(0.3, 1), 0.4);
+    }
+
+    #[test]
+    fn test_window_title_indicators() {
+        assert_eq!(window_title(None, None, false, [false; 5]), WINDOW_TITLE);
+        assert_eq!(window_title(None, None, true, [false; 5]), format!("{} (TURBO)", WINDOW_TITLE));
+        assert_eq!(window_title(None, None, false, [true, false, true, true, false]), format!("{} [P1 T N]", WINDOW_TITLE));
+        assert_eq!(window_title(Some("Game"), None, false, [false; 5]), format!("Game — {}", WINDOW_TITLE));
+        assert_eq!(window_title(None, Some(3), true, [false; 5]), format!("{} (slot 3) (TURBO)", WINDOW_TITLE));
+    }
+
+    #[test]
+    fn test_parse_scale() {
+        assert_eq!(parse_scale("1"), Some(1));
+        assert_eq!(parse_scale("8"), Some(8));
+        assert_eq!(parse_scale("0"), None);
+        assert_eq!(parse_scale("9"), None);
+        assert_eq!(parse_scale("2x"), None);
+    }
+
+    #[test]
+    fn test_letterbox_centers_integer_scale() {
+        // Windowed: the picture fills the window exactly
+        assert_eq!(letterbox(768, 720, false), Rect::new(0, 0, 768, 720));
+        // 1080p: 4x (1024x960), pillarboxed and letterboxed
+        assert_eq!(letterbox(1920, 1080, false), Rect::new(448, 60, 1024, 960));
+        // 720p: 3x
+        assert_eq!(letterbox(1280, 720, false), Rect::new(256, 0, 768, 720));
+        // 1440p and 4K: 6x and 9x
+        assert_eq!(letterbox(2560, 1440, false), Rect::new(512, 0, 1536, 1440));
+        assert_eq!(letterbox(3840, 2160, false), Rect::new(768, 0, 2304, 2160));
+        // Smaller than the picture: 1x, overhanging evenly
+        assert_eq!(letterbox(200, 200, false), Rect::new(-28, -20, 256, 240));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_screenshot_path() {
+        assert_eq!(utc_timestamp(0), "19700101_000000");
+        // 2000-02-29 (leap day) 13:45:07
+        assert_eq!(utc_timestamp(951_831_907), "20000229_134507");
+        assert_eq!(utc_timestamp(1_791_072_000), "20261004_000000");
+        assert_eq!(
+            screenshot_path(std::path::Path::new("shots"), 951_831_907, 42),
+            std::path::Path::new("shots").join("screenshot_20000229_134507_frame42.png")
+        );
+    }
+
+    #[test]
+    fn test_turbo_frame_duration() {
+        let frame = Duration::from_millis(16);
+        assert_eq!(turbo_frame_duration(frame, 2.0), Duration::from_millis(8));
+        assert_eq!(turbo_frame_duration(frame, 4.0), Duration::from_millis(4));
+        assert_eq!(turbo_frame_duration(frame, 0.5), frame);
+        assert_eq!(turbo_frame_duration(frame, f32::INFINITY), Duration::ZERO);
+        assert_eq!(turbo_frame_duration(frame, f32::NAN), Duration::ZERO);
+
+        assert_eq!(parse_turbo_speed("4"), Some(4.0));
+        assert_eq!(parse_turbo_speed("MAX"), Some(f32::INFINITY));
+        assert_eq!(parse_turbo_speed("0.5"), None);
+        assert_eq!(parse_turbo_speed("fast"), None);
+    }
+
+    #[test]
+    fn test_pixel_aspect_width() {
+        assert_eq!(pixel_aspect_width(1, false), 256);
+        assert_eq!(pixel_aspect_width(3, false), 768);
+        assert_eq!(pixel_aspect_width(1, true), 292);
+        assert_eq!(pixel_aspect_width(2, true), 586);
+        assert_eq!(pixel_aspect_width(3, true), 878);
+        assert_eq!(pixel_aspect_width(4, true), 1170);
+    }
+
+    #[test]
+    fn test_letterbox_with_pixel_aspect_correction() {
+        // The corrected window fills exactly
+        assert_eq!(letterbox(878, 720, true), Rect::new(0, 0, 878, 720));
+        // 1080p: 4x is 1170x960
+        assert_eq!(letterbox(1920, 1080, true), Rect::new(375, 60, 1170, 960));
+        // Too narrow for the corrected 3x width, so 2x
+        assert_eq!(letterbox(800, 720, true), Rect::new(107, 120, 586, 480));
+    }
+
+    #[test]
+    fn test_point_to_nes_on_hidpi() {
+        // 700x660 points drawn at 2x: the picture is 5x, 1280x1200 at (60, 60)
+        let (output, window) = ((1400, 1320), (700, 660));
+        assert_eq!(point_to_nes(output, window, false, 350, 330), (128, 120));
+        assert_eq!(point_to_nes(output, window, false, 100, 100), (28, 28));
+        assert_eq!(point_to_nes(output, window, false, 0, 0), (0, 0));
+        assert_eq!(point_to_nes(output, window, false, 699, 659), (255, 239));
+        // Without scaling, points and pixels agree
+        assert_eq!(point_to_nes(window, window, false, 94, 90), (0, 0));
+    }
+}
@@ -6,3 +6,10 @@ pub mod bus;
 pub mod controller;
 pub mod nes;
 pub mod frontend;
+pub mod cheat;
+pub mod movie;
+pub mod util;
+pub mod config;
+pub mod db;
+#[cfg(feature = "save_states")]
+pub mod save_states;
@@ -1,31 +1,279 @@
 use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use vines::bus::PowerOnMode;
 use vines::cartridge::Cartridge;
-use vines::frontend;
+use vines::cheat::GameGenieCode;
+use vines::config;
+use vines::db::RomDatabase;
+use vines::frontend::input::{GamepadMapping, KeyMap};
+use vines::frontend::{self, FrontendConfig};
+use vines::movie::{is_fm2_path, Fm2Movie, Movie};
+use vines::nes::Region;
+use vines::ppu::OverscanConfig;
+
+/// A single Game Genie code, or a text file with one code per line. Blank
+/// lines and lines starting with '#' are skipped.
+fn genie_codes(arg: &str) -> Result<Vec<GameGenieCode>, String> {
+    if !Path::new(arg).is_file() {
+        let code = GameGenieCode::decode(arg).map_err(|e| format!("Bad Game Genie code '{}': {}", arg, e))?;
+        return Ok(vec![code]);
+    }
+    let text = fs::read_to_string(arg).map_err(|e| format!("Failed to read '{}': {}", arg, e))?;
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| GameGenieCode::decode(line).map_err(|e| format!("Bad Game Genie code '{}': {}", line, e)))
+        .collect()
+}
 
 fn main() {
     env_logger::init();
 
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <rom.nes>", args[0]);
-        process::exit(1);
-    }
+    let usage = format!("Usage: {} [--overscan <lines>] [--break <hex addr>]... [--watch <hex addr>]... [--genie <code|file>]... [--record <movie> | --play <movie>] [--zapper] [--region ntsc|pal|dendy] [--no-db | --db <nescartdb.xml>] [--info] [--ram-init zero|pattern|random] [--mono] [--config <file>] [--scale 1-8] [--fullscreen] [--vsync] [--par-correct] [--screenshots-dir <dir>] [--states-dir <dir>] [--turbo] [--turbo-speed <x>|max] [--volume 0-1] [--sample-rate <hz>] [--profile] <rom.nes>", args[0]);
 
-    let rom_path = &args[1];
-    let rom_data = fs::read(rom_path).unwrap_or_else(|e| {
+    let mut config = FrontendConfig::default();
+    let mut rom_path = None;
+    let mut config_path: Option<PathBuf> = None;
+    let mut fm2_playback = None;
+    let mut region = None;
+    let mut use_db = true;
+    let mut db_path = None;
+    let mut info_only = false;
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--overscan" => {
+                let lines = iter.next().and_then(|v| v.parse::<u8>().ok()).unwrap_or_else(|| {
+                    eprintln!("--overscan expects a line count\n{}", usage);
+                    process::exit(1);
+                });
+                config.overscan = OverscanConfig { top: lines, bottom: lines, left: 0, right: 0 };
+            }
+            "--break" => {
+                let addr = iter.next().and_then(|v| frontend::parse_hex_addr(v)).unwrap_or_else(|| {
+                    eprintln!("--break expects a hex address\n{}", usage);
+                    process::exit(1);
+                });
+                config.breakpoints.push(addr);
+            }
+            "--watch" => {
+                let addr = iter.next().and_then(|v| frontend::parse_hex_addr(v)).unwrap_or_else(|| {
+                    eprintln!("--watch expects a hex address\n{}", usage);
+                    process::exit(1);
+                });
+                config.watchpoints.push(addr);
+            }
+            "--genie" => {
+                let codes = iter
+                    .next()
+                    .ok_or_else(|| format!("--genie expects a code or a file of codes\n{}", usage))
+                    .and_then(|v| genie_codes(v))
+                    .unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    });
+                config.cheats.extend(codes);
+            }
+            "--zapper" => config.zapper = true,
+            "--mono" => config.audio.mono = true,
+            "--no-db" => use_db = false,
+            "--info" => info_only = true,
+            "--fullscreen" => config.fullscreen = true,
+            "--vsync" => config.vsync = true,
+            "--par-correct" => config.par_correct = true,
+            "--turbo" => config.turbo = true,
+            "--profile" => {
+                if !cfg!(feature = "profiling") {
+                    eprintln!("--profile needs a build with the profiling feature");
+                    process::exit(1);
+                }
+                config.profile = true;
+            }
+            "--turbo-speed" => {
+                config.turbo_speed_multiplier = iter.next().and_then(|v| frontend::parse_turbo_speed(v)).unwrap_or_else(|| {
+                    eprintln!("--turbo-speed expects a multiplier of at least 1, or max\n{}", usage);
+                    process::exit(1);
+                });
+            }
+            "--volume" => {
+                config.volume = iter.next().and_then(|v| frontend::parse_volume(v)).unwrap_or_else(|| {
+                    eprintln!("--volume expects a number from 0 to 1\n{}", usage);
+                    process::exit(1);
+                });
+            }
+            "--sample-rate" => {
+                config.audio.sample_rate = iter.next().and_then(|v| frontend::parse_sample_rate(v)).unwrap_or_else(|| {
+                    eprintln!("--sample-rate expects a rate in Hz from 8000 to 192000\n{}", usage);
+                    process::exit(1);
+                });
+            }
+            "--scale" => {
+                config.scale = iter.next().and_then(|v| frontend::parse_scale(v)).unwrap_or_else(|| {
+                    eprintln!("--scale expects a whole number from 1 to 8\n{}", usage);
+                    process::exit(1);
+                });
+            }
+            "--screenshots-dir" => {
+                let path = iter.next().unwrap_or_else(|| {
+                    eprintln!("--screenshots-dir expects a directory\n{}", usage);
+                    process::exit(1);
+                });
+                config.screenshots_dir = path.into();
+            }
+            "--states-dir" => {
+                let path = iter.next().unwrap_or_else(|| {
+                    eprintln!("--states-dir expects a directory\n{}", usage);
+                    process::exit(1);
+                });
+                config.states_dir = path.into();
+            }
+            "--db" => {
+                let path = iter.next().unwrap_or_else(|| {
+                    eprintln!("--db expects a NesCartDB XML file\n{}", usage);
+                    process::exit(1);
+                });
+                db_path = Some(path.clone());
+            }
+            "--config" => {
+                let path = iter.next().unwrap_or_else(|| {
+                    eprintln!("--config expects a file path\n{}", usage);
+                    process::exit(1);
+                });
+                config_path = Some(path.into());
+            }
+            "--region" => {
+                region = match iter.next().map(|v| v.to_ascii_lowercase()).as_deref() {
+                    Some("ntsc") => Some(Region::Ntsc),
+                    Some("pal") => Some(Region::Pal),
+                    Some("dendy") => Some(Region::Dendy),
+                    _ => {
+                        eprintln!("--region expects ntsc, pal or dendy\n{}", usage);
+                        process::exit(1);
+                    }
+                };
+            }
+            "--ram-init" => {
+                config.ram_init = match iter.next().map(|v| v.to_ascii_lowercase()).as_deref() {
+                    Some("zero") => PowerOnMode::Zero,
+                    Some("pattern") => PowerOnMode::HardwarePattern,
+                    Some("random") => {
+                        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+                        log::info!("RAM power-on seed: {}", seed);
+                        PowerOnMode::Random(seed)
+                    }
+                    _ => {
+                        eprintln!("--ram-init expects zero, pattern or random\n{}", usage);
+                        process::exit(1);
+                    }
+                };
+            }
+            "--record" => {
+                let path = iter.next().unwrap_or_else(|| {
+                    eprintln!("--record expects a movie path\n{}", usage);
+                    process::exit(1);
+                });
+                config.record_path = Some(path.into());
+            }
+            "--play" => {
+                let path = iter.next().unwrap_or_else(|| {
+                    eprintln!("--play expects a movie path\n{}", usage);
+                    process::exit(1);
+                });
+                let path = Path::new(path);
+                if is_fm2_path(path) {
+                    let movie = Fm2Movie::load(path).unwrap_or_else(|e| {
+                        eprintln!("Failed to load movie '{}': {}", path.display(), e);
+                        process::exit(1);
+                    });
+                    config.playback = Some(Movie::from(&movie));
+                    fm2_playback = Some(movie);
+                } else {
+                    let movie = Movie::load(path).unwrap_or_else(|e| {
+                        eprintln!("Failed to load movie '{}': {}", path.display(), e);
+                        process::exit(1);
+                    });
+                    config.playback = Some(movie);
+                }
+            }
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| {
+        eprintln!("{}", usage);
+        process::exit(1);
+    });
+    let rom_data = fs::read(&rom_path).unwrap_or_else(|e| {
         eprintln!("Failed to read ROM file '{}': {}", rom_path, e);
         process::exit(1);
     });
 
-    let cartridge = Cartridge::from_ines(&rom_data).unwrap_or_else(|e| {
+    let loaded_db = db_path.filter(|_| use_db).map(|path| {
+        fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|xml| RomDatabase::from_nescartdb(&xml).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load ROM database '{}': {}", path, e);
+                process::exit(1);
+            })
+    });
+    let db = use_db.then(|| loaded_db.as_ref().unwrap_or_else(|| RomDatabase::builtin()));
+    let cartridge = Cartridge::from_ines_with_db(&rom_data, db).unwrap_or_else(|e| {
         eprintln!("Failed to parse ROM: {}", e);
         process::exit(1);
     });
 
-    if let Err(e) = frontend::run(cartridge) {
+    if info_only {
+        if let Some(title) = &cartridge.rom_title {
+            println!("Title:      {}", title);
+        }
+        println!("{}", cartridge.header_info());
+        return;
+    }
+
+    // An explicit --region wins over the ROM database
+    config.region = region.or(cartridge.region).unwrap_or_default();
+
+    if let Some(movie) = fm2_playback.filter(|movie| !movie.matches_rom(cartridge.rom_crc32())) {
+        eprintln!(
+            "Warning: the movie was recorded on a different ROM (CRC32 {:08X}, this ROM is {:08X})",
+            movie.header.rom_crc32.unwrap_or_default(),
+            cartridge.rom_crc32()
+        );
+    }
+
+    // An explicit --config has to exist; the default one is optional
+    let config_file = match config_path {
+        Some(path) => Some((config::load_config(&path), path)),
+        None => config::default_config_path().map(|path| (config::load_config_or_default(&path), path)),
+    };
+    if let Some((bindings, path)) = config_file {
+        config.key_map = bindings
+            .map_err(|e| e.to_string())
+            .and_then(|bindings| KeyMap::new(&bindings))
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load {}: {}", path.display(), e);
+                process::exit(1);
+            });
+    }
+
+    let map_path = Path::new("controller_map.toml");
+    if map_path.is_file() {
+        config.gamepad_mapping = GamepadMapping::load(map_path).unwrap_or_else(|e| {
+            eprintln!("Failed to load {}: {}", map_path.display(), e);
+            process::exit(1);
+        });
+    }
+
+    if cartridge.has_battery {
+        config.save_path = Some(Path::new(&rom_path).with_extension("sav"));
+    }
+
+    if let Err(e) = frontend::run(cartridge, config) {
         eprintln!("Emulator error: {}", e);
         process::exit(1);
     }
@@ -0,0 +1,356 @@
+//! Input movies: one controller 1 and controller 2 button byte per frame,
+//! for recording and replaying deterministic runs. FCEUX `.fm2` movies are
+//! read and written through `Fm2Movie`.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 6] = b"VINES\x01";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Movie {
+    frames: Vec<[u8; 2]>,
+}
+
+impl Movie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_frame(&mut self, p1: u8, p2: u8) {
+        self.frames.push([p1, p2]);
+    }
+
+    /// Controller 1 and 2 buttons for frame `index`.
+    pub fn frame(&self, index: usize) -> Option<(u8, u8)> {
+        self.frames.get(index).map(|&[p1, p2]| (p1, p2))
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Serialize as the magic bytes, the frame count (u32 LE), then two bytes per frame.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MAGIC.len() + 4 + self.frames.len() * 2);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        out.extend(self.frames.iter().flatten());
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let rest = data.strip_prefix(MAGIC.as_slice()).ok_or_else(|| invalid("not a viNES movie"))?;
+        if rest.len() < 4 {
+            return Err(invalid("movie header truncated"));
+        }
+        let count = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let body = &rest[4..];
+        if body.len() != count * 2 {
+            return Err(invalid("movie frame count doesn't match its length"));
+        }
+        let frames = body.chunks_exact(2).map(|f| [f[0], f[1]]).collect();
+        Ok(Movie { frames })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// FM2 button characters, from bit 7 (Right) down to bit 0 (A). This is the
+/// same order as the `controller` button bits.
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+/// FM2 header keys `Fm2Movie::save` writes itself rather than from `metadata`.
+const FM2_GENERATED_KEYS: [&str; 8] =
+    ["version", "romFilename", "palFlag", "romCRC32", "port0", "port1", "port2", "fourscore"];
+
+/// Header of an FCEUX movie.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fm2Header {
+    pub rom_filename: String,
+    /// CRC-32 of the ROM's PRG and CHR data (`Cartridge::rom_crc32`). FCEUX
+    /// itself records an MD5 in `romChecksum`, so this is our own `romCRC32`
+    /// key, which FCEUX ignores.
+    pub rom_crc32: Option<u32>,
+    /// From the `comment author` line.
+    pub author: Option<String>,
+    pub pal: bool,
+    /// Every other header line, kept in order so saving doesn't drop it.
+    pub metadata: Vec<(String, String)>,
+}
+
+/// Controller 1 and 2 buttons for one FM2 frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Fm2Frame {
+    pub port1: u8,
+    pub port2: u8,
+}
+
+/// A text FCEUX movie with standard controllers in ports 1 and 2. Reset
+/// commands in the frame lines are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fm2Movie {
+    pub header: Fm2Header,
+    pub frames: Vec<Fm2Frame>,
+}
+
+impl Fm2Movie {
+    pub fn parse(r: impl Read) -> Result<Self, Fm2Error> {
+        let mut movie = Fm2Movie::default();
+        for (i, line) in BufReader::new(r).lines().enumerate() {
+            let line = line?;
+            let line = line.trim_end_matches('\r');
+            let error = |message: String| Fm2Error::Parse { line: i + 1, message };
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('|') {
+                movie.frames.push(parse_fm2_frame(line).map_err(error)?);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "romFilename" => movie.header.rom_filename = value.to_string(),
+                "palFlag" => movie.header.pal = value == "1",
+                "romCRC32" => {
+                    let crc = u32::from_str_radix(value, 16).map_err(|_| error(format!("bad romCRC32 '{}'", value)))?;
+                    movie.header.rom_crc32 = Some(crc);
+                }
+                "comment" if value.starts_with("author ") => {
+                    movie.header.author = Some(value["author ".len()..].trim().to_string());
+                }
+                "binary" if value == "1" => return Err(error("binary FM2 movies aren't supported".into())),
+                "fourscore" if value == "1" => return Err(error("Four Score movies aren't supported".into())),
+                "port0" | "port1" if value != "0" && value != "1" => {
+                    return Err(error(format!("{} holds an unsupported device ({})", key, value)));
+                }
+                "version" | "port0" | "port1" | "port2" | "fourscore" => {}
+                _ => movie.header.metadata.push((key.to_string(), value.to_string())),
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn save(&self, mut w: impl Write) -> io::Result<()> {
+        let header = &self.header;
+        writeln!(w, "version 3")?;
+        for (key, value) in header.metadata.iter().filter(|(key, _)| !FM2_GENERATED_KEYS.contains(&key.as_str())) {
+            writeln!(w, "{} {}", key, value)?;
+        }
+        writeln!(w, "romFilename {}", header.rom_filename)?;
+        writeln!(w, "palFlag {}", header.pal as u8)?;
+        if let Some(crc) = header.rom_crc32 {
+            writeln!(w, "romCRC32 {:08X}", crc)?;
+        }
+        if let Some(author) = &header.author {
+            writeln!(w, "comment author {}", author)?;
+        }
+        writeln!(w, "fourscore 0\nport0 1\nport1 1\nport2 0")?;
+        for frame in &self.frames {
+            writeln!(w, "|0|{}|{}||", fm2_buttons(frame.port1), fm2_buttons(frame.port2))?;
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Fm2Error> {
+        Self::parse(fs::File::open(path)?)
+    }
+
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let mut w = io::BufWriter::new(fs::File::create(path)?);
+        self.save(&mut w)?;
+        w.flush()
+    }
+
+    /// Whether the movie was recorded on the ROM with CRC-32 `crc`. Movies
+    /// without a `romCRC32` match anything.
+    pub fn matches_rom(&self, crc: u32) -> bool {
+        self.header.rom_crc32.is_none_or(|recorded| recorded == crc)
+    }
+
+    pub fn from_movie(movie: &Movie, header: Fm2Header) -> Self {
+        let frames = movie.frames.iter().map(|&[port1, port2]| Fm2Frame { port1, port2 }).collect();
+        Fm2Movie { header, frames }
+    }
+}
+
+/// Whether `path` names an FCEUX movie rather than a native one.
+pub fn is_fm2_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("fm2"))
+}
+
+/// Plays back through `MovieMode::Playback` like a native movie.
+impl From<&Fm2Movie> for Movie {
+    fn from(fm2: &Fm2Movie) -> Self {
+        Movie { frames: fm2.frames.iter().map(|f| [f.port1, f.port2]).collect() }
+    }
+}
+
+/// Decode `|commands|port0|port1|port2|`.
+fn parse_fm2_frame(line: &str) -> Result<Fm2Frame, String> {
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 5 {
+        return Err("expected |commands|port0|port1|port2|".into());
+    }
+    Ok(Fm2Frame { port1: parse_fm2_buttons(fields[2])?, port2: parse_fm2_buttons(fields[3])? })
+}
+
+/// `RLDUTSBA`, with `.` or a space for a released button; an empty field is
+/// an unplugged port.
+fn parse_fm2_buttons(field: &str) -> Result<u8, String> {
+    if field.is_empty() {
+        return Ok(0);
+    }
+    if field.len() != FM2_BUTTONS.len() {
+        return Err(format!("controller field '{}' isn't 8 buttons", field));
+    }
+    Ok(field
+        .bytes()
+        .enumerate()
+        .filter(|&(_, c)| c != b'.' && c != b' ')
+        .fold(0, |buttons, (i, _)| buttons | (0x80 >> i)))
+}
+
+fn fm2_buttons(buttons: u8) -> String {
+    FM2_BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| if buttons & (0x80 >> i) != 0 { c as char } else { '.' })
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum Fm2Error {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl From<io::Error> for Fm2Error {
+    fn from(e: io::Error) -> Self {
+        Fm2Error::Io(e)
+    }
+}
+
+impl fmt::Display for Fm2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fm2Error::Io(e) => write!(f, "{}", e),
+            Fm2Error::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for Fm2Error {}
+
+/// What `Nes::step_frame` does with controller input.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum MovieMode {
+    #[default]
+    Off,
+    /// Append each completed frame's buttons to the movie.
+    Recording(Movie),
+    /// Drive the controllers from the movie; `frame` is the next one to play.
+    /// Returns to `Off` once the movie runs out.
+    Playback { movie: Movie, frame: usize },
+}
+
+impl MovieMode {
+    pub fn is_playing(&self) -> bool {
+        matches!(self, MovieMode::Playback { .. })
+    }
+
+    /// Buttons to force onto the controllers for the frame about to run.
+    pub(crate) fn playback_input(&self) -> Option<(u8, u8)> {
+        match self {
+            MovieMode::Playback { movie, frame } => movie.frame(*frame),
+            _ => None,
+        }
+    }
+
+    /// Called when a frame completes with the buttons it ran with.
+    pub(crate) fn frame_completed(&mut self, p1: u8, p2: u8) {
+        match self {
+            MovieMode::Off => {}
+            MovieMode::Recording(movie) => movie.record_frame(p1, p2),
+            MovieMode::Playback { movie, frame } => {
+                *frame += 1;
+                if *frame >= movie.len() {
+                    *self = MovieMode::Off;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_movie_bytes_round_trip() {
+        let mut movie = Movie::new();
+        movie.record_frame(0x01, 0x00);
+        movie.record_frame(0x88, 0x40);
+        let bytes = movie.to_bytes();
+        assert_eq!(&bytes[..10], b"VINES\x01\x02\x00\x00\x00");
+        assert_eq!(&bytes[10..], &[0x01, 0x00, 0x88, 0x40]);
+        assert_eq!(Movie::from_bytes(&bytes).unwrap(), movie);
+
+        assert!(Movie::from_bytes(b"VINES\x02\x00\x00\x00\x00").is_err());
+        assert!(Movie::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    const FM2_FIXTURE: &str = include_str!("../tests/movies/minimal.fm2");
+
+    #[test]
+    fn test_fm2_parse() {
+        use crate::controller::{BUTTON_A, BUTTON_RIGHT, BUTTON_START, BUTTON_UP};
+
+        let movie = Fm2Movie::parse(FM2_FIXTURE.as_bytes()).unwrap();
+        assert_eq!(movie.header.rom_filename, "test");
+        assert_eq!(movie.header.rom_crc32, Some(0xCBF4_3926));
+        assert_eq!(movie.header.author.as_deref(), Some("tester"));
+        assert!(!movie.header.pal);
+        let port1: Vec<u8> = movie.frames.iter().map(|f| f.port1).collect();
+        assert_eq!(port1, [0, BUTTON_A, BUTTON_RIGHT | BUTTON_A, BUTTON_START]);
+        assert_eq!(movie.frames[2].port2, BUTTON_UP);
+
+        assert!(movie.matches_rom(0xCBF4_3926));
+        assert!(!movie.matches_rom(0));
+
+        assert!(matches!(Fm2Movie::parse("|0|RLDU|........||".as_bytes()), Err(Fm2Error::Parse { line: 1, .. })));
+        assert!(matches!(Fm2Movie::parse("version 3\nport1 2\n".as_bytes()), Err(Fm2Error::Parse { line: 2, .. })));
+    }
+
+    #[test]
+    fn test_fm2_save_round_trip() {
+        let movie = Fm2Movie::parse(FM2_FIXTURE.as_bytes()).unwrap();
+        let mut saved = Vec::new();
+        movie.save(&mut saved).unwrap();
+        let text = String::from_utf8(saved).unwrap();
+        assert!(text.starts_with("version 3\n"));
+        assert!(text.contains("\nrerecordCount 7\n"));
+        assert!(text.contains("\n|0|R......A|...U....||\n"));
+        assert_eq!(Fm2Movie::parse(text.as_bytes()).unwrap(), movie);
+
+        // Through a native movie and back
+        let native = Movie::from(&movie);
+        assert_eq!(native.frame(2), Some((movie.frames[2].port1, movie.frames[2].port2)));
+        assert_eq!(Fm2Movie::from_movie(&native, movie.header.clone()), movie);
+    }
+}
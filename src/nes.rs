@@ -1,62 +1,1114 @@
 use crossbeam::queue::ArrayQueue;
+#[cfg(feature = "save_states")]
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
 use std::sync::Arc;
 
+use crate::apu::{SampleSink, DEFAULT_SAMPLE_RATE};
 use crate::bus::Bus;
 use crate::cartridge::Cartridge;
-use crate::cpu::Cpu;
+use crate::cpu::{Cpu, StepResult};
+use crate::movie::MovieMode;
+use crate::ppu::frame::Frame;
+#[cfg(feature = "rewind")]
+use crate::util::RingBuffer;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HaltReason {
+    InfiniteLoop,
+    KillOpcode,
+    FrameLimit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HaltResult {
+    pub halted: bool,
+    pub frames: u32,
+    pub final_pc: u16,
+    pub halt_reason: HaltReason,
+}
+
+/// Console timing variant.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    /// Famiclone timing: PAL's 312 lines and 50Hz with NTSC's 3 dots per CPU cycle.
+    Dendy,
+}
+
+impl Region {
+    pub fn cpu_freq(self) -> f64 {
+        match self {
+            Region::Ntsc => 1_789_773.0,
+            Region::Pal => 1_662_607.0,
+            Region::Dendy => 1_773_448.0,
+        }
+    }
+
+    /// Frame rate as the fraction `fps_numer / fps_denom`: the CPU clock over
+    /// CPU cycles per frame, doubled where that count is a half cycle.
+    pub fn fps_numer(self) -> u64 {
+        match self {
+            Region::Ntsc => 3_579_546,
+            Region::Pal => 3_325_214,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    pub fn fps_denom(self) -> u64 {
+        match self {
+            Region::Ntsc => 59_561,
+            Region::Pal => 66_495,
+            Region::Dendy => 35_464,
+        }
+    }
+
+    pub fn nanos_per_frame(self) -> u64 {
+        1_000_000_000 * self.fps_denom() / self.fps_numer()
+    }
+
+    /// Scanlines per frame, including vblank and the pre-render line.
+    pub fn scanlines(self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// Scanline whose first dot sets the vblank flag.
+    pub fn vblank_scanline(self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// PPU dots per CPU cycle, in fifths: PAL runs 3.2 dots per cycle.
+    fn ppu_dots_x5(self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Dendy => 15,
+            Region::Pal => 16,
+        }
+    }
+}
+
+/// Machine-wide settings chosen before power-on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct NesConfig {
+    pub region: Region,
+    /// Audio output rate in Hz.
+    pub sample_rate: u32,
+}
+
+impl Default for NesConfig {
+    fn default() -> Self {
+        NesConfig { region: Region::default(), sample_rate: DEFAULT_SAMPLE_RATE }
+    }
+}
+
+/// How `Nes::step_frame` ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameResult {
+    Completed,
+    /// Stopped before the instruction at this address; the next step runs it.
+    Breakpoint(u16),
+    /// The last instruction wrote `value` to a watched address.
+    Watchpoint { addr: u16, value: u8 },
+    /// Hit the instruction safety limit without finishing a frame.
+    Incomplete,
+}
+
+/// What happened during one `Nes` step.
+struct StepEvents {
+    frame_complete: bool,
+    nmi: bool,
+    breakpoint: Option<u16>,
+    watchpoint: Option<(u16, u8)>,
+}
+
+/// Bumped whenever the serialized layout of the machine changes.
+#[cfg(feature = "save_states")]
+const STATE_VERSION: u32 = 21;
+
+#[cfg(feature = "save_states")]
+#[derive(Debug)]
+pub enum StateError {
+    VersionMismatch { expected: u32, found: u32 },
+    Deserialize(String),
+}
+
+#[cfg(feature = "save_states")]
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::VersionMismatch { expected, found } => {
+                write!(f, "Save state version {} is not supported (expected {})", found, expected)
+            }
+            StateError::Deserialize(e) => write!(f, "Corrupt save state: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "save_states")]
+impl std::error::Error for StateError {}
+
+/// Frames between rewind snapshots.
+#[cfg(feature = "rewind")]
+const REWIND_INTERVAL: u64 = 30;
+/// Snapshots kept: 20 at 30 frames apiece is 10 seconds.
+#[cfg(feature = "rewind")]
+const REWIND_SLOTS: usize = 20;
+
+/// A `save_state` blob kept in the rewind buffer.
+#[cfg(feature = "rewind")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveState(pub Vec<u8>);
+
+#[cfg(feature = "rewind")]
+fn rewind_buffer() -> RingBuffer<SaveState> {
+    RingBuffer::new(REWIND_SLOTS)
+}
 
 #[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub struct Nes {
     pub cpu: Cpu,
     pub bus: Bus,
+    config: NesConfig,
+    /// PPU dots owed to the CPU, in fifths of a dot (only nonzero on PAL).
+    ppu_dot_fraction: u16,
+    /// Where `save` writes battery-backed PRG RAM.
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    save_path: Option<PathBuf>,
+    /// Input movie being recorded or played back by `step_frame`.
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    pub movie: MovieMode,
+    /// Recent snapshots for `rewind`, oldest overwritten first.
+    #[cfg(feature = "rewind")]
+    #[serde(skip, default = "rewind_buffer")]
+    rewind: RingBuffer<SaveState>,
 }
 
 impl Nes {
     pub fn new(cartridge: Cartridge, sample_buffer: Arc<ArrayQueue<f32>>) -> Self {
-        Nes {
+        Self::with_audio_output(cartridge, SampleSink::Queue(sample_buffer))
+    }
+
+    /// A machine with no audio device attached: samples are buffered internally
+    /// and handed out by `step_frame_headless`.
+    pub fn new_headless(cartridge: Cartridge) -> Self {
+        Self::with_audio_output(cartridge, SampleSink::Buffer(Vec::new()))
+    }
+
+    /// A machine that discards its audio, for benchmarks and other runs that
+    /// only care about the CPU and picture.
+    pub fn new_no_audio(cartridge: Cartridge) -> Self {
+        Self::with_audio_output(cartridge, SampleSink::Discard)
+    }
+
+    fn with_audio_output(cartridge: Cartridge, output: SampleSink) -> Self {
+        let mut nes = Nes {
             cpu: Cpu::new(),
-            bus: Bus::new(cartridge, sample_buffer),
+            bus: Bus::with_audio_output(cartridge, output),
+            config: NesConfig::default(),
+            ppu_dot_fraction: 0,
+            save_path: None,
+            movie: MovieMode::Off,
+            #[cfg(feature = "rewind")]
+            rewind: rewind_buffer(),
+        };
+        nes.cpu.power_on_reset(&mut nes.bus);
+        nes
+    }
+
+    pub fn config(&self) -> &NesConfig {
+        &self.config
+    }
+
+    /// Switch console timing.
+    pub fn set_region(&mut self, region: Region) {
+        self.config.region = region;
+        self.bus.ppu.set_region(region);
+        self.bus.apu.set_region(region);
+        self.ppu_dot_fraction = 0;
+    }
+
+    /// Resample audio to `sample_rate` Hz, to match the output device.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.config.sample_rate = sample_rate;
+        self.bus.apu.set_sample_rate(sample_rate);
+    }
+
+    /// Persist battery-backed PRG RAM to `path`, loading it now if the file
+    /// already exists. Does nothing for cartridges without a battery.
+    pub fn set_save_path(&mut self, path: PathBuf) -> io::Result<()> {
+        if self.bus.mapper.save_ram().is_none() {
+            return Ok(());
         }
+        match std::fs::read(&path) {
+            Ok(data) => self.bus.mapper.load_ram(&data),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.save_path = Some(path);
+        Ok(())
     }
 
+    /// Serialize the whole machine (CPU, RAM, PPU, APU and mapper, including
+    /// ROM data) to a versioned bincode blob.
+    #[cfg(feature = "save_states")]
+    pub fn save_state(&self) -> Vec<u8> {
+        bincode::serialize(&(STATE_VERSION, self)).expect("machine state is always serializable")
+    }
+
+    /// Replace the running machine with one from `save_state`. The audio queue,
+    /// battery save path and PPU register log stay attached to this instance.
+    #[cfg(feature = "save_states")]
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let found: u32 = bincode::deserialize(data).map_err(|e| StateError::Deserialize(e.to_string()))?;
+        if found != STATE_VERSION {
+            return Err(StateError::VersionMismatch { expected: STATE_VERSION, found });
+        }
+        let (_, mut state): (u32, Nes) =
+            bincode::deserialize(data).map_err(|e| StateError::Deserialize(e.to_string()))?;
+        state.bus.apu.output = std::mem::replace(&mut self.bus.apu.output, SampleSink::Buffer(Vec::new()));
+        state.bus.apu.panning = self.bus.apu.panning;
+        state.bus.apu.master_volume = self.bus.apu.master_volume;
+        state.bus.apu.channel_mute = self.bus.apu.channel_mute;
+        if state.config.sample_rate != self.config.sample_rate {
+            state.set_sample_rate(self.config.sample_rate);
+        }
+        state.save_path = self.save_path.take();
+        state.cpu.debugger = std::mem::take(&mut self.cpu.debugger);
+        state.bus.watchpoint = std::mem::take(&mut self.bus.watchpoint);
+        state.bus.cheats = std::mem::take(&mut self.bus.cheats);
+        #[cfg(feature = "ppu-debug-log")]
+        std::mem::swap(&mut state.bus.ppu.debug_log, &mut self.bus.ppu.debug_log);
+        state.movie = std::mem::take(&mut self.movie);
+        #[cfg(feature = "rewind")]
+        std::mem::swap(&mut state.rewind, &mut self.rewind);
+        *self = state;
+        Ok(())
+    }
+
+    /// Restore the most recent rewind snapshot, dropping it from the buffer.
+    /// Returns false once the buffer is empty.
+    #[cfg(feature = "rewind")]
+    pub fn rewind(&mut self) -> bool {
+        match self.rewind.pop_latest() {
+            Some(SaveState(data)) => {
+                self.load_state(&data).expect("rewind snapshots come from this machine");
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of snapshots `rewind` can still go back through.
+    #[cfg(feature = "rewind")]
+    pub fn rewind_len(&self) -> usize {
+        self.rewind.len()
+    }
+
+    /// Write battery-backed PRG RAM to the save path. This isn't done on drop,
+    /// since save states are clones of the whole machine.
+    pub fn save(&self) -> io::Result<()> {
+        match (&self.save_path, self.bus.mapper.save_ram()) {
+            (Some(path), Some(ram)) => std::fs::write(path, ram),
+            _ => Ok(()),
+        }
+    }
+
+    /// Press the reset button. The CPU restarts from the reset vector with
+    /// its registers and RAM kept; a new `Nes` has already been powered on.
     pub fn reset(&mut self) {
         self.cpu.reset(&mut self.bus);
     }
 
+    /// The most recently rendered frame.
+    pub fn frame(&self) -> &Frame {
+        &self.bus.ppu.frame
+    }
+
+    /// Mutable frame access, for drawing overlays on top of the PPU output.
+    pub fn frame_mut(&mut self) -> &mut Frame {
+        &mut self.bus.ppu.frame
+    }
+
     /// Run one CPU instruction, then catch up PPU and APU. Returns true if frame is complete.
+    /// At a breakpoint nothing runs; `cpu.debugger.hit()` reports it.
     pub fn step(&mut self) -> bool {
-        let cpu_cycles = self.cpu.step(&mut self.bus);
-        let ppu_cycles = cpu_cycles as u16 * 3;
+        self.step_events().frame_complete
+    }
+
+    /// Execute exactly one instruction, stepping past a breakpoint if one is in
+    /// the way. Returns whether a frame completed and the instruction's trace line.
+    pub fn step_one(&mut self) -> (bool, String) {
+        let trace = self.cpu.trace(&self.bus);
+        let events = self.step_past_breakpoint();
+        (events.frame_complete, trace)
+    }
+
+    /// Step until the CPU is about to run the instruction at `target`, for
+    /// tests that need the machine at a known point. Breakpoints are stepped
+    /// over. Returns false if `max_steps` steps (DMA stall cycles included)
+    /// don't get there.
+    pub fn step_to_pc(&mut self, target: u16, max_steps: u64) -> bool {
+        for _ in 0..max_steps {
+            if self.cpu.pc == target {
+                return true;
+            }
+            self.step_past_breakpoint();
+        }
+        self.cpu.pc == target
+    }
+
+    /// Run exactly `n` CPU instructions, across frame boundaries and through
+    /// breakpoints. DMA stalls and interrupt sequences don't count. Stops early
+    /// if the CPU jams.
+    pub fn step_n_instructions(&mut self, n: u64) {
+        let mut executed = 0;
+        while executed < n && !self.cpu.halted {
+            if self.cpu.next_step_is_instruction() {
+                executed += 1;
+            }
+            self.step_past_breakpoint();
+        }
+    }
+
+    fn step_past_breakpoint(&mut self) -> StepEvents {
+        let events = self.step_events();
+        if events.breakpoint.is_some() {
+            return self.step_events();
+        }
+        events
+    }
+
+    /// Run until the CPU takes an NMI. Returns false if a breakpoint or
+    /// watchpoint stops it first, or after a frame's worth of instructions (e.g. with NMIs disabled).
+    pub fn run_until_nmi(&mut self) -> bool {
+        for _ in 0..40_000 {
+            let events = self.step_events();
+            if events.breakpoint.is_some() || events.watchpoint.is_some() {
+                return false;
+            }
+            if events.nmi {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn step_events(&mut self) -> StepEvents {
+        self.bus.watchpoint.clear_trigger();
+        let nmi_was_pending = self.cpu.nmi_pending;
+        let cpu_cycles = match self.cpu.step(&mut self.bus) {
+            StepResult::Normal(cycles) => cycles,
+            StepResult::Breakpoint(addr) => {
+                return StepEvents { frame_complete: false, nmi: false, breakpoint: Some(addr), watchpoint: None };
+            }
+        };
+        let dots_x5 = cpu_cycles as u16 * self.config.region.ppu_dots_x5() + self.ppu_dot_fraction;
+        let ppu_cycles = dots_x5 / 5;
+        self.ppu_dot_fraction = dots_x5 % 5;
         let mut frame_complete = false;
+        let mut nmi_dot = None;
 
-        for _ in 0..ppu_cycles {
-            if self.bus.ppu.tick() {
+        for dot in 0..ppu_cycles {
+            if self.bus.tick_ppu() {
                 frame_complete = true;
             }
+            if nmi_dot.is_none() && self.bus.ppu.nmi_pending {
+                nmi_dot = Some(dot);
+            }
         }
 
         // APU ticks at CPU rate
         for _ in 0..cpu_cycles {
-            self.bus.apu.tick();
+            self.bus.tick_apu();
+            if self.bus.dmc_fetch_pending {
+                self.cpu.stall += self.bus.calculate_dma_stall(self.cpu.stall);
+                self.bus.dmc_fetch_pending = false;
+            }
+        }
+        if self.cpu.stall == 0 {
+            self.bus.oam_dma_in_progress = false;
+        }
+        // An OAM DMA started by this instruction stalls the CPU from here on,
+        // plus an alignment cycle if it starts on an odd CPU cycle. The bus
+        // doesn't count cycles, so the parity comes from the CPU's count.
+        let dma_stall = std::mem::take(&mut self.bus.pending_stall);
+        if dma_stall > 0 {
+            let length = dma_stall + (self.cpu.cycles % 2) as u16;
+            self.cpu.stall += length;
+            self.bus.oam_dma_in_progress = true;
+            self.bus.oam_dma_length = length;
         }
 
+        // The CPU services both interrupts before its next instruction. Which
+        // cycle the NMI arrived on matters when this step was BRK or an IRQ.
+        let mut nmi = nmi_was_pending && !self.cpu.nmi_pending;
         if self.bus.ppu.nmi_pending {
             self.bus.ppu.nmi_pending = false;
-            self.cpu.nmi(&mut self.bus);
+            let cycle = nmi_dot.unwrap_or(ppu_cycles) as u32 * 5 / self.config.region.ppu_dots_x5() as u32;
+            nmi |= self.cpu.signal_nmi(&mut self.bus, cycle);
         }
+        // IRQ is level-triggered: it fires again until the source is acknowledged
+        self.cpu.irq_pending = self.bus.irq_pending();
 
-        frame_complete
+        StepEvents { frame_complete, nmi, breakpoint: None, watchpoint: self.bus.watchpoint.triggered() }
     }
 
-    /// Run until a full frame is rendered (with safety limit).
-    /// Returns true if frame completed normally, false if safety limit hit.
-    pub fn step_frame(&mut self) -> bool {
+    /// Run until a full frame is rendered, a breakpoint or watchpoint is
+    /// reached, or the safety limit runs out. A playing movie sets the
+    /// controllers first, and a recording one logs them once the frame is done.
+    pub fn step_frame(&mut self) -> FrameResult {
+        if let Some((p1, p2)) = self.movie.playback_input() {
+            self.bus.controller1.buttons = p1;
+            self.bus.controller2.buttons = p2;
+        }
+
+        let mut result = FrameResult::Incomplete;
         // ~29,781 CPU steps per frame; 40,000 is a generous safety margin
         for _ in 0..40_000 {
+            let events = self.step_events();
+            if let Some(addr) = events.breakpoint {
+                result = FrameResult::Breakpoint(addr);
+                break;
+            }
+            if let Some((addr, value)) = events.watchpoint {
+                result = FrameResult::Watchpoint { addr, value };
+                break;
+            }
+            if events.frame_complete {
+                result = FrameResult::Completed;
+                break;
+            }
+        }
+
+        if result == FrameResult::Completed {
+            let (p1, p2) = (self.bus.controller1.buttons, self.bus.controller2.buttons);
+            self.movie.frame_completed(p1, p2);
+
+            #[cfg(feature = "rewind")]
+            if self.bus.ppu.frame_count.is_multiple_of(REWIND_INTERVAL) {
+                self.rewind.push(SaveState(self.save_state()));
+            }
+        }
+
+        #[cfg(debug_assertions)]
+        self.bus.ppu.assert_invariants();
+
+        result
+    }
+
+    /// Run one frame and return the finished RGB888 picture along with the audio
+    /// samples produced during it. Samples are only collected by a `new_headless`
+    /// machine; with an audio queue attached, or none, the slice is empty.
+    pub fn step_frame_headless(&mut self) -> (&[u8], &[f32]) {
+        if let SampleSink::Buffer(buffer) = &mut self.bus.apu.output {
+            buffer.clear();
+        }
+        self.step_frame();
+
+        let samples = match &self.bus.apu.output {
+            SampleSink::Buffer(buffer) => &buffer[..],
+            SampleSink::Queue(_) | SampleSink::Discard => &[],
+        };
+        (self.bus.ppu.frame.front_slice(), samples)
+    }
+
+    /// Run until the CPU parks itself in an infinite loop or hits a KIL opcode,
+    /// or until `max_frames` frames have been rendered. Used for test ROM automation.
+    pub fn run_until_halt(&mut self, max_frames: u32) -> HaltResult {
+        let mut frames = 0;
+        while frames < max_frames {
             if self.step() {
-                return true;
+                frames += 1;
             }
+
+            let halt_reason = if self.cpu.is_in_infinite_loop(&self.bus) {
+                HaltReason::InfiniteLoop
+            } else if self.cpu.halted {
+                HaltReason::KillOpcode
+            } else {
+                continue;
+            };
+            return HaltResult {
+                halted: true,
+                frames,
+                final_pc: self.cpu.pc,
+                halt_reason,
+            };
         }
-        false
+
+        HaltResult {
+            halted: false,
+            frames,
+            final_pc: self.cpu.pc,
+            halt_reason: HaltReason::FrameLimit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Mirroring;
+
+    /// NROM-128 cartridge with `program` at $C000 and the reset vector pointing at it.
+    fn cartridge_with_program(program: &[u8]) -> Cartridge {
+        let mut prg_rom = vec![0xEA; 16384];
+        prg_rom[..program.len()].copy_from_slice(program);
+        prg_rom[0x3FFC] = 0x00;
+        prg_rom[0x3FFD] = 0xC0;
+        Cartridge::nrom(prg_rom, vec![0; 8192])
+    }
+
+    fn nes_with_program(program: &[u8]) -> Nes {
+        Nes::new(cartridge_with_program(program), Arc::new(ArrayQueue::new(4096)))
+    }
+
+    #[test]
+    fn test_run_until_halt_detects_jmp_to_self() {
+        let mut nes = nes_with_program(&[0xEA, 0xEA, 0x4C, 0x02, 0xC0]); // NOP; NOP; JMP $C002
+        let result = nes.run_until_halt(5);
+        assert!(result.halted);
+        assert_eq!(result.halt_reason, HaltReason::InfiniteLoop);
+        assert_eq!(result.final_pc, 0xC002);
+        assert_eq!(result.frames, 0);
+    }
+
+    #[test]
+    fn test_run_until_halt_detects_taken_branch_to_self() {
+        // LDA #$00; BEQ *
+        let mut nes = nes_with_program(&[0xA9, 0x00, 0xF0, 0xFE]);
+        let result = nes.run_until_halt(5);
+        assert_eq!(result.halt_reason, HaltReason::InfiniteLoop);
+        assert_eq!(result.final_pc, 0xC002);
+    }
+
+    #[test]
+    fn test_run_until_halt_ignores_untaken_branch_to_self() {
+        // LDA #$01; BEQ * (not taken); JMP $C004
+        let mut nes = nes_with_program(&[0xA9, 0x01, 0xF0, 0xFE, 0x4C, 0x04, 0xC0]);
+        let result = nes.run_until_halt(5);
+        assert_eq!(result.halt_reason, HaltReason::InfiniteLoop);
+        assert_eq!(result.final_pc, 0xC004);
+    }
+
+    #[test]
+    fn test_run_until_halt_detects_kil() {
+        let mut nes = nes_with_program(&[0xEA, 0x02]);
+        let result = nes.run_until_halt(5);
+        assert_eq!(result.halt_reason, HaltReason::KillOpcode);
+        assert_eq!(result.final_pc, 0xC001);
+    }
+
+    #[test]
+    fn test_run_until_halt_frame_limit() {
+        // Loop that never lands on itself: JMP $C003; JMP $C000
+        let mut nes = nes_with_program(&[0x4C, 0x03, 0xC0, 0x4C, 0x00, 0xC0]);
+        let result = nes.run_until_halt(2);
+        assert!(!result.halted);
+        assert_eq!(result.halt_reason, HaltReason::FrameLimit);
+        assert_eq!(result.frames, 2);
+    }
+
+    /// Compares against `tests/visual/palette_stripes.png`; run with
+    /// `VINES_UPDATE_GOLDEN=1` to rewrite it after an intended change.
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_visual_regression_palette_stripes() {
+        use crate::ppu::frame::FrameSnapshot;
+        use std::path::Path;
+
+        // Load 16 palette entries from $C080, fill the nametables and attributes
+        // with tiles 0-3 in runs of 4, then turn the background on and loop
+        let program = [
+            0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, // PPUADDR = $3F00
+            0xA2, 0x00, 0xBD, 0x80, 0xC0, 0x8D, 0x07, 0x20, 0xE8, 0xE0, 0x10, 0xD0, 0xF5,
+            0xA9, 0x20, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, // PPUADDR = $2000
+            0xA0, 0x04, 0x8A, 0x4A, 0x4A, 0x29, 0x03, 0x8D, 0x07, 0x20, 0xE8, 0xD0, 0xF5, 0x88, 0xD0, 0xF2,
+            0xA9, 0x00, 0x8D, 0x05, 0x20, 0x8D, 0x05, 0x20, // scroll 0, 0
+            0xA9, 0x0A, 0x8D, 0x01, 0x20, // PPUMASK: background, left column too
+            0x4C, 0x3E, 0xC0,
+        ];
+        let mut cartridge = cartridge_with_program(&program);
+        let palette = [0x0F, 0x16, 0x2A, 0x12, 0x0F, 0x27, 0x1A, 0x30, 0x0F, 0x05, 0x21, 0x38, 0x0F, 0x14, 0x3C, 0x00];
+        cartridge.prg_rom[0x80..0x90].copy_from_slice(&palette);
+        // Tile 1 solid color 1, tile 2 solid color 2, tile 3 a color 1/2/3 checkerboard
+        cartridge.chr_rom[16..24].fill(0xFF);
+        cartridge.chr_rom[40..48].fill(0xFF);
+        for row in 0..8 {
+            cartridge.chr_rom[48 + row] = if row % 2 == 0 { 0xF0 } else { 0xFF };
+            cartridge.chr_rom[56 + row] = if row % 2 == 0 { 0xFF } else { 0x0F };
+        }
+        let mut nes = Nes::new_no_audio(cartridge);
+        for _ in 0..3 {
+            assert_eq!(nes.step_frame(), FrameResult::Completed);
+        }
+
+        let golden = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/visual/palette_stripes.png");
+        let snapshot = nes.bus.ppu.snapshot();
+        if std::env::var_os("VINES_UPDATE_GOLDEN").is_some() {
+            snapshot.save_png(Path::new(golden)).unwrap();
+        }
+        let expected = FrameSnapshot::load_png(Path::new(golden)).unwrap();
+        let diff = snapshot.diff(&expected);
+        assert!(diff <= 0.001, "{:.2}% of pixels differ from {}", diff * 100.0, golden);
+    }
+
+    #[test]
+    fn test_step_one_and_run_until_nmi() {
+        // LDA #$80; STA $2000; JMP $C005, with the NMI vector at $C010
+        let mut cartridge = cartridge_with_program(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0xC0]);
+        cartridge.prg_rom[0x3FFA] = 0x10;
+        cartridge.prg_rom[0x3FFB] = 0xC0;
+        let mut nes = Nes::new(cartridge, Arc::new(ArrayQueue::new(4096)));
+
+        let (frame_complete, trace) = nes.step_one();
+        assert!(!frame_complete);
+        assert!(trace.starts_with("C000  A9 80     LDA #$80"), "{}", trace);
+        assert_eq!(nes.cpu.pc, 0xC002);
+
+        assert!(nes.run_until_nmi());
+        assert_eq!(nes.cpu.pc, 0xC010);
+    }
+
+    #[test]
+    fn test_vblank_nmi_during_brk() {
+        use crate::ppu::registers::PpuCtrl;
+
+        // BRK at $C000, NMI handler at $C100, IRQ/BRK handler at $C200
+        let brk_at_vblank = |scanline, cycle| {
+            let mut cartridge = cartridge_with_program(&[0x00, 0x00]);
+            cartridge.prg_rom[0x3FFA..0x3FFC].copy_from_slice(&[0x00, 0xC1]);
+            cartridge.prg_rom[0x3FFE..].copy_from_slice(&[0x00, 0xC2]);
+            let mut nes = nes_with_program(&[]);
+            nes.bus.mapper = cartridge.into_mapper();
+            nes.cpu.power_on_reset(&mut nes.bus);
+            nes.bus.ppu.ctrl = PpuCtrl::NMI_ENABLE;
+            (nes.bus.ppu.scanline, nes.bus.ppu.cycle) = (scanline, cycle);
+            nes
+        };
+
+        // Vblank starts on BRK's first cycle: the NMI hijacks it
+        let mut nes = brk_at_vblank(241, 1);
+        nes.step();
+        assert_eq!(nes.cpu.pc, 0xC100);
+
+        // Vblank starts on BRK's sixth cycle, after the vector fetch began: the
+        // BRK handler is entered, then immediately interrupted by the NMI
+        let mut nes = brk_at_vblank(240, 327);
+        nes.step();
+        assert_eq!(nes.cpu.pc, 0xC200);
+        assert!(nes.run_until_nmi());
+        assert_eq!(nes.cpu.pc, 0xC100);
+        let sp = 0x100 + nes.cpu.sp as usize;
+        assert_eq!((nes.bus.ram[sp + 3], nes.bus.ram[sp + 2]), (0xC2, 0x00));
+    }
+
+    #[test]
+    fn test_breakpoint_stops_step_frame() {
+        // NOP; NOP; JMP $C000
+        let mut nes = nes_with_program(&[0xEA, 0xEA, 0x4C, 0x00, 0xC0]);
+        nes.cpu.debugger.add_breakpoint(0xC002);
+        let cycles = nes.cpu.cycles;
+
+        assert_eq!(nes.step_frame(), FrameResult::Breakpoint(0xC002));
+        assert_eq!(nes.cpu.pc, 0xC002);
+        assert_eq!(nes.cpu.cycles, cycles + 4); // two NOPs, JMP not run yet
+        assert_eq!(nes.cpu.debugger.hit(), Some(0xC002));
+
+        // Resuming runs the JMP and stops on the next pass
+        assert_eq!(nes.step_frame(), FrameResult::Breakpoint(0xC002));
+        assert_eq!(nes.cpu.cycles, cycles + 4 + 3 + 4);
+
+        nes.cpu.debugger.remove_breakpoint(0xC002);
+        assert_eq!(nes.step_frame(), FrameResult::Completed);
+    }
+
+    #[test]
+    fn test_watchpoint_stops_step_frame() {
+        // loop: INC $0300; JMP loop
+        let mut nes = nes_with_program(&[0xEE, 0x00, 0x03, 0x4C, 0x00, 0xC0]);
+        nes.bus.watchpoint.add(0x0300);
+
+        // INC writes twice (old value, then new), but reports once
+        assert_eq!(nes.step_frame(), FrameResult::Watchpoint { addr: 0x0300, value: 1 });
+        assert_eq!(nes.cpu.pc, 0xC003);
+        assert_eq!(nes.step_frame(), FrameResult::Watchpoint { addr: 0x0300, value: 2 });
+        assert!(!nes.step()); // the JMP writes nothing
+        assert!(!nes.bus.watchpoint.watchpoint_triggered());
+
+        nes.bus.watchpoint.remove(0x0300);
+        assert_eq!(nes.step_frame(), FrameResult::Completed);
+    }
+
+    #[test]
+    fn test_step_one_steps_over_breakpoint() {
+        let mut nes = nes_with_program(&[0xEA, 0xEA]);
+        nes.cpu.debugger.add_breakpoint(0xC000);
+        let (_, trace) = nes.step_one();
+        assert!(trace.starts_with("C000"));
+        assert_eq!(nes.cpu.pc, 0xC001);
+    }
+
+    #[test]
+    fn test_game_genie_codes_patch_cpu_reads() {
+        use crate::cheat::GameGenieCode;
+
+        // LDA #$05; STA $0200; loop: JMP loop
+        let program = [0xA9, 0x05, 0x8D, 0x00, 0x02, 0x4C, 0x05, 0xC0];
+        for (compare, expected) in [(None, 0x2A), (Some(0x05), 0x2A), (Some(0x06), 0x05)] {
+            let mut nes = nes_with_program(&program);
+            nes.bus.cheats.add(GameGenieCode { address: 0xC001, value: 0x2A, compare });
+            nes.step();
+            nes.step();
+            assert_eq!(nes.bus.ram[0x200], expected, "compare {:?}", compare);
+            assert_eq!(nes.bus.peek(0xC001), expected);
+        }
+    }
+
+    #[test]
+    fn test_fm2_playback_drives_controllers() {
+        use crate::movie::{Fm2Movie, Movie};
+
+        let fm2 = Fm2Movie::parse(include_str!("../tests/movies/minimal.fm2").as_bytes()).unwrap();
+        let mut nes = nes_with_program(&[0x4C, 0x00, 0xC0]); // JMP $C000
+        nes.movie = MovieMode::Playback { movie: Movie::from(&fm2), frame: 0 };
+        for frame in &fm2.frames {
+            assert!(nes.movie.is_playing());
+            assert_eq!(nes.step_frame(), FrameResult::Completed);
+            assert_eq!((nes.bus.controller1.buttons, nes.bus.controller2.buttons), (frame.port1, frame.port2));
+        }
+        assert_eq!(nes.movie, MovieMode::Off);
+    }
+
+    /// 32KB of PRG with an IRQ line the test raises; any write to $8000-$FFFF
+    /// acknowledges it, as MMC3's $E000 does.
+    #[derive(Clone)]
+    struct IrqTestMapper {
+        prg_rom: Vec<u8>,
+        irq: Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl crate::cartridge::mapper::Mapper for IrqTestMapper {
+        fn cpu_read(&self, addr: u16) -> u8 {
+            if addr >= 0x8000 { self.prg_rom[addr as usize - 0x8000] } else { 0 }
+        }
+        fn cpu_write(&mut self, addr: u16, _val: u8) {
+            if addr >= 0x8000 {
+                self.irq.store(false, std::sync::atomic::Ordering::Relaxed);
+            }
+        }
+        fn chr_read(&self, _addr: u16) -> u8 {
+            0
+        }
+        fn chr_write(&mut self, _addr: u16, _val: u8) {}
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Horizontal
+        }
+        fn clone_box(&self) -> Box<dyn crate::cartridge::mapper::Mapper> {
+            Box::new(self.clone())
+        }
+        #[cfg(feature = "save_states")]
+        fn state(&self) -> crate::cartridge::mapper::MapperState {
+            unreachable!("never saved")
+        }
+        fn irq_pending(&self) -> bool {
+            self.irq.load(std::sync::atomic::Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn test_mapper_irq_interrupts_cpu_until_acknowledged() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let mut prg_rom = vec![0xEA; 0x8000];
+        // $8000: CLI; JMP $8001
+        prg_rom[..4].copy_from_slice(&[0x58, 0x4C, 0x01, 0x80]);
+        // $8010: INC $0200; STA $8000 (acknowledge); RTI
+        prg_rom[0x10..0x17].copy_from_slice(&[0xEE, 0x00, 0x02, 0x8D, 0x00, 0x80, 0x40]);
+        prg_rom[0x7FFC..].copy_from_slice(&[0x00, 0x80, 0x10, 0x80]); // reset, IRQ/BRK
+        let irq = Arc::new(AtomicBool::new(false));
+        let mut nes = nes_with_program(&[]);
+        nes.bus.mapper = Box::new(IrqTestMapper { prg_rom, irq: irq.clone() });
+        nes.cpu.power_on_reset(&mut nes.bus);
+
+        for _ in 0..10 {
+            nes.step();
+        }
+        assert!((0x8001..0x8004).contains(&nes.cpu.pc));
+
+        // The line is polled at the end of the instruction it rises during, and
+        // the IRQ is taken in place of the next one
+        irq.store(true, Ordering::Relaxed);
+        nes.step();
+        assert!(nes.cpu.irq_pending);
+        nes.step();
+        assert_eq!(nes.cpu.pc, 0x8010);
+        assert!(nes.cpu.status.contains(crate::cpu::CpuFlags::IRQ_DIS));
+
+        for _ in 0..20 {
+            nes.step();
+        }
+        assert!(!irq.load(Ordering::Relaxed));
+        assert_eq!(nes.bus.ram[0x200], 1, "handler ran exactly once");
+        assert!((0x8001..0x8004).contains(&nes.cpu.pc));
+    }
+
+    #[test]
+    fn test_zapper_reads_replace_controller_2() {
+        use crate::controller::Zapper;
+
+        let mut nes = nes_with_program(&[]);
+        nes.bus.controller2.buttons = 0xFF;
+        nes.bus.cpu_write(0x4016, 1);
+        assert_eq!(nes.bus.cpu_read(0x4017), 1);
+
+        // A white target at (100, 50) on an otherwise black screen, drawn
+        // earlier in the frame the PPU is on
+        nes.bus.ppu.frame.set_pixel(100, 50, (0xFF, 0xFF, 0xFF));
+        nes.bus.ppu.scanline = 60;
+        nes.bus.zapper = Some(Zapper::new());
+        let cases = [
+            (100, 50, false, 0x00), // on target: sensor bit clears
+            (100, 50, true, 0x10),
+            (0, 0, true, 0x18), // off target
+            (0, 0, false, 0x08),
+        ];
+        for (x, y, trigger, expected) in cases {
+            let zapper = nes.bus.zapper.as_mut().unwrap();
+            zapper.aim(x, y);
+            zapper.trigger = trigger;
+            assert_eq!(nes.bus.cpu_read(0x4017), expected, "({}, {}) trigger {}", x, y, trigger);
+            assert_eq!(nes.bus.peek(0x4017), expected);
+        }
+    }
+
+    #[test]
+    fn test_oam_dma_stalls_cpu() {
+        // LDA #$02 or LDA $00 (2 or 3 cycles), STA $4014, NOP
+        let cases = [(vec![0xA9, 0x02], 514), (vec![0xA5, 0x00], 513)];
+        for (lda, expected) in cases {
+            let mut program = lda;
+            program.extend_from_slice(&[0x8D, 0x14, 0x40, 0xEA]);
+            let mut nes = nes_with_program(&program);
+            nes.bus.ram[0] = 0x02;
+            for i in 0..256 {
+                nes.bus.ram[0x200 + i] = i as u8;
+            }
+
+            nes.step();
+            nes.step();
+            let start = nes.cpu.cycles;
+            assert_eq!(nes.cpu.stall, expected as u16);
+            while nes.cpu.stall > 0 {
+                nes.step();
+            }
+            assert_eq!(nes.cpu.cycles - start, expected, "DMA starting on cycle {}", start);
+            assert_eq!(nes.cpu.pc, 0xC005);
+            assert!(nes.bus.ppu.oam.iter().enumerate().all(|(i, &b)| b == i as u8));
+        }
+    }
+
+    #[test]
+    fn test_dmc_fetch_during_oam_dma() {
+        // LDA $00, STA $4014, NOP: a 513-cycle DMA
+        let mut nes = nes_with_program(&[0xA5, 0x00, 0x8D, 0x14, 0x40, 0xEA]);
+        nes.bus.ram[0] = 0x02;
+        nes.step();
+        nes.step();
+        let start = nes.cpu.cycles;
+        for _ in 0..10 {
+            nes.step();
+        }
+        // Empty sample buffer with a byte left to read: the DMC fetches next cycle
+        nes.bus.apu.dmc.bytes_remaining = 1;
+        while nes.cpu.stall > 0 {
+            nes.step();
+        }
+        assert_eq!(nes.cpu.cycles - start, 513 + 2);
+        assert!(!nes.bus.oam_dma_in_progress);
+
+        // Outside a DMA the same fetch halts the CPU for 4 cycles
+        let mut nes = nes_with_program(&[0xEA]);
+        nes.bus.apu.dmc.bytes_remaining = 1;
+        nes.step();
+        assert_eq!(nes.cpu.stall, 4);
+    }
+
+    #[test]
+    fn test_region_frame_timing() {
+        for (region, cpu_cycles_per_frame) in [(Region::Ntsc, 29_780.5), (Region::Pal, 33_247.5), (Region::Dendy, 35_464.0)] {
+            let mut nes = Nes::new_headless(cartridge_with_program(&[0x4C, 0x00, 0xC0])); // JMP $C000
+            nes.set_region(region);
+            nes.step_frame();
+            let start = nes.cpu.cycles;
+            for _ in 0..10 {
+                assert_eq!(nes.step_frame(), FrameResult::Completed);
+            }
+            let per_frame = (nes.cpu.cycles - start) as f64 / 10.0;
+            // NTSC skips no dot on odd frames here, so it runs half a cycle long
+            assert!((per_frame - cpu_cycles_per_frame).abs() <= 1.0, "{:?}: {}", region, per_frame);
+            assert_eq!(nes.bus.ppu.scanline, region.vblank_scanline());
+
+            let fps = 1e9 / region.nanos_per_frame() as f64;
+            assert!((fps - region.cpu_freq() / cpu_cycles_per_frame).abs() < 0.01, "{:?}: {} fps", region, fps);
+        }
+    }
+
+    #[test]
+    fn test_step_frame_headless_collects_samples() {
+        let mut nes = Nes::new_headless(cartridge_with_program(&[0x4C, 0x00, 0xC0])); // JMP $C000
+        nes.step_frame_headless();
+        let (pixels, samples) = nes.step_frame_headless();
+        assert_eq!(pixels.len(), 256 * 240 * 3);
+        // 44.1kHz at ~60.1 frames per second; the buffer is drained each frame
+        assert!((730..=740).contains(&samples.len()), "{} samples", samples.len());
+    }
+
+    // cargo test --release test_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn test_benchmark_1000_headless_frames() {
+        // LDA #$1E; STA $2001; JMP $C005 -- rendering on, then spin
+        let mut nes = Nes::new_no_audio(cartridge_with_program(&[0xA9, 0x1E, 0x8D, 0x01, 0x20, 0x4C, 0x05, 0xC0]));
+        let start = std::time::Instant::now();
+        for _ in 0..1000 {
+            nes.step_frame_headless();
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "1000 frames in {:.2?} ({:.0} fps)",
+            elapsed,
+            1000.0 / elapsed.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn test_no_audio_discards_samples() {
+        let mut nes = Nes::new_no_audio(cartridge_with_program(&[0x4C, 0x00, 0xC0])); // JMP $C000
+        assert_eq!(nes.step_frame(), FrameResult::Completed);
+        let (pixels, samples) = nes.step_frame_headless();
+        assert_eq!(pixels.len(), 256 * 240 * 3);
+        assert!(samples.is_empty());
+    }
+
+    #[test]
+    fn test_nes_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Cartridge>();
+        assert_send::<Nes>();
+    }
+
+    #[test]
+    fn test_battery_save_round_trip() {
+        let path = std::env::temp_dir().join(format!("vines_battery_{}.sav", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let ram: Vec<u8> = (0..8192).map(|i| (i * 7 + 3) as u8).collect();
+
+        let cartridge = Cartridge { has_battery: true, ..cartridge_with_program(&[]) };
+        let mut nes = Nes::new(cartridge, Arc::new(ArrayQueue::new(16)));
+        nes.set_save_path(path.clone()).unwrap(); // no file yet
+        for (i, &byte) in ram.iter().enumerate() {
+            nes.bus.mapper.cpu_write(0x6000 + i as u16, byte);
+        }
+        nes.save().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), ram);
+
+        let cartridge = Cartridge { has_battery: true, ..cartridge_with_program(&[]) };
+        let mut nes = Nes::new(cartridge, Arc::new(ArrayQueue::new(16)));
+        nes.set_save_path(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(nes.bus.mapper.save_ram(), Some(&ram[..]));
+        assert_eq!(nes.bus.mapper.cpu_read(0x7FFF), ram[8191]);
+    }
+
+    #[test]
+    fn test_no_save_without_battery() {
+        let path = std::env::temp_dir().join(format!("vines_no_battery_{}.sav", std::process::id()));
+        let mut nes = nes_with_program(&[]);
+        nes.set_save_path(path.clone()).unwrap();
+        nes.save().unwrap();
+        assert!(!path.exists());
+    }
+
+    /// 32KB PRG that loops `INC $0200` from $8000 (bank 0 on every mapper at
+    /// power-on), with 32KB of CHR.
+    #[cfg(feature = "save_states")]
+    fn looping_cartridge(mapper_id: u16) -> Cartridge {
+        let mut prg_rom = vec![0xEA; 0x8000];
+        prg_rom[..6].copy_from_slice(&[0xEE, 0x00, 0x02, 0x4C, 0x00, 0x80]);
+        prg_rom[0x7FFC] = 0x00;
+        prg_rom[0x7FFD] = 0x80;
+        Cartridge { prg_rom, chr_rom: vec![0x55; 0x8000], mapper_id, ..cartridge_with_program(&[]) }
+    }
+
+    #[cfg(feature = "save_states")]
+    #[test]
+    fn test_save_state_round_trip_all_mappers() {
+        for &mapper_id in crate::cartridge::mapper::SUPPORTED_MAPPERS {
+            let mut nes = Nes::new(looping_cartridge(mapper_id), Arc::new(ArrayQueue::new(4096)));
+            nes.step_frame();
+            let state = nes.save_state();
+
+            // Load into a machine running a different cartridge
+            let mut restored = nes_with_program(&[]);
+            restored.load_state(&state).unwrap();
+            assert_eq!(restored.save_state(), state, "mapper {}", mapper_id);
+            assert!(restored.bus.ram[0x200] > 0);
+
+            nes.step_frame();
+            restored.step_frame();
+            assert_eq!(restored.save_state(), nes.save_state(), "mapper {} diverged", mapper_id);
+        }
+    }
+
+    #[cfg(feature = "rewind")]
+    #[test]
+    fn test_rewind_restores_snapshots() {
+        // loop: INC $0200; JMP loop
+        let mut nes = nes_with_program(&[0xEE, 0x00, 0x02, 0x4C, 0x00, 0xC0]);
+        let mut at_frame_60 = None;
+        while nes.bus.ppu.frame_count < 75 {
+            nes.step_frame();
+            if nes.bus.ppu.frame_count == 60 {
+                at_frame_60 = Some((nes.cpu.pc, nes.cpu.cycles, nes.bus.ram[0x200]));
+            }
+        }
+        // The first frame completes before the counter ticks, so frame 0 is snapshotted too
+        assert_eq!(nes.rewind_len(), 3);
+
+        assert!(nes.rewind());
+        assert_eq!(nes.bus.ppu.frame_count, 60);
+        assert_eq!(Some((nes.cpu.pc, nes.cpu.cycles, nes.bus.ram[0x200])), at_frame_60);
+        assert!(nes.rewind());
+        assert_eq!(nes.bus.ppu.frame_count, 30);
+        assert!(nes.rewind());
+        assert!(!nes.rewind());
+        assert_eq!(nes.bus.ppu.frame_count, 0);
+    }
+
+    #[cfg(feature = "save_states")]
+    #[test]
+    fn test_load_state_errors() {
+        let mut nes = nes_with_program(&[]);
+        let mut state = nes.save_state();
+        state[0] = state[0].wrapping_add(1);
+        assert!(matches!(
+            nes.load_state(&state),
+            Err(StateError::VersionMismatch { expected: STATE_VERSION, .. })
+        ));
+        let mut truncated = STATE_VERSION.to_le_bytes().to_vec();
+        truncated.push(0xFF);
+        assert!(matches!(nes.load_state(&truncated), Err(StateError::Deserialize(_))));
     }
 }
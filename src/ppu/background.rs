@@ -0,0 +1,170 @@
+use super::Ppu;
+use super::registers::PpuMask;
+
+/// Background shift registers and the tile latches that refill them. Each
+/// 16-bit shifter holds the tile being drawn in its high byte and the next
+/// tile in its low byte; attribute bits are widened to 8 bits per tile so
+/// they shift in step with the pattern planes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct PpuBackground {
+    pub pattern_lo: u16,
+    pub pattern_hi: u16,
+    pub attr_lo: u16,
+    pub attr_hi: u16,
+
+    // Fetched for the next tile, loaded into the shifters every 8 dots
+    pub next_tile: u8,
+    pub next_attr: u8,
+    pub next_lo: u8,
+    pub next_hi: u8,
+}
+
+impl PpuBackground {
+    fn load(&mut self) {
+        let widen = |bit: u8| if bit != 0 { 0xFF } else { 0x00 };
+        self.pattern_lo = (self.pattern_lo & 0xFF00) | self.next_lo as u16;
+        self.pattern_hi = (self.pattern_hi & 0xFF00) | self.next_hi as u16;
+        self.attr_lo = (self.attr_lo & 0xFF00) | widen(self.next_attr & 1);
+        self.attr_hi = (self.attr_hi & 0xFF00) | widen(self.next_attr & 2);
+    }
+
+    fn shift(&mut self) {
+        self.pattern_lo <<= 1;
+        self.pattern_hi <<= 1;
+        self.attr_lo <<= 1;
+        self.attr_hi <<= 1;
+    }
+
+    /// Color index (0-3) and palette (0-3) of the pixel `fine_x` dots into the current tile.
+    fn pixel(&self, fine_x: u8) -> (u8, u8) {
+        let mux = 0x8000 >> fine_x;
+        let bit = |shifter: u16| (shifter & mux != 0) as u8;
+        (
+            bit(self.pattern_hi) << 1 | bit(self.pattern_lo),
+            bit(self.attr_hi) << 1 | bit(self.attr_lo),
+        )
+    }
+}
+
+impl Ppu {
+    /// Background fetches for the current dot of a visible or pre-render line.
+    /// Dots 2-257 and 321-337 shift the registers; every 8 dots fetch the
+    /// nametable byte, attribute, then the low and high pattern planes, and
+    /// step coarse X. Dots 321-336 prefetch the first two tiles of the next
+    /// line. Dot 256 steps V down a row and dot 257 reloads its horizontal bits
+    /// from T.
+    pub(super) fn clock_background_fetch(&mut self) {
+        let cycle = self.cycle;
+        if (2..=257).contains(&cycle) || (321..=337).contains(&cycle) {
+            self.bg.shift();
+            match (cycle - 1) % 8 {
+                0 => {
+                    self.bg.load();
+                    self.bg.next_tile = self.internal_read(0x2000 | (self.v & 0x0FFF));
+                }
+                2 => self.bg.next_attr = self.fetch_bg_attribute(),
+                4 => self.bg.next_lo = self.internal_read(self.bg_pattern_addr()),
+                6 => self.bg.next_hi = self.internal_read(self.bg_pattern_addr() + 8),
+                7 => self.increment_v_x(),
+                _ => {}
+            }
+        }
+
+        if cycle == 256 {
+            self.increment_v_y();
+        }
+        // A $2005 write made before this dot of scanline N scrolls scanline N+1
+        // horizontally. Vertical scroll in T is only copied on the pre-render
+        // line, so mid-frame vertical splits need a $2006 write, which loads V
+        // directly and takes effect from the next tile fetch.
+        if cycle == 257 {
+            self.v = (self.v & !super::V_HORIZONTAL_BITS) | (self.t & super::V_HORIZONTAL_BITS);
+        }
+    }
+
+    /// Output the background pixel for the current dot (1-256) of a visible line.
+    pub(super) fn clock_background_pixel(&mut self) {
+        let x = (self.cycle - 1) as usize;
+        let shown = self.mask.contains(PpuMask::SHOW_BG) && (x >= 8 || self.mask.contains(PpuMask::SHOW_BG_LEFT));
+        let (pixel, palette_index) = if shown { self.bg.pixel(self.fine_x) } else { (0, 0) };
+        self.bg_pixel_row[x] = pixel;
+
+        let color = if pixel == 0 {
+            self.palette_color(0)
+        } else {
+            self.palette_color((palette_index as usize * 4 + pixel as usize) & 0x1F)
+        };
+        let rgb = self.emphasis_palette()[color as usize];
+        self.frame.set_pixel(x, self.scanline as usize, rgb);
+    }
+
+    /// Palette (0-3) for the tile V points at.
+    fn fetch_bg_attribute(&self) -> u8 {
+        let coarse_x = self.v & 0x001F;
+        // Rows 30/31 only exist when V is written directly; clamp them to the
+        // last real attribute row.
+        let coarse_y = ((self.v >> 5) & 0x001F).min(29);
+        // Each attribute byte covers a 4x4 tile block, 8 bytes per attribute row,
+        // and holds four 2-bit palettes: bit 1 of coarse Y picks the bottom half
+        // (shift 4), bit 1 of coarse X the right half (shift 2).
+        let addr = 0x23C0 | (self.v & 0x0C00) | (coarse_y / 4) << 3 | (coarse_x / 4);
+        let shift = (coarse_y & 2) << 1 | (coarse_x & 2);
+        (self.internal_read(addr) >> shift) & 0x03
+    }
+
+    /// Address of the low pattern plane for the fetched tile's row at fine Y.
+    fn bg_pattern_addr(&self) -> u16 {
+        self.ctrl.bg_pattern_table() + self.bg.next_tile as u16 * 16 + (self.v >> 12)
+    }
+
+    /// Step coarse X in V, wrapping into the horizontally adjacent nametable.
+    fn increment_v_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v &= !0x001F;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Mirroring;
+    use crate::ppu::frame::SYSTEM_PALETTE;
+
+    fn tick_to(ppu: &mut Ppu, scanline: u16, cycle: u16) {
+        while ppu.scanline != scanline || ppu.cycle != cycle {
+            ppu.tick();
+        }
+    }
+
+    #[test]
+    fn test_fine_x_write_mid_scanline_shifts_rest_of_line() {
+        // Tile 1 is solid; nametable column 16 (x = 128-135) holds it on every row
+        let mut chr = vec![0u8; 8192];
+        chr[16..24].fill(0xFF);
+        let mut ppu = Ppu::new(chr, Mirroring::Horizontal);
+        for row in 0..30 {
+            ppu.vram[row * 32 + 16] = 1;
+        }
+        ppu.palette_ram[1] = 0x16;
+        ppu.mask = PpuMask::SHOW_BG | PpuMask::SHOW_BG_LEFT;
+        let solid = SYSTEM_PALETTE[0x16];
+
+        tick_to(&mut ppu, 100, 100);
+        ppu.cpu_write(0x2005, 4); // fine X = 4, coarse X unchanged
+        tick_to(&mut ppu, 101, 0);
+
+        assert_eq!(ppu.frame.pixel(127, 99), SYSTEM_PALETTE[0]);
+        assert_eq!(ppu.frame.pixel(128, 99), solid);
+        assert_eq!(ppu.frame.pixel(135, 99), solid);
+        // Pixels after the write come 4 dots further along the shifters
+        assert_eq!(ppu.frame.pixel(123, 100), SYSTEM_PALETTE[0]);
+        assert_eq!(ppu.frame.pixel(124, 100), solid);
+        assert_eq!(ppu.frame.pixel(131, 100), solid);
+        assert_eq!(ppu.frame.pixel(132, 100), SYSTEM_PALETTE[0]);
+    }
+}
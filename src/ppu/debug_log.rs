@@ -0,0 +1,120 @@
+//! PPU register access log, enabled with the `ppu-debug-log` feature.
+//!
+//! Every CPU access to a PPU register is appended to `ppu_reg.log` (or the file
+//! given to `Ppu::set_debug_log_path`), one per line:
+//!
+//! ```text
+//! CYCLE:<ticks> PPU:<scanline>/<cycle> WRITE:<addr>=<val>
+//! CYCLE:<ticks> PPU:<scanline>/<cycle> READ:<addr>=<val>
+//! ```
+//!
+//! `<ticks>` is the number of PPU ticks since power-on, `<addr>` is the register
+//! address as 4 hex digits and `<val>` the data byte as 2 hex digits. Only reads
+//! of $2002, $2004 and $2007 are logged since the other registers are write-only.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Where the log goes by default.
+pub const LOG_PATH: &str = "ppu_reg.log";
+
+// The first log opened on a path in a process truncates the file; later ones
+// (e.g. after a save state is loaded) append to it.
+static STARTED_PATHS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+pub struct RegisterLog {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+impl Default for RegisterLog {
+    fn default() -> Self {
+        RegisterLog::new(LOG_PATH)
+    }
+}
+
+impl Clone for RegisterLog {
+    /// Clones start without a file handle and open the log lazily on their first access.
+    fn clone(&self) -> Self {
+        RegisterLog::new(self.path.clone())
+    }
+}
+
+impl RegisterLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        RegisterLog { path: path.into(), writer: None }
+    }
+
+    pub fn record(&mut self, kind: &str, ticks: u64, scanline: u16, cycle: u16, addr: u16, val: u8) {
+        if self.writer.is_none() {
+            self.writer = self.open().map(BufWriter::new);
+        }
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = writeln!(
+                writer,
+                "CYCLE:{} PPU:{}/{} {}:{:04X}={:02X}",
+                ticks, scanline, cycle, kind, addr, val
+            );
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = writer.flush();
+        }
+    }
+
+    fn open(&self) -> Option<File> {
+        let truncate = {
+            let mut started = STARTED_PATHS.lock().unwrap_or_else(|e| e.into_inner());
+            !started.contains(&self.path) && {
+                started.push(self.path.clone());
+                true
+            }
+        };
+        let result = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(truncate)
+            .append(!truncate)
+            .open(&self.path);
+        match result {
+            Ok(file) => Some(file),
+            Err(e) => {
+                log::warn!("Failed to open {}: {}", self.path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::Mirroring;
+    use crate::ppu::Ppu;
+
+    #[test]
+    fn test_register_accesses_are_logged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ppu_reg.log");
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.set_debug_log_path(&path);
+        let mut frames = 0;
+        while frames < 5 {
+            if ppu.tick() {
+                frames += 1;
+                ppu.cpu_read(0x2002);
+                ppu.cpu_write(0x2001, 0x1E);
+            }
+        }
+        drop(ppu);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(log.lines().count() >= 10);
+        assert!(log.lines().all(|line| line.starts_with("CYCLE:")));
+        assert!(log.contains("WRITE:2001=1E"));
+        assert!(log.contains("READ:2002="));
+    }
+}
@@ -0,0 +1,164 @@
+use super::{OamEntry, Ppu};
+use super::frame::SYSTEM_PALETTE;
+use super::nametable_view::LogicalNametableView;
+use super::registers::PpuCtrl;
+
+pub const NAMETABLES_WIDTH: usize = 512;
+pub const NAMETABLES_HEIGHT: usize = 480;
+pub const PATTERN_TABLES_WIDTH: usize = 256;
+pub const PATTERN_TABLES_HEIGHT: usize = 128;
+pub const OAM_VIEW_SIZE: usize = 256;
+
+const BORDER: [u8; 3] = [0x80, 0x80, 0x80];
+
+/// Render all four logical nametables as a 512x480 RGB image in the current
+/// palettes, with a one-pixel border between the quadrants.
+pub fn render_nametables(ppu: &Ppu) -> Vec<u8> {
+    let view = LogicalNametableView { origin_x: 0, origin_y: 0, width: 64, height: 60 };
+    let mut out = ppu.render_logical_nametable(view);
+    for y in 0..NAMETABLES_HEIGHT {
+        let offset = (y * NAMETABLES_WIDTH + NAMETABLES_WIDTH / 2) * 3;
+        out[offset..offset + 3].copy_from_slice(&BORDER);
+    }
+    let row = NAMETABLES_HEIGHT / 2 * NAMETABLES_WIDTH * 3;
+    for pixel in out[row..row + NAMETABLES_WIDTH * 3].chunks_mut(3) {
+        pixel.copy_from_slice(&BORDER);
+    }
+    out
+}
+
+/// Render both CHR pages side by side as a 256x128 RGB image, $0000 on the left.
+pub fn render_pattern_tables(ppu: &Ppu) -> Vec<u8> {
+    let mut out = vec![0u8; PATTERN_TABLES_WIDTH * PATTERN_TABLES_HEIGHT * 3];
+    for page in 0..2u8 {
+        let image = ppu.render_chr_page(page, 0);
+        for (y, src) in image.chunks(128 * 3).enumerate() {
+            let offset = (y * PATTERN_TABLES_WIDTH + page as usize * 128) * 3;
+            out[offset..offset + 128 * 3].copy_from_slice(src);
+        }
+    }
+    out
+}
+
+/// Render all 64 sprites as an 8x8 grid of 32x32 cells, each sprite drawn at
+/// double size in its own palette and flips over the backdrop color. Returns
+/// the 256x256 RGB image and the decoded OAM slots.
+pub fn render_oam_debug(ppu: &Ppu) -> (Vec<u8>, Vec<OamEntry>) {
+    let mut out = vec![0u8; OAM_VIEW_SIZE * OAM_VIEW_SIZE * 3];
+    let sprite_height: u16 = if ppu.ctrl.contains(PpuCtrl::SPRITE_SIZE) { 16 } else { 8 };
+    let backdrop = SYSTEM_PALETTE[ppu.palette_ram[0] as usize % 64];
+    let mut entries = Vec::with_capacity(64);
+
+    for slot in 0..64usize {
+        let [y, tile, attrs, x] = [0, 1, 2, 3].map(|i| ppu.oam[slot * 4 + i]);
+        let top = y as u16 + 1;
+        entries.push(OamEntry {
+            slot: slot as u8,
+            x,
+            y,
+            tile,
+            attrs,
+            visible_on_line: ppu.scanline >= top && ppu.scanline < top + sprite_height,
+        });
+
+        let (cell_x, cell_y) = (slot % 8 * 32, slot / 8 * 32);
+        for row in 0..sprite_height as u8 {
+            let flipped_row = if attrs & 0x80 != 0 { sprite_height as u8 - 1 - row } else { row };
+            let addr = ppu.sprite_pattern_addr(tile, flipped_row, sprite_height);
+            let (plane0, plane1) = (ppu.internal_read(addr), ppu.internal_read(addr + 8));
+            for col in 0..8u8 {
+                let bit = if attrs & 0x40 != 0 { col } else { 7 - col };
+                let pixel = ((plane1 >> bit) & 1) << 1 | ((plane0 >> bit) & 1);
+                let (r, g, b) = if pixel == 0 {
+                    backdrop
+                } else {
+                    let palette = (attrs & 0x03) as usize + 4;
+                    SYSTEM_PALETTE[ppu.palette_ram[palette * 4 + pixel as usize] as usize % 64]
+                };
+                // Each sprite pixel becomes a 2x2 block
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let px = cell_x + col as usize * 2 + dx;
+                    let py = cell_y + row as usize * 2 + dy;
+                    let offset = (py * OAM_VIEW_SIZE + px) * 3;
+                    out[offset..offset + 3].copy_from_slice(&[r, g, b]);
+                }
+            }
+        }
+    }
+    (out, entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Mirroring;
+    use crate::ppu::frame::SYSTEM_PALETTE;
+
+    fn pixel(buf: &[u8], width: usize, x: usize, y: usize) -> (u8, u8, u8) {
+        let i = (y * width + x) * 3;
+        (buf[i], buf[i + 1], buf[i + 2])
+    }
+
+    #[test]
+    fn test_debug_views() {
+        // Tile 1 is solid in page 0, tile 0 is solid in page 1
+        let mut chr = vec![0u8; 8192];
+        chr[16..24].fill(0xFF);
+        chr[0x1000..0x1008].fill(0xFF);
+        let mut ppu = Ppu::new(chr, Mirroring::Vertical);
+        ppu.vram[..960].fill(1);
+        ppu.palette_ram[0] = 0x0F;
+        ppu.palette_ram[1] = 0x30;
+        let (solid, backdrop) = (SYSTEM_PALETTE[0x30], SYSTEM_PALETTE[0x0F]);
+
+        let nametables = render_nametables(&ppu);
+        assert_eq!(nametables.len(), NAMETABLES_WIDTH * NAMETABLES_HEIGHT * 3);
+        assert_eq!(pixel(&nametables, 512, 0, 0), solid);
+        assert_eq!(pixel(&nametables, 512, 300, 0), backdrop);
+        assert_eq!(pixel(&nametables, 512, 0, 250), solid); // vertical mirroring
+        assert_eq!(pixel(&nametables, 512, 256, 10), (0x80, 0x80, 0x80));
+        assert_eq!(pixel(&nametables, 512, 10, 240), (0x80, 0x80, 0x80));
+
+        let patterns = render_pattern_tables(&ppu);
+        assert_eq!(patterns.len(), PATTERN_TABLES_WIDTH * PATTERN_TABLES_HEIGHT * 3);
+        assert_eq!(pixel(&patterns, 256, 0, 0), backdrop);
+        assert_eq!(pixel(&patterns, 256, 8, 0), solid);
+        assert_eq!(pixel(&patterns, 256, 128, 0), solid);
+    }
+
+    #[test]
+    fn test_oam_debug_view() {
+        // Tile 2: left column opaque (color 1) on the top row only
+        let mut chr = vec![0u8; 8192];
+        chr[2 * 16] = 0x80;
+        let mut ppu = Ppu::new(chr, Mirroring::Vertical);
+        ppu.palette_ram[0] = 0x0F;
+        ppu.palette_ram[0x11] = 0x16; // sprite palette 0, color 1
+        ppu.palette_ram[0x19] = 0x2A; // sprite palette 2, color 1
+        ppu.oam.fill(0xFF);
+        ppu.oam[0..4].copy_from_slice(&[9, 2, 0x00, 40]); // slot 0, no flip
+        ppu.oam[36..40].copy_from_slice(&[100, 2, 0xC2, 0]); // slot 9, palette 2, both flips
+        ppu.scanline = 12;
+
+        let (image, entries) = render_oam_debug(&ppu);
+        assert_eq!(image.len(), OAM_VIEW_SIZE * OAM_VIEW_SIZE * 3);
+        let (red, green, backdrop) = (SYSTEM_PALETTE[0x16], SYSTEM_PALETTE[0x2A], SYSTEM_PALETTE[0x0F]);
+
+        // Slot 0 sits in the top-left cell at double size
+        assert_eq!(pixel(&image, 256, 0, 0), red);
+        assert_eq!(pixel(&image, 256, 1, 1), red);
+        assert_eq!(pixel(&image, 256, 2, 0), backdrop);
+        assert_eq!(pixel(&image, 256, 0, 2), backdrop);
+        // Slot 9 is in row 1, column 1; flipped, its pixel lands bottom-right
+        assert_eq!(pixel(&image, 256, 32 + 15, 32 + 15), green);
+        assert_eq!(pixel(&image, 256, 32, 32), backdrop);
+
+        assert_eq!(entries.len(), 64);
+        assert_eq!(
+            entries[0],
+            OamEntry { slot: 0, x: 40, y: 9, tile: 2, attrs: 0, visible_on_line: true }
+        );
+        assert!(!entries[9].visible_on_line);
+        assert_eq!(entries[9].attrs, 0xC2);
+    }
+}
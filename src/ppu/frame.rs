@@ -1,9 +1,15 @@
 pub const WIDTH: usize = 256;
 pub const HEIGHT: usize = 240;
 
+const FRAME_BYTES: usize = WIDTH * HEIGHT * 3;
+
+/// Double-buffered RGB888 frame. The PPU draws into the back buffer while the
+/// front buffer holds the last completed frame; `swap` flips them at vblank so
+/// readers never see a half-drawn picture.
 #[derive(Clone)]
 pub struct Frame {
-    pub data: [u8; WIDTH * HEIGHT * 3], // RGB888
+    frames: [Box<[u8; FRAME_BYTES]>; 2],
+    front: usize,
 }
 
 impl Default for Frame {
@@ -15,18 +21,164 @@ impl Default for Frame {
 impl Frame {
     pub fn new() -> Self {
         Frame {
-            data: [0; WIDTH * HEIGHT * 3],
+            frames: [Self::blank_buffer(), Self::blank_buffer()],
+            front: 0,
         }
     }
 
+    // Allocate directly on the heap rather than building the array on the stack
+    fn blank_buffer() -> Box<[u8; FRAME_BYTES]> {
+        vec![0; FRAME_BYTES].into_boxed_slice().try_into().unwrap()
+    }
+
+    pub fn width(&self) -> usize {
+        WIDTH
+    }
+
+    pub fn height(&self) -> usize {
+        HEIGHT
+    }
+
+    /// Make the back buffer the displayed frame and start drawing into the old front buffer.
+    pub fn swap(&mut self) {
+        self.front ^= 1;
+    }
+
+    /// The last completed frame, row-major RGB888 (3 bytes per pixel).
+    pub fn front_slice(&self) -> &[u8] {
+        &self.frames[self.front][..]
+    }
+
+    /// Same as `front_slice`.
+    pub fn as_rgb_slice(&self) -> &[u8] {
+        self.front_slice()
+    }
+
+    /// Pixel from the last completed frame.
+    pub fn front_pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        Self::read(&self.frames[self.front], x, y)
+    }
+
+    /// Pixel from the frame currently being drawn.
+    pub fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        Self::read(&self.frames[self.front ^ 1], x, y)
+    }
+
     pub fn set_pixel(&mut self, x: usize, y: usize, color: (u8, u8, u8)) {
         let idx = (y * WIDTH + x) * 3;
-        if idx + 2 < self.data.len() {
-            self.data[idx] = color.0;
-            self.data[idx + 1] = color.1;
-            self.data[idx + 2] = color.2;
+        let data = &mut self.frames[self.front ^ 1];
+        if idx + 2 < data.len() {
+            data[idx] = color.0;
+            data[idx + 1] = color.1;
+            data[idx + 2] = color.2;
         }
     }
+
+    /// Write the last completed frame to `path` as a 256x240 RGB PNG.
+    #[cfg(feature = "png")]
+    pub fn save_png(&self, path: &std::path::Path) -> std::io::Result<()> {
+        write_png(path, self.front_slice())
+    }
+
+    /// A copy of the last completed frame.
+    pub fn snapshot(&self) -> FrameSnapshot {
+        FrameSnapshot(self.frames[self.front].clone())
+    }
+
+    fn read(data: &[u8; FRAME_BYTES], x: usize, y: usize) -> (u8, u8, u8) {
+        let idx = (y * WIDTH + x) * 3;
+        (data[idx], data[idx + 1], data[idx + 2])
+    }
+}
+
+#[cfg(feature = "png")]
+fn write_png(path: &std::path::Path, rgb: &[u8]) -> std::io::Result<()> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Channels may differ by this much before `FrameSnapshot::diff` counts a
+/// pixel as changed.
+pub const SNAPSHOT_TOLERANCE: u8 = 2;
+
+/// A frame copied out of the PPU, for comparing against golden images.
+#[derive(Clone, PartialEq)]
+pub struct FrameSnapshot(Box<[u8; FRAME_BYTES]>);
+
+impl FrameSnapshot {
+    /// Row-major RGB888, like `Frame::front_slice`.
+    pub fn as_rgb_slice(&self) -> &[u8] {
+        &self.0[..]
+    }
+
+    /// Fraction of pixels, 0.0-1.0, with a channel more than
+    /// `SNAPSHOT_TOLERANCE` away from the same pixel in `other`.
+    pub fn diff(&self, other: &FrameSnapshot) -> f64 {
+        let changed = self
+            .0
+            .chunks_exact(3)
+            .zip(other.0.chunks_exact(3))
+            .filter(|(a, b)| a.iter().zip(b.iter()).any(|(&a, &b)| a.abs_diff(b) > SNAPSHOT_TOLERANCE))
+            .count();
+        changed as f64 / (WIDTH * HEIGHT) as f64
+    }
+
+    /// Write the snapshot to `path` as a 256x240 RGB PNG.
+    #[cfg(feature = "png")]
+    pub fn save_png(&self, path: &std::path::Path) -> std::io::Result<()> {
+        write_png(path, self.as_rgb_slice())
+    }
+
+    /// Read a snapshot saved by `save_png`. Anything but an 8-bit 256x240
+    /// RGB PNG is `InvalidData`.
+    #[cfg(feature = "png")]
+    pub fn load_png(path: &std::path::Path) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+
+        let decoder = png::Decoder::new(std::io::BufReader::new(std::fs::File::open(path)?));
+        let mut reader = decoder.read_info()?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data)?;
+        if (info.width, info.height) != (WIDTH as u32, HEIGHT as u32)
+            || info.color_type != png::ColorType::Rgb
+            || info.bit_depth != png::BitDepth::Eight
+        {
+            return Err(Error::new(ErrorKind::InvalidData, "expected an 8-bit 256x240 RGB PNG"));
+        }
+        data.truncate(info.buffer_size());
+        let buffer = data.into_boxed_slice().try_into().map_err(|_| Error::new(ErrorKind::InvalidData, "truncated image"))?;
+        Ok(FrameSnapshot(buffer))
+    }
+}
+
+// Both buffers are saved so a restored state shows the same picture it was saved with
+#[cfg(feature = "save_states")]
+impl serde::Serialize for Frame {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.front, &self.frames[0][..], &self.frames[1][..]).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "save_states")]
+impl<'de> serde::Deserialize<'de> for Frame {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let (front, a, b): (usize, Vec<u8>, Vec<u8>) = serde::Deserialize::deserialize(deserializer)?;
+        let to_buffer = |data: Vec<u8>| -> Result<Box<[u8; FRAME_BYTES]>, D::Error> {
+            let len = data.len();
+            data.into_boxed_slice()
+                .try_into()
+                .map_err(|_| D::Error::invalid_length(len, &"a 256x240 RGB frame"))
+        };
+        Ok(Frame { frames: [to_buffer(a)?, to_buffer(b)?], front: front & 1 })
+    }
 }
 
 /// NES system palette — 64 colors mapped to RGB values.
@@ -97,3 +249,114 @@ pub static SYSTEM_PALETTE: [(u8, u8, u8); 64] = [
     (0, 0, 0),       // 0x3E
     (0, 0, 0),       // 0x3F
 ];
+
+/// `SYSTEM_PALETTE` under each PPUMASK emphasis setting, indexed by mask bits
+/// 5-7 (red, green, blue). Setting any emphasis bit darkens every channel
+/// other than the emphasized ones to about 75%.
+pub static EMPHASIS_PALETTES: [[(u8, u8, u8); 64]; 8] = emphasis_palettes();
+
+const fn emphasis_palettes() -> [[(u8, u8, u8); 64]; 8] {
+    const fn attenuate(channel: u8, dim: bool) -> u8 {
+        if dim { (channel as u16 * 3 / 4) as u8 } else { channel }
+    }
+
+    let mut tables = [SYSTEM_PALETTE; 8];
+    let mut emphasis = 1;
+    while emphasis < 8 {
+        let mut i = 0;
+        while i < 64 {
+            let (r, g, b) = SYSTEM_PALETTE[i];
+            tables[emphasis][i] = (
+                attenuate(r, emphasis & !0b001 != 0),
+                attenuate(g, emphasis & !0b010 != 0),
+                attenuate(b, emphasis & !0b100 != 0),
+            );
+            i += 1;
+        }
+        emphasis += 1;
+    }
+    tables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_roundtrip() {
+        let mut frame = Frame::new();
+        frame.set_pixel(10, 20, (1, 2, 3));
+        assert_eq!(frame.pixel(10, 20), (1, 2, 3));
+        assert_eq!(frame.pixel(11, 20), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_rgb_slice_layout() {
+        let mut frame = Frame::new();
+        frame.set_pixel(0, 0, (0x11, 0x22, 0x33));
+        frame.set_pixel(255, 239, (0x44, 0x55, 0x66));
+        frame.set_pixel(1, 1, (0x77, 0x88, 0x99));
+        frame.swap();
+
+        let data = frame.as_rgb_slice();
+        assert_eq!(data.len(), frame.width() * frame.height() * 3);
+        assert_eq!(&data[0..3], &[0x11, 0x22, 0x33]);
+        assert_eq!(&data[data.len() - 3..], &[0x44, 0x55, 0x66]);
+        let idx = (frame.width() + 1) * 3;
+        assert_eq!(&data[idx..idx + 3], &[0x77, 0x88, 0x99]);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_save_png_roundtrip() {
+        let mut frame = Frame::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                frame.set_pixel(x, y, (x as u8, y as u8, (x ^ y) as u8));
+            }
+        }
+        frame.swap();
+
+        let file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+        frame.save_png(file.path()).unwrap();
+
+        let image = image::open(file.path()).unwrap().into_rgb8();
+        assert_eq!(image.dimensions(), (WIDTH as u32, HEIGHT as u32));
+        assert_eq!(image.as_raw().as_slice(), frame.front_slice());
+        assert_eq!(image.get_pixel(200, 100).0, [200, 100, 200 ^ 100]);
+    }
+
+    #[test]
+    fn test_snapshot_diff_ignores_small_differences() {
+        let mut frame = Frame::new();
+        frame.swap();
+        let before = frame.snapshot();
+        frame.swap();
+        frame.set_pixel(0, 0, (SNAPSHOT_TOLERANCE, 0, 0));
+        frame.set_pixel(1, 0, (0, 0, SNAPSHOT_TOLERANCE + 1));
+        frame.set_pixel(2, 0, (0xFF, 0xFF, 0xFF));
+        frame.swap();
+        assert_eq!(frame.snapshot().diff(&before), 2.0 / (WIDTH * HEIGHT) as f64);
+        assert_eq!(before.diff(&before), 0.0);
+    }
+
+    #[test]
+    fn test_front_buffer_holds_old_frame_until_swap() {
+        let mut frame = Frame::new();
+        frame.set_pixel(5, 5, (1, 1, 1));
+        frame.swap();
+
+        // Partially draw the next frame
+        frame.set_pixel(5, 5, (2, 2, 2));
+        frame.set_pixel(6, 5, (3, 3, 3));
+        assert_eq!(frame.front_pixel(5, 5), (1, 1, 1));
+        assert_eq!(frame.front_pixel(6, 5), (0, 0, 0));
+        let idx = (5 * WIDTH + 5) * 3;
+        assert_eq!(&frame.front_slice()[idx..idx + 3], &[1, 1, 1]);
+
+        frame.swap();
+        assert_eq!(frame.front_pixel(5, 5), (2, 2, 2));
+        assert_eq!(frame.front_pixel(6, 5), (3, 3, 3));
+        assert_eq!(&frame.front_slice()[idx..idx + 3], &[2, 2, 2]);
+    }
+}
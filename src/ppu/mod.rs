@@ -1,19 +1,60 @@
 pub mod registers;
 pub mod frame;
 pub mod render;
+pub mod background;
+pub mod nametable_view;
+pub mod debug_view;
+pub mod scroll_test;
+#[cfg(feature = "ppu-debug-log")]
+pub mod debug_log;
 
 use registers::{PpuCtrl, PpuMask, PpuStatus};
 use frame::Frame;
 use crate::cartridge::Mirroring;
+use crate::nes::Region;
+use crate::cartridge::mapper::DEFAULT_CHR_BANKS;
+
+/// Loopy V/T bits copied at cycle 257: coarse X (bits 0-4) and the horizontal
+/// nametable select (bit 10).
+const V_HORIZONTAL_BITS: u16 = 0x041F;
+/// Loopy V/T bits copied on pre-render cycles 280-304: coarse Y (bits 5-9),
+/// the vertical nametable select (bit 11) and fine Y (bits 12-14).
+const V_VERTICAL_BITS: u16 = 0x7BE0;
+
+/// Number of pixels hidden at each edge of the picture, as a CRT's overscan would.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
+pub struct OverscanConfig {
+    pub top: u8,
+    pub bottom: u8,
+    pub left: u8,
+    pub right: u8,
+}
+
+/// Outcome of `Ppu::tick_batch`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PpuBatchResult {
+    pub frames_completed: u32,
+    pub nmi_fired: bool,
+}
 
 #[derive(Clone)]
+#[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ppu {
-    // CHR data (from cartridge, static for Mapper 0)
+    // CHR data (from cartridge), banked into $0000-$1FFF in 1KB slots by the mapper
     pub chr_rom: Vec<u8>,
+    pub chr_banks: [usize; 8],
+    // Pattern table writes through $2007 only land when the cartridge has CHR RAM
+    pub chr_is_ram: bool,
 
     // VRAM
     pub palette_ram: [u8; 32],
+    #[cfg_attr(feature = "save_states", serde(with = "serde_big_array::BigArray"))]
     pub vram: [u8; 2048],
+    /// The cartridge's extra 2KB of nametable RAM for nametables 2 and 3,
+    /// present (and only used) when it wires up four-screen mirroring.
+    pub extra_vram: Vec<u8>,
+    #[cfg_attr(feature = "save_states", serde(with = "serde_big_array::BigArray"))]
     pub oam: [u8; 256],
 
     // Registers
@@ -28,12 +69,16 @@ pub struct Ppu {
     pub fine_x: u8,  // fine X scroll
     pub w: bool,     // write toggle
 
-    // Scroll values (simplified for scanline renderer)
-    pub scroll_x: u8,
-    pub scroll_y: u8,
-
     // Latches
     pub read_buffer: u8,
+    pub bus_latch: u8, // last value driven on the CPU-PPU data bus, read back as open bus
+
+    // Background fetch pipeline
+    pub bg: background::PpuBackground,
+
+    // Background color index (0-3) per pixel of the current scanline, for sprite priority
+    #[cfg_attr(feature = "save_states", serde(with = "serde_big_array::BigArray"))]
+    pub bg_pixel_row: [u8; 256],
 
     // Rendering state
     pub scanline: u16,
@@ -42,20 +87,48 @@ pub struct Ppu {
 
     // NMI
     pub nmi_pending: bool,
+    // A $2002 read raced the start of vblank, so this frame's flag and NMI are dropped
+    vblank_suppressed: bool,
 
     // Output
     pub frame: Frame,
+    pub overscan: OverscanConfig,
 
     // Mirroring
     pub mirroring: Mirroring,
+
+    // Frame timing
+    pub region: Region,
+
+    // Register access log
+    #[cfg(feature = "ppu-debug-log")]
+    pub debug_cycle_counter: u64,
+    #[cfg(feature = "ppu-debug-log")]
+    #[cfg_attr(feature = "save_states", serde(skip))]
+    pub(crate) debug_log: debug_log::RegisterLog,
+}
+
+/// One decoded OAM slot, as listed by the OAM viewer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OamEntry {
+    pub slot: u8,
+    pub x: u8,
+    pub y: u8,
+    pub tile: u8,
+    pub attrs: u8,
+    /// The sprite covers the scanline the PPU is currently on.
+    pub visible_on_line: bool,
 }
 
 impl Ppu {
     pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
         Ppu {
             chr_rom,
+            chr_banks: DEFAULT_CHR_BANKS,
+            chr_is_ram: false,
             palette_ram: [0; 32],
             vram: [0; 2048],
+            extra_vram: if mirroring == Mirroring::FourScreen { vec![0; 2048] } else { Vec::new() },
             oam: [0; 256],
             ctrl: PpuCtrl::empty(),
             mask: PpuMask::empty(),
@@ -65,22 +138,80 @@ impl Ppu {
             t: 0,
             fine_x: 0,
             w: false,
-            scroll_x: 0,
-            scroll_y: 0,
             read_buffer: 0,
+            bus_latch: 0,
+            bg: background::PpuBackground::default(),
+            bg_pixel_row: [0; 256],
             scanline: 0,
             cycle: 0,
             frame_count: 0,
             nmi_pending: false,
+            vblank_suppressed: false,
             frame: Frame::new(),
+            overscan: OverscanConfig::default(),
             mirroring,
+            region: Region::Ntsc,
+            #[cfg(feature = "ppu-debug-log")]
+            debug_cycle_counter: 0,
+            #[cfg(feature = "ppu-debug-log")]
+            debug_log: debug_log::RegisterLog::default(),
         }
     }
 
+    pub fn set_overscan(&mut self, cfg: OverscanConfig) {
+        self.overscan = cfg;
+    }
+
+    /// A copy of the last completed frame, for visual regression tests.
+    pub fn snapshot(&self) -> frame::FrameSnapshot {
+        self.frame.snapshot()
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.scanline %= region.scanlines();
+    }
+
+    /// Write the register access log to `path` instead of `debug_log::LOG_PATH`.
+    #[cfg(feature = "ppu-debug-log")]
+    pub fn set_debug_log_path(&mut self, path: impl Into<std::path::PathBuf>) {
+        self.debug_log = debug_log::RegisterLog::new(path);
+    }
+
+    /// The last scanline of the frame, which prefetches for the next one.
+    fn pre_render_scanline(&self) -> u16 {
+        self.region.scanlines() - 1
+    }
+
     fn rendering_enabled(&self) -> bool {
         self.mask.contains(PpuMask::SHOW_BG) || self.mask.contains(PpuMask::SHOW_SPR)
     }
 
+    /// True while the PPU is fetching for a visible or pre-render scanline and owns the VRAM address bus.
+    fn rendering_active(&self) -> bool {
+        self.rendering_enabled() && (self.scanline < 240 || self.scanline == self.pre_render_scanline())
+    }
+
+    /// True on the dot where PPU address line A12 rises during a rendering
+    /// scanline, which MMC3 counts: the sprite fetches at 260 when sprites use
+    /// $1000 (or 8x16), or the BG prefetch at 324 when only the BG does.
+    pub fn a12_rising_edge(&self) -> bool {
+        if !self.rendering_active() {
+            return false;
+        }
+        if self.ctrl.sprite_pattern_table() == 0x1000 || self.ctrl.contains(PpuCtrl::SPRITE_SIZE) {
+            self.cycle == 260
+        } else {
+            self.ctrl.bg_pattern_table() == 0x1000 && self.cycle == 324
+        }
+    }
+
+    /// The NTSC PPU drops the last dot of the pre-render line on odd frames
+    /// while rendering, making those frames one dot short.
+    fn skips_last_dot(&self) -> bool {
+        self.region == Region::Ntsc && !self.frame_count.is_multiple_of(2) && self.rendering_enabled()
+    }
+
     /// Increment the fine Y scroll in V, wrapping through coarse Y and nametable.
     fn increment_v_y(&mut self) {
         if (self.v & 0x7000) != 0x7000 {
@@ -102,31 +233,35 @@ impl Ppu {
 
     /// Tick the PPU by one cycle. Returns true when a frame is complete.
     pub fn tick(&mut self) -> bool {
-        let mut frame_complete = false;
-        let visible = self.scanline < 240;
-        let pre_render = self.scanline == 261;
+        debug_assert!(self.cycle <= 340, "PPU cycle {} out of range", self.cycle);
+        debug_assert!(self.scanline <= self.pre_render_scanline(), "PPU scanline {} out of range", self.scanline);
 
-        // Render visible scanline at cycle 0 (reads V but doesn't modify it)
-        if visible && self.cycle == 0 {
-            self.render_scanline(self.scanline);
+        #[cfg(feature = "ppu-debug-log")]
+        {
+            self.debug_cycle_counter += 1;
         }
 
-        // V register updates at correct cycle timing (visible + pre-render)
+        let mut frame_complete = false;
+        let visible = self.scanline < 240;
+        let pre_render = self.scanline == self.pre_render_scanline();
+
+        // Background fetches and V updates (visible + pre-render)
         if (visible || pre_render) && self.rendering_enabled() {
-            // Cycle 256: increment fine Y
-            if self.cycle == 256 {
-                self.increment_v_y();
-            }
-            // Cycle 257: copy horizontal bits from T to V
-            if self.cycle == 257 {
-                self.v = (self.v & !0x041F) | (self.t & 0x041F);
-            }
+            self.clock_background_fetch();
             // Pre-render line cycles 280-304: copy vertical bits from T to V
             if pre_render && self.cycle >= 280 && self.cycle <= 304 {
-                self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+                self.v = (self.v & !V_VERTICAL_BITS) | (self.t & V_VERTICAL_BITS);
             }
         }
 
+        // One background pixel per dot, then sprites once the row is complete
+        if visible && (1..=256).contains(&self.cycle) {
+            self.clock_background_pixel();
+        }
+        if visible && self.cycle == 257 {
+            self.finish_scanline(self.scanline);
+        }
+
         // Pre-render line: clear flags
         if pre_render && self.cycle == 1 {
             self.status.remove(PpuStatus::VBLANK);
@@ -135,19 +270,22 @@ impl Ppu {
         }
 
         // Vblank start
-        if self.scanline == 241 && self.cycle == 1 {
-            self.status.insert(PpuStatus::VBLANK);
-            if self.ctrl.contains(PpuCtrl::NMI_ENABLE) {
-                self.nmi_pending = true;
+        if self.scanline == self.region.vblank_scanline() && self.cycle == 1 {
+            self.frame.swap();
+            if !std::mem::take(&mut self.vblank_suppressed) {
+                self.status.insert(PpuStatus::VBLANK);
+                if self.ctrl.contains(PpuCtrl::NMI_ENABLE) {
+                    self.nmi_pending = true;
+                }
             }
             frame_complete = true;
         }
 
         self.cycle += 1;
-        if self.cycle > 340 {
+        if self.cycle > 340 || (self.cycle == 340 && pre_render && self.skips_last_dot()) {
             self.cycle = 0;
             self.scanline += 1;
-            if self.scanline > 261 {
+            if self.scanline > self.pre_render_scanline() {
                 self.scanline = 0;
                 self.frame_count += 1;
             }
@@ -156,12 +294,103 @@ impl Ppu {
         frame_complete
     }
 
+    /// Advance the PPU by `n` cycles. Batches that start after the vblank flag is
+    /// set (241/1 on NTSC) and end before the pre-render line touch nothing but the
+    /// scanline/cycle counters, so they're applied arithmetically; anything else
+    /// falls back to calling `tick` `n` times.
+    pub fn tick_batch(&mut self, n: u32) -> PpuBatchResult {
+        const DOTS: u32 = 341;
+        let position = self.scanline as u32 * DOTS + self.cycle as u32;
+        let end = position + n;
+        let vblank = self.region.vblank_scanline() as u32 * DOTS + 1;
+        if position > vblank && end <= self.pre_render_scanline() as u32 * DOTS {
+            #[cfg(feature = "ppu-debug-log")]
+            {
+                self.debug_cycle_counter += n as u64;
+            }
+            self.scanline = (end / DOTS) as u16;
+            self.cycle = (end % DOTS) as u16;
+            return PpuBatchResult::default();
+        }
+
+        let mut result = PpuBatchResult::default();
+        for _ in 0..n {
+            if self.tick() {
+                result.frames_completed += 1;
+                // A $2002 read racing vblank start leaves the flag clear and
+                // raises no NMI
+                result.nmi_fired |= self.status.contains(PpuStatus::VBLANK) && self.ctrl.contains(PpuCtrl::NMI_ENABLE);
+            }
+        }
+        result
+    }
+
+    /// Returns true if all internal state is within the bounds real hardware can hold.
+    pub fn invariant_check(&self) -> bool {
+        self.invariant_violation().is_none()
+    }
+
+    /// Panic with a description of the first out-of-range piece of state, if any.
+    pub fn assert_invariants(&self) {
+        if let Some(violation) = self.invariant_violation() {
+            panic!("PPU invariant violated: {}", violation);
+        }
+    }
+
+    fn invariant_violation(&self) -> Option<String> {
+        if self.v > 0x7FFF {
+            return Some(format!("v = ${:04X} exceeds 15 bits", self.v));
+        }
+        if self.t > 0x7FFF {
+            return Some(format!("t = ${:04X} exceeds 15 bits", self.t));
+        }
+        if self.fine_x > 7 {
+            return Some(format!("fine_x = {} exceeds 7", self.fine_x));
+        }
+        if let Some(i) = self.palette_ram.iter().position(|&c| c > 0x3F) {
+            return Some(format!("palette_ram[{}] = ${:02X} is not a NES color", i, self.palette_ram[i]));
+        }
+        if self.scanline > self.pre_render_scanline() {
+            return Some(format!("scanline = {} exceeds {}", self.scanline, self.pre_render_scanline()));
+        }
+        if self.cycle > 340 {
+            return Some(format!("cycle = {} exceeds 340", self.cycle));
+        }
+        None
+    }
+
+    /// What a CPU read of `addr` ($2000-$2007) would return, without clearing
+    /// vblank, resetting the write latch or advancing V.
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        match addr {
+            // On the dot vblank starts, the read sees the flag being set
+            0x2002 if self.scanline == self.region.vblank_scanline() && self.cycle == 1 => {
+                self.status.bits() | PpuStatus::VBLANK.bits() | (self.bus_latch & 0x1F)
+            }
+            0x2002 => self.status.bits() | (self.bus_latch & 0x1F),
+            0x2004 => self.oam[self.oam_addr as usize],
+            0x2007 if self.v & 0x3FFF >= 0x3F00 => self.palette_read(self.v),
+            0x2007 => self.read_buffer,
+            _ => self.bus_latch,
+        }
+    }
+
     /// CPU read from PPU register ($2000-$2007)
     pub fn cpu_read(&mut self, addr: u16) -> u8 {
-        match addr {
+        let val = match addr {
             0x2002 => {
                 // PPUSTATUS
-                let val = self.status.bits() | (self.read_buffer & 0x1F);
+                let val = self.peek_register(addr);
+                // A read one dot before vblank starts sees the flag clear and stops it
+                // being set; one on that dot or the next sees it set. Either way the
+                // frame's NMI is lost.
+                if self.scanline == self.region.vblank_scanline() {
+                    match self.cycle {
+                        0 | 1 => self.vblank_suppressed = true,
+                        2 => self.nmi_pending = false,
+                        _ => {}
+                    }
+                }
                 self.status.remove(PpuStatus::VBLANK);
                 self.w = false;
                 val
@@ -171,10 +400,14 @@ impl Ppu {
                 self.oam[self.oam_addr as usize]
             }
             0x2007 => {
-                // PPUDATA
-                let addr = self.v;
-                self.v = self.v.wrapping_add(self.ctrl.vram_increment());
-                self.v &= 0x3FFF;
+                // PPUDATA. V's top bit is fine Y and isn't on the address bus.
+                let addr = self.v & 0x3FFF;
+                // During rendering the PPU drives V for its own tile fetches, so a CPU read
+                // returns the buffer without advancing V.
+                if !self.rendering_active() {
+                    self.v = self.v.wrapping_add(self.ctrl.vram_increment());
+                    self.v &= 0x3FFF;
+                }
 
                 if addr >= 0x3F00 {
                     // Palette reads are not buffered
@@ -188,12 +421,23 @@ impl Ppu {
                     result
                 }
             }
-            _ => 0, // write-only registers return 0
-        }
+            _ => return self.bus_latch, // write-only registers read back open bus
+        };
+        // $2002 only drives its top three bits; the rest stay open bus
+        self.bus_latch = if addr == 0x2002 { (val & 0xE0) | (self.bus_latch & 0x1F) } else { val };
+
+        #[cfg(feature = "ppu-debug-log")]
+        self.debug_log.record("READ", self.debug_cycle_counter, self.scanline, self.cycle, addr, val);
+
+        val
     }
 
     /// CPU write to PPU register ($2000-$2007)
     pub fn cpu_write(&mut self, addr: u16, val: u8) {
+        #[cfg(feature = "ppu-debug-log")]
+        self.debug_log.record("WRITE", self.debug_cycle_counter, self.scanline, self.cycle, addr, val);
+
+        self.bus_latch = val;
         match addr {
             0x2000 => {
                 // PPUCTRL
@@ -223,12 +467,10 @@ impl Ppu {
                 // PPUSCROLL
                 if !self.w {
                     // First write: X scroll
-                    self.scroll_x = val;
                     self.fine_x = val & 0x07;
                     self.t = (self.t & 0xFFE0) | ((val as u16) >> 3);
                 } else {
                     // Second write: Y scroll
-                    self.scroll_y = val;
                     self.t = (self.t & 0x8C1F)
                         | (((val as u16) & 0x07) << 12)
                         | (((val as u16) >> 3) << 5);
@@ -262,18 +504,14 @@ impl Ppu {
     pub fn internal_read(&self, addr: u16) -> u8 {
         let addr = addr & 0x3FFF;
         match addr {
-            0x0000..=0x1FFF => {
-                // Pattern tables (CHR ROM/RAM)
-                if (addr as usize) < self.chr_rom.len() {
-                    self.chr_rom[addr as usize]
-                } else {
-                    0
-                }
-            }
+            // Pattern tables (CHR ROM/RAM)
+            0x0000..=0x1FFF if self.chr_index(addr) < self.chr_rom.len() => self.chr_rom[self.chr_index(addr)],
             0x2000..=0x3EFF => {
                 // Nametables
-                let mirrored = self.mirror_vram_addr(addr);
-                self.vram[mirrored]
+                match self.mirror_vram_addr(addr) {
+                    index @ 0..=0x7FF => self.vram[index],
+                    index => self.extra_vram.get(index - 0x800).copied().unwrap_or(0),
+                }
             }
             0x3F00..=0x3FFF => {
                 self.palette_read(addr)
@@ -286,16 +524,19 @@ impl Ppu {
     fn internal_write(&mut self, addr: u16, val: u8) {
         let addr = addr & 0x3FFF;
         match addr {
-            0x0000..=0x1FFF => {
-                // CHR RAM write (if using CHR RAM)
-                if (addr as usize) < self.chr_rom.len() {
-                    self.chr_rom[addr as usize] = val;
-                }
-            }
-            0x2000..=0x3EFF => {
-                let mirrored = self.mirror_vram_addr(addr);
-                self.vram[mirrored] = val;
+            // CHR RAM write; CHR ROM ignores it
+            0x0000..=0x1FFF if self.chr_is_ram && self.chr_index(addr) < self.chr_rom.len() => {
+                let index = self.chr_index(addr);
+                self.chr_rom[index] = val;
             }
+            0x2000..=0x3EFF => match self.mirror_vram_addr(addr) {
+                index @ 0..=0x7FF => self.vram[index] = val,
+                index => {
+                    if let Some(byte) = self.extra_vram.get_mut(index - 0x800) {
+                        *byte = val;
+                    }
+                }
+            },
             0x3F00..=0x3FFF => {
                 self.palette_write(addr, val);
             }
@@ -303,14 +544,30 @@ impl Ppu {
         }
     }
 
+    /// Offset into `chr_rom` of pattern table address `addr`, through the mapper's CHR banks.
+    fn chr_index(&self, addr: u16) -> usize {
+        self.chr_banks[addr as usize / 0x400] + (addr as usize & 0x3FF)
+    }
+
     fn palette_read(&self, addr: u16) -> u8 {
-        let index = self.palette_mirror(addr);
-        self.palette_ram[index]
+        self.palette_color(self.palette_mirror(addr))
+    }
+
+    /// Palette RAM entry as the PPU outputs it. Greyscale mode keeps only the
+    /// luminance bits, which selects the grey column of the NES palette.
+    fn palette_color(&self, index: usize) -> u8 {
+        // Writes are masked, but a loaded save state can hold any byte
+        let color = self.palette_ram[index] & 0x3F;
+        if self.mask.contains(PpuMask::GREYSCALE) {
+            color & 0x30
+        } else {
+            color
+        }
     }
 
     fn palette_write(&mut self, addr: u16, val: u8) {
         let index = self.palette_mirror(addr);
-        self.palette_ram[index] = val;
+        self.palette_ram[index] = val & 0x3F; // palette entries are 6 bits wide
     }
 
     fn palette_mirror(&self, addr: u16) -> usize {
@@ -322,6 +579,9 @@ impl Ppu {
         index
     }
 
+    /// Offset of nametable address `addr` in the PPU's 2KB of VRAM, or past
+    /// it (0x800-0xFFF) for nametables 2 and 3 in the cartridge's
+    /// `extra_vram` under four-screen mirroring.
     fn mirror_vram_addr(&self, addr: u16) -> usize {
         let addr = (addr - 0x2000) as usize & 0x0FFF; // remove mirroring above $2FFF
         let nametable = addr / 0x400;
@@ -337,8 +597,390 @@ impl Ppu {
                 1 | 3 => 1,
                 _ => 0,
             },
+            Mirroring::SingleScreenA => 0,
+            Mirroring::SingleScreenB => 1,
             Mirroring::FourScreen => nametable,
         };
         mirrored_nt * 0x400 + offset
     }
 }
+
+#[cfg(feature = "ppu-debug-log")]
+impl Drop for Ppu {
+    fn drop(&mut self) {
+        self.debug_log.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick_to(ppu: &mut Ppu, scanline: u16, cycle: u16) {
+        while ppu.scanline != scanline || ppu.cycle != cycle {
+            ppu.tick();
+        }
+    }
+
+    #[test]
+    fn test_mid_frame_scroll_write_applies_to_next_scanline() {
+        // Tile 1 is solid; nametable column 1 holds it on every row
+        let mut chr = vec![0u8; 8192];
+        for row in 0..8 {
+            chr[16 + row] = 0xFF;
+        }
+        let mut ppu = Ppu::new(chr, Mirroring::Horizontal);
+        for row in 0..30 {
+            ppu.vram[row * 32 + 1] = 1;
+        }
+        ppu.palette_ram[1] = 0x16;
+        ppu.mask = PpuMask::SHOW_BG | PpuMask::SHOW_BG_LEFT;
+        let solid = frame::SYSTEM_PALETTE[0x16];
+
+        ppu.cpu_write(0x2005, 0);
+        ppu.cpu_write(0x2005, 0);
+        tick_to(&mut ppu, 100, 256);
+        assert_eq!(ppu.frame.pixel(8, 100), solid);
+        assert_ne!(ppu.frame.pixel(0, 100), solid);
+
+        ppu.cpu_write(0x2005, 8);
+        ppu.cpu_write(0x2005, 0);
+        tick_to(&mut ppu, 101, 257);
+        assert_eq!(ppu.frame.pixel(0, 101), solid);
+        assert_ne!(ppu.frame.pixel(8, 101), solid);
+        // The line already rendered keeps the old scroll
+        assert_eq!(ppu.frame.pixel(8, 100), solid);
+    }
+
+    #[test]
+    fn test_ppuscroll_writes_load_t_and_fine_x() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.t = 0x0C00; // nametable select bits survive both writes
+        ppu.cpu_write(0x2005, 0x7D); // coarse X 15, fine X 5
+        assert_eq!((ppu.t, ppu.fine_x, ppu.w), (0x0C0F, 5, true));
+        ppu.cpu_write(0x2005, 0x5E); // coarse Y 11, fine Y 6
+        assert_eq!((ppu.t, ppu.fine_x, ppu.w), (0x6D6F, 5, false));
+    }
+
+    #[test]
+    fn test_status_read_racing_vblank_start() {
+        // (dot of the read, VBLANK bit it returns, NMI that frame)
+        let cases = [(0, false, false), (1, true, false), (2, true, false), (3, true, true)];
+        for (cycle, vblank_read, nmi) in cases {
+            let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+            ppu.ctrl = PpuCtrl::NMI_ENABLE;
+            tick_to(&mut ppu, 241, cycle);
+            let status = ppu.cpu_read(0x2002);
+            tick_to(&mut ppu, 241, 10);
+            assert_eq!(status & 0x80 != 0, vblank_read, "read at dot {}", cycle);
+            assert_eq!(ppu.nmi_pending, nmi, "read at dot {}", cycle);
+            // Cleared by the read, or never set
+            assert!(!ppu.status.contains(PpuStatus::VBLANK), "read at dot {}", cycle);
+        }
+    }
+
+    #[test]
+    fn test_tick_batch_reports_no_suppressed_nmi() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.ctrl = PpuCtrl::NMI_ENABLE;
+        tick_to(&mut ppu, 241, 0);
+        ppu.cpu_read(0x2002);
+        let result = ppu.tick_batch(10);
+        assert_eq!(result, PpuBatchResult { frames_completed: 1, nmi_fired: false });
+        assert!(!ppu.nmi_pending);
+    }
+
+    #[test]
+    fn test_odd_frames_skip_a_dot_when_rendering() {
+        let frame_dots = |ppu: &mut Ppu| {
+            let mut dots = 0;
+            loop {
+                ppu.tick();
+                dots += 1;
+                if ppu.scanline == 0 && ppu.cycle == 0 {
+                    return dots;
+                }
+            }
+        };
+
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.mask = PpuMask::SHOW_BG;
+        assert_eq!(frame_dots(&mut ppu), 341 * 262);
+        assert_eq!(frame_dots(&mut ppu), 341 * 262 - 1);
+        assert_eq!(frame_dots(&mut ppu), 341 * 262);
+
+        // No skip with rendering off, or on PAL
+        ppu.mask = PpuMask::empty();
+        assert_eq!(frame_dots(&mut ppu), 341 * 262);
+        ppu.mask = PpuMask::SHOW_BG;
+        ppu.set_region(Region::Pal);
+        assert_eq!(frame_dots(&mut ppu), 341 * 312);
+        assert_eq!(frame_dots(&mut ppu), 341 * 312);
+    }
+
+    #[test]
+    fn test_cycle_257_copies_only_horizontal_bits() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.mask = PpuMask::SHOW_BG;
+        tick_to(&mut ppu, 0, 257);
+
+        // V: fine Y 3, NT bit 11, coarse Y 10, coarse X 2
+        ppu.v = 0x3000 | 0x0800 | (10 << 5) | 2;
+        // T: coarse X 21, NT bit 10, plus vertical bits that must not be copied
+        ppu.t = 0x7000 | 0x0400 | (29 << 5) | 21;
+        ppu.tick();
+
+        assert_eq!(ppu.v, 0x3000 | 0x0800 | 0x0400 | (10 << 5) | 21);
+    }
+
+    /// PPU cycles from the current position to (`scanline`, `cycle`), wrapping into the next frame.
+    fn dots_until(ppu: &Ppu, scanline: u16, cycle: u16) -> u32 {
+        let frame = 262 * 341;
+        let from = ppu.scanline as u32 * 341 + ppu.cycle as u32;
+        let to = scanline as u32 * 341 + cycle as u32;
+        let dots = (to + frame - from) % frame;
+        // Crossing the end of an odd frame loses its skipped dot
+        if from + dots >= frame && ppu.skips_last_dot() { dots - 1 } else { dots }
+    }
+
+    #[test]
+    fn test_tick_batch_matches_tick() {
+        let mut stepped = Ppu::new(vec![0; 8192], Mirroring::Vertical);
+        stepped.ctrl = PpuCtrl::NMI_ENABLE;
+        stepped.mask = PpuMask::SHOW_BG;
+        let mut batched = stepped.clone();
+
+        // 100 vblank scanlines, five per frame across 20 frames, in uneven batch sizes
+        let mut nmis = 0;
+        for frame in 0..20u32 {
+            tick_to(&mut stepped, 241, 2);
+            let result = batched.tick_batch(dots_until(&batched, 241, 2));
+            assert_eq!(result.frames_completed, 1);
+            nmis += result.nmi_fired as u32;
+
+            let n = 5 * 341 + frame;
+            for _ in 0..n {
+                stepped.tick();
+            }
+            assert_eq!(batched.tick_batch(n), PpuBatchResult::default());
+            assert_eq!((batched.scanline, batched.cycle), (stepped.scanline, stepped.cycle));
+            assert_eq!(batched.status, stepped.status);
+            assert_eq!(batched.frame_count, stepped.frame_count);
+            assert_eq!(batched.v, stepped.v);
+        }
+        assert_eq!(nmis, 20);
+    }
+
+    #[test]
+    fn test_a12_rises_once_per_rendering_scanline() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Vertical);
+        ppu.mask = PpuMask::SHOW_BG | PpuMask::SHOW_SPR;
+        ppu.ctrl = PpuCtrl::SPRITE_TABLE;
+        let mut edges = 0;
+        for _ in 0..262 * 341 {
+            edges += ppu.a12_rising_edge() as u32;
+            ppu.tick();
+        }
+        // 240 visible lines plus the pre-render line
+        assert_eq!(edges, 241);
+
+        ppu.mask = PpuMask::empty();
+        assert!((0..262 * 341).all(|_| {
+            let edge = ppu.a12_rising_edge();
+            ppu.tick();
+            !edge
+        }));
+    }
+
+    #[test]
+    fn test_four_screen_mirroring() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::FourScreen);
+        assert_eq!(ppu.extra_vram.len(), 2048);
+        for (nametable, addr) in [0x2000u16, 0x2400, 0x2800, 0x2C00].into_iter().enumerate() {
+            ppu.internal_write(addr + 0x10, nametable as u8 + 1);
+        }
+        for (nametable, addr) in [0x2000u16, 0x2400, 0x2800, 0x2C00].into_iter().enumerate() {
+            assert_eq!(ppu.internal_read(addr + 0x10), nametable as u8 + 1);
+            // $3000-$3EFF mirrors $2000-$2EFF
+            assert_eq!(ppu.internal_read(addr + 0x1010), nametable as u8 + 1);
+        }
+        assert_eq!((ppu.vram[0x010], ppu.vram[0x410]), (1, 2));
+        assert_eq!((ppu.extra_vram[0x010], ppu.extra_vram[0x410]), (3, 4));
+
+        // Other mirrorings don't carry the extra RAM
+        assert!(Ppu::new(vec![0; 8192], Mirroring::Vertical).extra_vram.is_empty());
+    }
+
+    #[test]
+    fn test_single_screen_mirroring() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::SingleScreenA);
+        let nametables = [0x2000, 0x2400, 0x2800, 0x2C00];
+        assert!(nametables.iter().all(|&addr| ppu.mirror_vram_addr(addr + 5) == 5));
+        ppu.mirroring = Mirroring::SingleScreenB;
+        assert!(nametables.iter().all(|&addr| ppu.mirror_vram_addr(addr + 5) == 0x405));
+    }
+
+    #[test]
+    fn test_invariant_check() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        assert!(ppu.invariant_check());
+
+        ppu.fine_x = 8;
+        assert!(!ppu.invariant_check());
+        ppu.fine_x = 7;
+
+        ppu.palette_ram[3] = 0x40;
+        assert!(!ppu.invariant_check());
+        ppu.palette_ram[3] = 0x3F;
+
+        ppu.cycle = 341;
+        assert!(!ppu.invariant_check());
+    }
+
+    #[test]
+    #[should_panic(expected = "fine_x = 9")]
+    fn test_assert_invariants_panics_with_description() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.fine_x = 9;
+        ppu.assert_invariants();
+    }
+
+    #[test]
+    fn test_palette_writes_keep_six_bits() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.cpu_write(0x2006, 0x3F);
+        ppu.cpu_write(0x2006, 0x01);
+        ppu.cpu_write(0x2007, 0xFF);
+        assert_eq!(ppu.palette_ram[1], 0x3F);
+        assert!(ppu.invariant_check());
+    }
+
+    #[test]
+    fn test_ppudata_read_increments_v_outside_rendering() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.mask = PpuMask::SHOW_BG;
+        ppu.scanline = 241;
+        ppu.v = 0x2000;
+        ppu.cpu_read(0x2007);
+        assert_eq!(ppu.v, 0x2001);
+    }
+
+    #[test]
+    fn test_ppudata_read_during_rendering_leaves_v() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.mask = PpuMask::SHOW_BG;
+        for scanline in [0, 120, 239, 261] {
+            ppu.scanline = scanline;
+            ppu.v = 0x2010;
+            ppu.read_buffer = 0x42;
+            assert_eq!(ppu.cpu_read(0x2007), 0x42);
+            assert_eq!(ppu.v, 0x2010);
+        }
+    }
+
+    #[test]
+    fn test_palette_read_fills_buffer_from_nametable_below() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        let set_addr = |ppu: &mut Ppu, addr: u16| {
+            ppu.cpu_write(0x2006, (addr >> 8) as u8);
+            ppu.cpu_write(0x2006, addr as u8);
+        };
+        set_addr(&mut ppu, 0x2F05);
+        ppu.cpu_write(0x2007, 0xAB);
+        ppu.palette_ram[5] = 0x16;
+
+        set_addr(&mut ppu, 0x3F05);
+        assert_eq!(ppu.cpu_read(0x2007), 0x16);
+        assert_eq!(ppu.read_buffer, 0xAB);
+        set_addr(&mut ppu, 0x2F05);
+        assert_eq!(ppu.cpu_read(0x2007), 0xAB);
+
+        // Fine Y bit 14 left in V by rendering doesn't make this a palette read
+        ppu.v = 0x6F05;
+        ppu.read_buffer = 0;
+        assert_eq!(ppu.peek_register(0x2007), 0);
+        assert_eq!(ppu.cpu_read(0x2007), 0);
+        assert_eq!(ppu.read_buffer, 0xAB);
+    }
+
+    #[test]
+    fn test_write_only_registers_read_open_bus() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        assert_eq!(ppu.cpu_read(0x2000), 0);
+
+        ppu.cpu_write(0x2003, 0x5A);
+        for addr in [0x2000, 0x2001, 0x2003, 0x2005, 0x2006] {
+            assert_eq!(ppu.cpu_read(addr), 0x5A);
+            assert_eq!(ppu.peek_register(addr), 0x5A);
+        }
+        // Any register write refreshes the latch
+        ppu.cpu_write(0x2001, 0x00);
+        assert_eq!(ppu.cpu_read(0x2005), 0x00);
+    }
+
+    #[test]
+    fn test_status_low_bits_come_from_bus_latch() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.status.insert(PpuStatus::VBLANK | PpuStatus::SPRITE_ZERO_HIT);
+        ppu.cpu_write(0x2003, 0x3F);
+        assert_eq!(ppu.cpu_read(0x2002), 0xC0 | 0x1F);
+        // The read drives the top bits onto the bus, leaving the low ones
+        assert_eq!(ppu.bus_latch, 0xDF);
+        assert_eq!(ppu.cpu_read(0x2002), 0x40 | 0x1F);
+        assert_eq!(ppu.cpu_read(0x2000), 0x5F);
+    }
+
+    #[test]
+    fn test_peek_register_has_no_side_effects() {
+        let mut ppu = Ppu::new(vec![0; 8192], Mirroring::Horizontal);
+        ppu.status.insert(PpuStatus::VBLANK);
+        ppu.w = true;
+        ppu.v = 0x2000;
+        ppu.read_buffer = 0x42;
+        ppu.bus_latch = 0x42;
+        ppu.palette_ram[1] = 0x16;
+
+        assert_eq!(ppu.peek_register(0x2002), 0x80 | 0x02);
+        assert_eq!(ppu.peek_register(0x2007), 0x42);
+        assert!(ppu.status.contains(PpuStatus::VBLANK));
+        assert!(ppu.w);
+        assert_eq!(ppu.v, 0x2000);
+
+        // Peeks agree with the real reads that follow
+        assert_eq!(ppu.cpu_read(0x2002), 0x82);
+        assert_eq!(ppu.cpu_read(0x2007), 0x42);
+        ppu.v = 0x3F01;
+        assert_eq!(ppu.peek_register(0x2007), 0x16);
+        assert_eq!(ppu.cpu_read(0x2007), 0x16);
+    }
+
+    #[test]
+    fn test_greyscale_masks_palette_output() {
+        // Tile 0 is solid, so the whole screen uses color 1 of palette 0
+        let mut chr = vec![0u8; 8192];
+        chr[..8].fill(0xFF);
+        let mut ppu = Ppu::new(chr, Mirroring::Horizontal);
+        ppu.palette_ram[1] = 0x16;
+        ppu.mask = PpuMask::SHOW_BG | PpuMask::SHOW_BG_LEFT | PpuMask::GREYSCALE;
+
+        // Palette RAM keeps the color; reads and output see its grey column
+        ppu.v = 0x3F01;
+        assert_eq!(ppu.peek_register(0x2007), 0x10);
+        assert_eq!(ppu.palette_ram[1], 0x16);
+
+        ppu.v = 0;
+        tick_to(&mut ppu, 10, 256);
+        assert_eq!(ppu.frame.pixel(0, 10), frame::SYSTEM_PALETTE[0x10]);
+    }
+
+    #[test]
+    fn test_out_of_range_palette_entry_renders_masked() {
+        let mut chr = vec![0u8; 8192];
+        chr[..8].fill(0xFF);
+        let mut ppu = Ppu::new(chr, Mirroring::Horizontal);
+        ppu.palette_ram[1] = 0xD6;
+        ppu.mask = PpuMask::SHOW_BG | PpuMask::SHOW_BG_LEFT;
+        tick_to(&mut ppu, 10, 256);
+        assert_eq!(ppu.frame.pixel(0, 10), frame::SYSTEM_PALETTE[0x16]);
+    }
+}
@@ -0,0 +1,174 @@
+use super::Ppu;
+use super::frame::SYSTEM_PALETTE;
+
+/// Width of the physical VRAM view: both 2KB-VRAM nametables side by side.
+pub const PHYSICAL_VRAM_WIDTH: usize = 512;
+pub const PHYSICAL_VRAM_HEIGHT: usize = 240;
+
+/// Region of the logical 64x60-tile nametable space ($2000-$2FFF) to render, in tiles.
+/// The origin wraps, so a view can straddle the right or bottom edge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogicalNametableView {
+    pub origin_x: u16,
+    pub origin_y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Ppu {
+    /// Render the 2KB of physical VRAM as a 512x240 RGB image, nametable 0 on
+    /// the left and 1 on the right, without applying mirroring.
+    pub fn render_physical_vram(&self) -> Vec<u8> {
+        let mut out = vec![0u8; PHYSICAL_VRAM_WIDTH * PHYSICAL_VRAM_HEIGHT * 3];
+        for nametable in 0..2 {
+            let base = nametable * 0x400;
+            for row in 0..30 {
+                for col in 0..32 {
+                    let tile = self.vram[base + row * 32 + col];
+                    let attr = self.vram[base + 0x3C0 + (row / 4) * 8 + col / 4];
+                    let origin = (nametable * 256 + col * 8, row * 8);
+                    self.draw_nametable_tile(&mut out, PHYSICAL_VRAM_WIDTH, origin, tile, attr, (row, col));
+                }
+            }
+        }
+        out
+    }
+
+    /// Render part of the logical nametable space as the game sees it, with
+    /// mirroring applied. Returns `width * 8` x `height * 8` RGB pixels.
+    pub fn render_logical_nametable(&self, view: LogicalNametableView) -> Vec<u8> {
+        let out_width = view.width as usize * 8;
+        let mut out = vec![0u8; out_width * view.height as usize * 8 * 3];
+        for ty in 0..view.height {
+            for tx in 0..view.width {
+                let abs_x = ((view.origin_x + tx) % 64) as usize;
+                let abs_y = ((view.origin_y + ty) % 60) as usize;
+                let (col, row) = (abs_x % 32, abs_y % 30);
+                let nt_base = 0x2000 + ((abs_y / 30) * 2 + abs_x / 32) as u16 * 0x400;
+
+                let tile = self.internal_read(nt_base + (row * 32 + col) as u16);
+                let attr = self.internal_read(nt_base + 0x3C0 + ((row / 4) * 8 + col / 4) as u16);
+                let origin = (tx as usize * 8, ty as usize * 8);
+                self.draw_nametable_tile(&mut out, out_width, origin, tile, attr, (row, col));
+            }
+        }
+        out
+    }
+
+    /// Draw one background tile at `origin` in an RGB buffer `out_width` pixels wide,
+    /// using the attribute quadrant for tile (`row`, `col`) of its nametable.
+    fn draw_nametable_tile(
+        &self,
+        out: &mut [u8],
+        out_width: usize,
+        origin: (usize, usize),
+        tile: u8,
+        attr: u8,
+        (row, col): (usize, usize),
+    ) {
+        let shift = ((row % 4) / 2 * 2 + (col % 4) / 2) * 2;
+        let palette_index = (attr >> shift) as usize & 0x03;
+        let pattern_base = self.ctrl.bg_pattern_table() + tile as u16 * 16;
+
+        for fine_y in 0..8 {
+            let plane0 = self.internal_read(pattern_base + fine_y);
+            let plane1 = self.internal_read(pattern_base + fine_y + 8);
+            for fine_x in 0..8 {
+                let bit = 7 - fine_x;
+                let pixel = ((plane1 >> bit) & 1) << 1 | ((plane0 >> bit) & 1);
+                let color = if pixel == 0 {
+                    self.palette_ram[0]
+                } else {
+                    self.palette_ram[palette_index * 4 + pixel as usize]
+                };
+                let (r, g, b) = SYSTEM_PALETTE[color as usize % 64];
+                let offset = ((origin.1 + fine_y as usize) * out_width + origin.0 + fine_x) * 3;
+                out[offset..offset + 3].copy_from_slice(&[r, g, b]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Mirroring;
+
+    const BACKDROP: u8 = 0x0F;
+    const SOLID: u8 = 0x30;
+
+    /// Tile 1 is solid color 1; nametable 0 is all tile 1, nametable 1 all tile 0.
+    fn split_vram_ppu(mirroring: Mirroring) -> Ppu {
+        let mut chr = vec![0u8; 8192];
+        chr[16..24].fill(0xFF);
+        let mut ppu = Ppu::new(chr, mirroring);
+        ppu.vram[..960].fill(1);
+        ppu.palette_ram[0] = BACKDROP;
+        ppu.palette_ram[1] = SOLID;
+        ppu
+    }
+
+    fn pixel(buf: &[u8], width: usize, x: usize, y: usize) -> (u8, u8, u8) {
+        let i = (y * width + x) * 3;
+        (buf[i], buf[i + 1], buf[i + 2])
+    }
+
+    #[test]
+    fn test_physical_vram_shows_both_nametables() {
+        let mut ppu = split_vram_ppu(Mirroring::Vertical);
+        ppu.vram[0x400 + 5] = 1; // one solid tile in nametable 1, column 5
+        let image = ppu.render_physical_vram();
+        assert_eq!(image.len(), 512 * 240 * 3);
+
+        let solid = SYSTEM_PALETTE[SOLID as usize];
+        let backdrop = SYSTEM_PALETTE[BACKDROP as usize];
+        assert_eq!(pixel(&image, 512, 0, 0), solid);
+        assert_eq!(pixel(&image, 512, 255, 239), solid);
+        assert_eq!(pixel(&image, 512, 256, 0), backdrop);
+        assert_eq!(pixel(&image, 512, 256 + 5 * 8, 7), solid);
+        assert_eq!(pixel(&image, 512, 256 + 6 * 8, 0), backdrop);
+    }
+
+    #[test]
+    fn test_logical_nametable_applies_mirroring() {
+        let solid = SYSTEM_PALETTE[SOLID as usize];
+        let backdrop = SYSTEM_PALETTE[BACKDROP as usize];
+        // One tile from each of the four logical nametables
+        let corners = [(0, 0), (32, 0), (0, 30), (32, 30)];
+        let view = |(x, y)| LogicalNametableView { origin_x: x, origin_y: y, width: 1, height: 1 };
+
+        let vertical = split_vram_ppu(Mirroring::Vertical);
+        let colors: Vec<_> = corners
+            .iter()
+            .map(|&c| pixel(&vertical.render_logical_nametable(view(c)), 8, 0, 0))
+            .collect();
+        assert_eq!(colors, [solid, backdrop, solid, backdrop]);
+
+        let horizontal = split_vram_ppu(Mirroring::Horizontal);
+        let colors: Vec<_> = corners
+            .iter()
+            .map(|&c| pixel(&horizontal.render_logical_nametable(view(c)), 8, 0, 0))
+            .collect();
+        assert_eq!(colors, [solid, solid, backdrop, backdrop]);
+    }
+
+    #[test]
+    fn test_logical_view_wraps_and_uses_attributes() {
+        let mut ppu = split_vram_ppu(Mirroring::Vertical);
+        ppu.palette_ram[5] = 0x16; // palette 1, color 1
+        ppu.vram[0x3C0 + 7] = 0b01 << 2; // top-right quadrant of attribute byte 7 -> palette 1
+
+        // Two tiles straddling the right edge: logical column 63, then column 0
+        let view = LogicalNametableView { origin_x: 63, origin_y: 0, width: 2, height: 1 };
+        let image = ppu.render_logical_nametable(view);
+        assert_eq!(image.len(), 16 * 8 * 3);
+        // Column 63 is nametable 1 (mirrors physical 1: tile 0), column 0 is tile 1 with palette 0
+        assert_eq!(pixel(&image, 16, 0, 0), SYSTEM_PALETTE[BACKDROP as usize]);
+        assert_eq!(pixel(&image, 16, 8, 0), SYSTEM_PALETTE[SOLID as usize]);
+
+        // Column 30 of nametable 0 uses the top-right quadrant of attribute byte 7
+        let view = LogicalNametableView { origin_x: 30, origin_y: 0, width: 1, height: 1 };
+        let image = ppu.render_logical_nametable(view);
+        assert_eq!(pixel(&image, 8, 0, 0), SYSTEM_PALETTE[0x16]);
+    }
+}
@@ -2,6 +2,7 @@ use bitflags::bitflags;
 
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
     pub struct PpuCtrl: u8 {
         const NAMETABLE_LO   = 0b0000_0001;
         const NAMETABLE_HI   = 0b0000_0010;
@@ -16,6 +17,7 @@ bitflags! {
 
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
     pub struct PpuMask: u8 {
         const GREYSCALE       = 0b0000_0001;
         const SHOW_BG_LEFT    = 0b0000_0010;
@@ -30,6 +32,7 @@ bitflags! {
 
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "save_states", derive(serde::Serialize, serde::Deserialize))]
     pub struct PpuStatus: u8 {
         const SPRITE_OVERFLOW  = 0b0010_0000;
         const SPRITE_ZERO_HIT  = 0b0100_0000;
@@ -0,0 +1,154 @@
+use super::Ppu;
+use super::frame::Frame;
+use super::registers::{PpuCtrl, PpuMask};
+
+/// Color index of the solid checkerboard squares (white); the rest show the backdrop (black).
+const SOLID_COLOR: u8 = 0x30;
+const BACKDROP_COLOR: u8 = 0x0F;
+
+impl Ppu {
+    /// Render `frames` frames of a synthetic 8x8 checkerboard nametable scrolled to
+    /// (`scroll_x`, `scroll_y`) and return each completed frame. Runs on a clone, so
+    /// the live PPU state is untouched.
+    ///
+    /// Tile (col, row) of every nametable is solid white when `col + row` is odd and
+    /// backdrop black otherwise, so screen pixel (x, y) is white exactly when
+    /// `(x + scroll_x) / 8 + (y + scroll_y) / 8` is odd.
+    pub fn simulate_scroll_test(&self, scroll_x: u8, scroll_y: u8, frames: u32) -> Vec<Frame> {
+        let mut ppu = self.clone();
+
+        // Tile 0 is transparent, tile 1 is solid color index 1. The clone's
+        // pattern tables are scratch, even on a CHR ROM cartridge.
+        ppu.chr_is_ram = true;
+        for row in 0..8 {
+            ppu.internal_write(row, 0x00);
+            ppu.internal_write(row + 8, 0x00);
+            ppu.internal_write(16 + row, 0xFF);
+            ppu.internal_write(16 + row + 8, 0x00);
+        }
+        for nametable in 0..2 {
+            let base = nametable * 0x400;
+            for i in 0..960 {
+                let (row, col) = (i / 32, i % 32);
+                ppu.vram[base + i] = ((row + col) % 2) as u8;
+            }
+            ppu.vram[base + 960..base + 1024].fill(0);
+        }
+        ppu.palette_ram = [BACKDROP_COLOR; 32];
+        ppu.palette_ram[1] = SOLID_COLOR;
+
+        ppu.ctrl = PpuCtrl::empty();
+        ppu.mask = PpuMask::SHOW_BG | PpuMask::SHOW_BG_LEFT;
+        ppu.nmi_pending = false;
+        ppu.w = false;
+        ppu.t = 0;
+        ppu.cpu_write(0x2005, scroll_x);
+        ppu.cpu_write(0x2005, scroll_y);
+
+        // Start on the pre-render line so V is loaded from T before scanline 0
+        ppu.scanline = 261;
+        ppu.cycle = 0;
+
+        let mut result = Vec::with_capacity(frames as usize);
+        while result.len() < frames as usize {
+            if ppu.tick() {
+                result.push(ppu.frame.clone());
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Mirroring;
+    use crate::ppu::frame::SYSTEM_PALETTE;
+
+    fn expected_pixel(x: usize, y: usize, scroll_x: u8, scroll_y: u8) -> (u8, u8, u8) {
+        let odd = ((x + scroll_x as usize) / 8 + (y + scroll_y as usize) / 8) % 2 == 1;
+        SYSTEM_PALETTE[if odd { SOLID_COLOR } else { BACKDROP_COLOR } as usize]
+    }
+
+    fn assert_scrolled(scroll_x: u8, scroll_y: u8) {
+        let ppu = Ppu::new(vec![0; 8192], Mirroring::Vertical);
+        let frames = ppu.simulate_scroll_test(scroll_x, scroll_y, 1);
+        for y in 0..240 {
+            for x in 0..256 {
+                assert_eq!(
+                    frames[0].front_pixel(x, y),
+                    expected_pixel(x, y, scroll_x, scroll_y),
+                    "pixel ({}, {}) with scroll ({}, {})",
+                    x, y, scroll_x, scroll_y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_scroll_origin_shows_tile_0_0_top_left() {
+        let ppu = Ppu::new(vec![0; 8192], Mirroring::Vertical);
+        let frames = ppu.simulate_scroll_test(0, 0, 1);
+        assert_eq!(frames[0].front_pixel(0, 0), SYSTEM_PALETTE[BACKDROP_COLOR as usize]);
+        assert_eq!(frames[0].front_pixel(8, 0), SYSTEM_PALETTE[SOLID_COLOR as usize]);
+        assert_scrolled(0, 0);
+    }
+
+    #[test]
+    fn test_scroll_fine_x() {
+        for fine_x in 0..8 {
+            assert_scrolled(fine_x, 0);
+        }
+    }
+
+    #[test]
+    fn test_scroll_one_tile_right() {
+        let ppu = Ppu::new(vec![0; 8192], Mirroring::Vertical);
+        let frames = ppu.simulate_scroll_test(8, 0, 1);
+        assert_eq!(frames[0].front_pixel(0, 0), SYSTEM_PALETTE[SOLID_COLOR as usize]);
+        assert_scrolled(8, 0);
+    }
+
+    #[test]
+    fn test_scroll_into_second_nametable() {
+        assert_scrolled(255, 0);
+        assert_scrolled(129, 0);
+    }
+
+    #[test]
+    fn test_scroll_coarse_y() {
+        for coarse_y in 0..30u8 {
+            assert_scrolled(0, coarse_y * 8);
+        }
+    }
+
+    #[test]
+    fn test_scroll_fine_y() {
+        for fine_y in 1..8 {
+            assert_scrolled(0, fine_y);
+        }
+    }
+
+    #[test]
+    fn test_scroll_both_axes() {
+        assert_scrolled(13, 77);
+        assert_scrolled(250, 239);
+    }
+
+    #[test]
+    fn test_scroll_returns_requested_frame_count() {
+        let ppu = Ppu::new(vec![0; 8192], Mirroring::Vertical);
+        let frames = ppu.simulate_scroll_test(3, 5, 3);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].front_slice(), frames[2].front_slice());
+    }
+
+    #[test]
+    fn test_scroll_leaves_live_state_untouched() {
+        let ppu = Ppu::new(vec![0; 8192], Mirroring::Vertical);
+        ppu.simulate_scroll_test(8, 8, 1);
+        assert!(ppu.vram.iter().all(|&b| b == 0));
+        assert_eq!(ppu.t, 0);
+        assert_eq!(ppu.scanline, 0);
+    }
+}
@@ -0,0 +1,165 @@
+//! Numbered save state slots, kept in memory and mirrored to
+//! `{rom_crc32}_{slot}.state` files so they survive a restart.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::nes::{Nes, StateError};
+
+pub const SLOT_COUNT: usize = 10;
+
+#[derive(Debug)]
+pub enum SlotError {
+    /// There's no slot with this number.
+    OutOfRange(usize),
+    Empty(usize),
+    Io(io::Error),
+    State(StateError),
+}
+
+impl fmt::Display for SlotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlotError::OutOfRange(slot) => write!(f, "No slot {}; slots are 0-{}", slot, SLOT_COUNT - 1),
+            SlotError::Empty(slot) => write!(f, "Slot {} is empty", slot),
+            SlotError::Io(e) => write!(f, "{}", e),
+            SlotError::State(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SlotError {}
+
+pub struct SaveStateManager {
+    slots: [Option<Vec<u8>>; SLOT_COUNT],
+    /// Slot the frontend's save and load keys use.
+    pub current_slot: usize,
+    /// Directory and ROM checksum the slot files are named after; `None`
+    /// keeps states in memory only.
+    storage: Option<(PathBuf, u32)>,
+}
+
+impl Default for SaveStateManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveStateManager {
+    /// Slots that live in memory only.
+    pub fn new() -> Self {
+        SaveStateManager { slots: Default::default(), current_slot: 0, storage: None }
+    }
+
+    /// Slots persisted in `dir` for the ROM with checksum `rom_crc32`. States
+    /// already saved there are read in now; unreadable ones are skipped with
+    /// a warning.
+    pub fn with_dir(dir: PathBuf, rom_crc32: u32) -> Self {
+        let mut manager = SaveStateManager { storage: Some((dir, rom_crc32)), ..Self::new() };
+        for slot in 0..SLOT_COUNT {
+            let Some(path) = manager.path(slot) else { continue };
+            match fs::read(&path) {
+                Ok(data) => manager.slots[slot] = Some(data),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => log::warn!("Failed to read save state '{}': {}", path.display(), e),
+            }
+        }
+        manager
+    }
+
+    /// The file `slot` is persisted to, if the slots have a directory.
+    pub fn path(&self, slot: usize) -> Option<PathBuf> {
+        let (dir, rom_crc32) = self.storage.as_ref()?;
+        Some(dir.join(format!("{:08x}_{}.state", rom_crc32, slot)))
+    }
+
+    pub fn is_filled(&self, slot: usize) -> bool {
+        self.slots.get(slot).is_some_and(Option::is_some)
+    }
+
+    fn check_slot(slot: usize) -> Result<(), SlotError> {
+        if slot < SLOT_COUNT { Ok(()) } else { Err(SlotError::OutOfRange(slot)) }
+    }
+
+    /// Snapshot `nes` into `slot` and write it out. The in-memory copy is
+    /// kept even when the write fails.
+    pub fn save(&mut self, nes: &Nes, slot: usize) -> Result<(), SlotError> {
+        Self::check_slot(slot)?;
+        let path = self.path(slot);
+        let data = self.slots[slot].insert(nes.save_state());
+        if let Some(path) = path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(SlotError::Io)?;
+            }
+            fs::write(&path, data).map_err(SlotError::Io)?;
+        }
+        Ok(())
+    }
+
+    /// Restore `nes` from `slot` (see `Nes::load_state` for what's kept).
+    pub fn load(&self, nes: &mut Nes, slot: usize) -> Result<(), SlotError> {
+        Self::check_slot(slot)?;
+        let data = self.slots[slot].as_ref().ok_or(SlotError::Empty(slot))?;
+        nes.load_state(data).map_err(SlotError::State)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+
+    fn nes() -> Nes {
+        Nes::new_no_audio(Cartridge::nrom(vec![0xEA; 16384], vec![0; 8192]))
+    }
+
+    #[test]
+    fn test_slots_persist_across_managers() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut nes = nes();
+        nes.bus.ram[0x10] = 0x42;
+
+        let mut manager = SaveStateManager::with_dir(dir.path().join("states"), 0xDEADBEEF);
+        manager.save(&nes, 3).unwrap();
+        assert!(dir.path().join("states/deadbeef_3.state").is_file());
+
+        let manager = SaveStateManager::with_dir(dir.path().join("states"), 0xDEADBEEF);
+        assert!(manager.is_filled(3));
+        assert!(!manager.is_filled(4));
+        nes.bus.ram[0x10] = 0;
+        manager.load(&mut nes, 3).unwrap();
+        assert_eq!(nes.bus.ram[0x10], 0x42);
+        assert!(matches!(manager.load(&mut nes, 4), Err(SlotError::Empty(4))));
+
+        // Another ROM's states aren't picked up
+        assert!(!SaveStateManager::with_dir(dir.path().join("states"), 1).is_filled(3));
+    }
+
+    #[test]
+    fn test_failed_write_keeps_the_slot() {
+        let dir = tempfile::tempdir().unwrap();
+        // A file where the states directory should be, so it can't be created
+        let blocker = dir.path().join("states");
+        fs::write(&blocker, b"").unwrap();
+        let mut nes = nes();
+        nes.bus.ram[0x10] = 0x42;
+
+        let mut manager = SaveStateManager::with_dir(blocker, 0xDEADBEEF);
+        assert!(matches!(manager.save(&nes, 2), Err(SlotError::Io(_))));
+        assert!(manager.is_filled(2));
+        nes.bus.ram[0x10] = 0;
+        manager.load(&mut nes, 2).unwrap();
+        assert_eq!(nes.bus.ram[0x10], 0x42);
+    }
+
+    #[test]
+    fn test_out_of_range_slots_are_errors() {
+        let mut nes = nes();
+        let mut manager = SaveStateManager::new();
+        assert!(matches!(manager.save(&nes, SLOT_COUNT), Err(SlotError::OutOfRange(SLOT_COUNT))));
+        assert!(matches!(manager.load(&mut nes, 42), Err(SlotError::OutOfRange(42))));
+        assert!(!manager.is_filled(SLOT_COUNT));
+    }
+}
@@ -0,0 +1,78 @@
+//! Small shared containers.
+
+/// Fixed-capacity ring buffer. Every slot is allocated up front, and pushing
+/// into a full buffer overwrites the oldest element.
+#[derive(Debug, Clone)]
+pub struct RingBuffer<T> {
+    slots: Vec<Option<T>>,
+    /// Slot the next push writes to.
+    head: usize,
+    len: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            slots: (0..capacity).map(|_| None).collect(),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        if self.slots.is_empty() {
+            return;
+        }
+        self.slots[self.head] = Some(item);
+        self.head = (self.head + 1) % self.slots.len();
+        self.len = (self.len + 1).min(self.slots.len());
+    }
+
+    /// Remove and return the most recently pushed element.
+    pub fn pop_latest(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.head = (self.head + self.slots.len() - 1) % self.slots.len();
+        self.len -= 1;
+        self.slots[self.head].take()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer_overwrites_oldest() {
+        let mut ring = RingBuffer::new(3);
+        for i in 1..=5 {
+            ring.push(i);
+        }
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.capacity(), 3);
+        assert_eq!(ring.pop_latest(), Some(5));
+        ring.push(6);
+        assert_eq!(ring.pop_latest(), Some(6));
+        assert_eq!(ring.pop_latest(), Some(4));
+        assert_eq!(ring.pop_latest(), Some(3));
+        assert_eq!(ring.pop_latest(), None);
+        assert!(ring.is_empty());
+
+        let mut empty = RingBuffer::new(0);
+        empty.push(1);
+        assert_eq!(empty.pop_latest(), None);
+    }
+}
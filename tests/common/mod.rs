@@ -0,0 +1,13 @@
+//! Fixtures shared by the integration tests.
+
+use vines::cartridge::Cartridge;
+use vines::nes::Nes;
+
+/// A console with an NROM-128 cartridge holding `program` at $C000, padded
+/// with NOPs, and the reset vector pointing at it.
+pub fn nes_with_program(program: &[u8]) -> Nes {
+    let mut prg_rom = vec![0xEA; 16384];
+    prg_rom[..program.len()].copy_from_slice(program);
+    prg_rom[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+    Nes::new_no_audio(Cartridge::nrom(prg_rom, vec![0; 8192]))
+}
@@ -0,0 +1,137 @@
+//! Cycle counts per addressing mode, in the spirit of blargg's
+//! cpu_timing_test: one instruction at a time from a RAM image.
+
+use vines::apu::SampleSink;
+use vines::bus::Bus;
+use vines::cartridge::Cartridge;
+use vines::cpu::{Cpu, CpuFlags};
+
+const ORIGIN: u16 = 0x0300;
+
+fn bus() -> Bus {
+    let mut bus = Bus::with_audio_output(Cartridge::nrom(vec![0; 16384], vec![0; 8192]), SampleSink::Discard);
+    // ($20) points at $0400, ($22) at $04FF
+    bus.ram[0x20..0x24].copy_from_slice(&[0x00, 0x04, 0xFF, 0x04]);
+    bus
+}
+
+/// Cycles taken by the single instruction `program`, run from `origin` with
+/// X = Y = 1 and the flags `prepare` sets.
+fn cycles_at(origin: u16, program: &[u8], prepare: impl FnOnce(&mut Cpu)) -> (u8, Cpu, Bus) {
+    let mut bus = bus();
+    bus.ram[origin as usize..origin as usize + program.len()].copy_from_slice(program);
+    let mut cpu = Cpu::new();
+    (cpu.pc, cpu.x, cpu.y) = (origin, 1, 1);
+    prepare(&mut cpu);
+    let cycles = cpu.step(&mut bus).cycles();
+    assert_eq!(cycles, cpu.last_instruction_cycles);
+    (cycles, cpu, bus)
+}
+
+fn cycles(program: &[u8]) -> u8 {
+    cycles_at(ORIGIN, program, |_| {}).0
+}
+
+fn assert_timings(cases: &[(&str, &[u8], u8)]) {
+    for &(name, program, expected) in cases {
+        assert_eq!(cycles(program), expected, "{}", name);
+    }
+}
+
+#[test]
+fn test_implied_and_stack() {
+    assert_timings(&[
+        ("NOP", &[0xEA], 2),
+        ("INX", &[0xE8], 2),
+        ("ASL A", &[0x0A], 2),
+        ("PHA", &[0x48], 3),
+        ("PLA", &[0x68], 4),
+        ("RTS", &[0x60], 6),
+    ]);
+}
+
+#[test]
+fn test_immediate() {
+    assert_timings(&[
+        ("LDA #", &[0xA9, 0x10], 2),
+        ("ADC #", &[0x69, 0x10], 2),
+        ("CPX #", &[0xE0, 0x10], 2),
+    ]);
+}
+
+#[test]
+fn test_zero_page() {
+    assert_timings(&[
+        ("LDA zp", &[0xA5, 0x10], 3),
+        ("STA zp", &[0x85, 0x10], 3),
+        ("INC zp", &[0xE6, 0x10], 5),
+        ("LDA zp,X", &[0xB5, 0x10], 4),
+        ("LDX zp,Y", &[0xB6, 0x10], 4),
+        ("INC zp,X", &[0xF6, 0x10], 6),
+    ]);
+
+    // zp,X wraps within the zero page rather than crossing into $0100
+    let (_, _, bus) = cycles_at(ORIGIN, &[0xF6, 0xFF], |_| {});
+    assert_eq!((bus.peek(0x0000), bus.peek(0x0100)), (1, 0));
+}
+
+#[test]
+fn test_absolute() {
+    assert_timings(&[
+        ("LDA abs", &[0xAD, 0x00, 0x04], 4),
+        ("STA abs", &[0x8D, 0x00, 0x04], 4),
+        ("ROL abs", &[0x2E, 0x00, 0x04], 6),
+        ("JMP abs", &[0x4C, 0x00, 0x04], 3),
+        ("JMP (ind)", &[0x6C, 0x20, 0x00], 5),
+        ("JSR", &[0x20, 0x00, 0x04], 6),
+    ]);
+}
+
+#[test]
+fn test_absolute_indexed() {
+    assert_timings(&[
+        ("LDA abs,X", &[0xBD, 0x00, 0x04], 4),
+        ("LDA abs,X crossing", &[0xBD, 0xFF, 0x04], 5),
+        ("LDA abs,Y", &[0xB9, 0x00, 0x04], 4),
+        ("LDA abs,Y crossing", &[0xB9, 0xFF, 0x04], 5),
+        // Stores and read-modify-writes always take the extra cycle
+        ("STA abs,X", &[0x9D, 0x00, 0x04], 5),
+        ("STA abs,X crossing", &[0x9D, 0xFF, 0x04], 5),
+        ("INC abs,X", &[0xFE, 0x00, 0x04], 7),
+        ("INC abs,X crossing", &[0xFE, 0xFF, 0x04], 7),
+    ]);
+
+    let (_, _, bus) = cycles_at(ORIGIN, &[0xFE, 0xFF, 0x04], |_| {});
+    assert_eq!(bus.peek(0x0500), 1);
+}
+
+#[test]
+fn test_indirect() {
+    assert_timings(&[
+        ("LDA (zp,X)", &[0xA1, 0x1F], 6),
+        ("STA (zp,X)", &[0x81, 0x1F], 6),
+        ("LDA (zp),Y", &[0xB1, 0x20], 5),
+        ("LDA (zp),Y crossing", &[0xB1, 0x22], 6),
+        ("STA (zp),Y", &[0x91, 0x20], 6),
+        ("STA (zp),Y crossing", &[0x91, 0x22], 6),
+    ]);
+}
+
+#[test]
+fn test_branches() {
+    let clear_zero = |cpu: &mut Cpu| cpu.status.remove(CpuFlags::ZERO);
+    let set_zero = |cpu: &mut Cpu| cpu.status.insert(CpuFlags::ZERO);
+
+    let (not_taken, cpu, _) = cycles_at(ORIGIN, &[0xD0, 0x10], set_zero);
+    assert_eq!((not_taken, cpu.pc), (2, ORIGIN + 2));
+
+    let (same_page, cpu, _) = cycles_at(ORIGIN, &[0xD0, 0x10], clear_zero);
+    assert_eq!((same_page, cpu.pc), (3, ORIGIN + 0x12));
+
+    // From $02FD the branch's next instruction is at $02FF, so +$10 lands on page 3
+    let (forward, cpu, _) = cycles_at(0x02FD, &[0xD0, 0x10], clear_zero);
+    assert_eq!((forward, cpu.pc), (4, 0x030F));
+
+    let (backward, cpu, _) = cycles_at(ORIGIN, &[0xD0, 0xF0], clear_zero);
+    assert_eq!((backward, cpu.pc), (4, 0x02F2));
+}
@@ -0,0 +1,47 @@
+//! Recording an input movie and playing it back.
+
+mod common;
+
+use common::nes_with_program;
+use vines::controller::{BUTTON_A, BUTTON_B};
+use vines::movie::{Movie, MovieMode};
+use vines::nes::FrameResult;
+
+#[test]
+fn test_movie_playback_reproduces_recording() {
+    // loop: strobe the pad, read A, and INC $0200 if it's held
+    let program = [
+        0xA9, 0x01, 0x8D, 0x16, 0x40, // LDA #1; STA $4016
+        0xA9, 0x00, 0x8D, 0x16, 0x40, // LDA #0; STA $4016
+        0xAD, 0x16, 0x40, 0x29, 0x01, // LDA $4016; AND #1
+        0xF0, 0x03, 0xEE, 0x00, 0x02, // BEQ +3; INC $0200
+        0x4C, 0x00, 0xC0, //             JMP $C000
+    ];
+    let inputs = [0, BUTTON_A, BUTTON_A | BUTTON_B, 0, BUTTON_A];
+
+    let mut recorder = nes_with_program(&program);
+    recorder.movie = MovieMode::Recording(Movie::new());
+    for buttons in inputs {
+        recorder.bus.controller1.buttons = buttons;
+        assert_eq!(recorder.step_frame(), FrameResult::Completed);
+    }
+    let MovieMode::Recording(movie) = std::mem::take(&mut recorder.movie) else { panic!("not recording") };
+    assert_eq!(movie.len(), inputs.len());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("recording.vmv");
+    movie.save(&path).unwrap();
+    let movie = Movie::load(&path).unwrap();
+
+    let mut player = nes_with_program(&program);
+    player.movie = MovieMode::Playback { movie, frame: 0 };
+    for _ in inputs {
+        assert!(player.movie.is_playing());
+        player.step_frame();
+    }
+    assert_eq!(player.movie, MovieMode::Off);
+    assert_eq!(player.cpu.pc, recorder.cpu.pc);
+    assert_eq!(player.cpu.cycles, recorder.cpu.cycles);
+    assert_eq!(player.bus.ram, recorder.bus.ram);
+    assert_ne!(player.bus.ram[0x200], 0);
+}
@@ -0,0 +1,35 @@
+//! `Nes::step_to_pc` and `Nes::step_n_instructions` driving small programs.
+
+mod common;
+
+use common::nes_with_program;
+
+#[test]
+fn test_step_to_pc() {
+    // LDA #$01; LDX #$02; INY; JMP $C004
+    let mut nes = nes_with_program(&[0xA9, 0x01, 0xA2, 0x02, 0xC8, 0x4C, 0x04, 0xC0]);
+    nes.cpu.debugger.add_breakpoint(0xC002);
+
+    assert!(nes.step_to_pc(0xC004, 10));
+    assert_eq!((nes.cpu.a, nes.cpu.x, nes.cpu.y), (0x01, 0x02, 0x00));
+    assert!(nes.step_to_pc(0xC004, 10), "already there");
+    assert!(!nes.step_to_pc(0xD000, 1000));
+}
+
+#[test]
+fn test_step_n_instructions_skips_dma_stall() {
+    // LDA #$02; STA $4014; INX; JMP $C005
+    let mut nes = nes_with_program(&[0xA9, 0x02, 0x8D, 0x14, 0x40, 0xE8, 0x4C, 0x05, 0xC0]);
+
+    nes.step_n_instructions(2);
+    assert_eq!(nes.cpu.pc, 0xC005);
+    assert!(nes.cpu.stall > 0);
+    nes.step_n_instructions(1);
+    assert_eq!((nes.cpu.x, nes.cpu.pc, nes.cpu.stall), (1, 0xC006, 0));
+
+    // Straight through several frames, in JMP/INX pairs
+    let frames = nes.bus.ppu.frame_count;
+    nes.step_n_instructions(200_000);
+    assert!(nes.bus.ppu.frame_count > frames + 2);
+    assert_eq!(nes.cpu.x, (1 + 100_000u32 % 256) as u8);
+}